@@ -1,5 +1,5 @@
 use crate::function_layer::V3f;
-use cgmath::{ElementWise, Zero};
+use cgmath::{ElementWise, InnerSpace, Zero};
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -11,6 +11,9 @@ impl SpectrumRGB {
     pub fn rgb(&self) -> V3f {
         self.rgb
     }
+    pub fn luminance(&self) -> f32 {
+        self.rgb.dot(V3f::new(0.212671, 0.715160, 0.072169))
+    }
     pub fn exp(&self) -> SpectrumRGB {
         SpectrumRGB {
             rgb: V3f::new(self.rgb.x.exp(), self.rgb.y.exp(), self.rgb.z.exp()),
@@ -111,3 +114,49 @@ impl SpectrumRGB {
         [self.rgb.x, self.rgb.y, self.rgb.z]
     }
 }
+
+// CIE 1931配色函数的多瓣高斯拟合(Wyman et al. 2013)
+fn cie_xyz(lambda: f64) -> [f64; 3] {
+    let g = |mu: f64, s1: f64, s2: f64| {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        (-0.5 * t * t).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+impl SpectrumRGB {
+    /// 温度为temperature(开尔文)的黑体辐射在线性sRGB下的颜色。
+    /// 与pbrt相同，光谱按峰值波长处的辐射归一化，因此各温度下的峰值均为1
+    pub fn blackbody(temperature: f32) -> Self {
+        if temperature <= 0.0 {
+            return Self::same(0.0);
+        }
+        let t = temperature as f64;
+        // 2hc^2与hc/k，波长以米为单位
+        let planck = |lambda: f64| {
+            1.191042972e-16 / (lambda.powi(5) * ((1.438776877e-2 / (lambda * t)).exp() - 1.0))
+        };
+        let peak = planck(2.897771955e-3 / t);
+        let (mut xyz, mut y_integral) = ([0.0; 3], 0.0);
+        for i in 0..=94 {
+            let lambda = 360.0 + 5.0 * i as f64;
+            let cmf = cie_xyz(lambda);
+            let le = planck(lambda * 1e-9) / peak;
+            for c in 0..3 {
+                xyz[c] += le * cmf[c];
+            }
+            y_integral += cmf[1];
+        }
+        let [x, y, z] = xyz.map(|c| c / y_integral);
+        let rgb = V3f::new(
+            (3.2404542 * x - 1.5371385 * y - 0.4985314 * z) as f32,
+            (-0.9692660 * x + 1.8760108 * y + 0.0415560 * z) as f32,
+            (0.0556434 * x - 0.2040259 * y + 1.0572252 * z) as f32,
+        );
+        Self::from_rgb(rgb.map(|c| c.max(0.0)))
+    }
+}
@@ -34,7 +34,7 @@ impl<T: Clone + PartialEq> Distribution<T> {
                     Ordering::Equal => Ordering::Greater,
                     ord => ord,
                 });
-        let idx = idx.err().unwrap() - 1;
+        let idx = idx.err().unwrap().max(1) - 1;
         *pdf = self.cdf[idx + 1] - self.cdf[idx];
         Some(self.data[idx.min(self.cdf.len() - 2)].clone())
     }
@@ -22,11 +22,94 @@ pub fn solve_quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
     }
 }
 
+/// 与solve_quadratic相同，但a为0时退化为一次方程求解，返回从小到大排列的实根
+pub fn solve_quadratic_or_linear(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+    match solve_quadratic(a, b, c) {
+        Some((t0, t1)) => vec![t0, t1],
+        None => vec![],
+    }
+}
+
+/// 求解四次方程c[4]x^4 + c[3]x^3 + c[2]x^2 + c[1]x + c[0] = 0的实根，按从小到大排列。
+/// 使用Ferrari方法，再用牛顿迭代修正精度
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    if c[4] == 0.0 {
+        return vec![];
+    }
+    let (a, b, cc, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+    // 代换x = y - a / 4消去三次项
+    let p = b - 3.0 * a * a / 8.0;
+    let q = cc - a * b / 2.0 + a * a * a / 8.0;
+    let r = d - a * cc / 4.0 + a * a * b / 16.0 - 3.0 * a * a * a * a / 256.0;
+    let mut ys = vec![];
+    let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+    if m > 1e-12 {
+        let s = (2.0 * m).sqrt();
+        ys.extend(real_quadratic_roots(s, p / 2.0 + m - q / (2.0 * s)));
+        ys.extend(real_quadratic_roots(-s, p / 2.0 + m + q / (2.0 * s)));
+    } else {
+        // q约为0，方程退化为关于y^2的二次方程
+        for z in real_quadratic_roots(p, r) {
+            if z >= 0.0 {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            }
+        }
+    }
+    let f = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let df = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    let mut roots: Vec<f64> = ys
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let dx = df(x);
+                if dx != 0.0 {
+                    x -= f(x) / dx;
+                }
+            }
+            x
+        })
+        .collect();
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+// x^2 + bx + c = 0的实根
+fn real_quadratic_roots(b: f64, c: f64) -> Vec<f64> {
+    let discr = b * b - 4.0 * c;
+    if discr < 0.0 {
+        return vec![];
+    }
+    let q = -0.5 * (b + b.signum() * discr.sqrt());
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    vec![q, c / q]
+}
+
+// x^3 + ax^2 + bx + c = 0的最大实根
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        -2.0 * q.sqrt() * ((theta + 2.0 * std::f64::consts::PI) / 3.0).cos() - a / 3.0
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let big_b = if big_a != 0.0 { q / big_a } else { 0.0 };
+        big_a + big_b - a / 3.0
+    }
+}
+
 pub fn lerp<T>(alpha: f32, p1: T, p2: T) -> T
 where
     T: Mul<f32, Output = T> + Add<T, Output = T>,
 {
-    p1 * alpha + p2 * (1.0 - alpha)
+    p1 * (1.0 - alpha) + p2 * alpha
 }
 
 pub fn coordinate_system(v1: V3f, v2: &mut V3f, v3: &mut V3f) {
@@ -1,5 +1,6 @@
-use crate::function_layer::{Bounds3, Ray, V3f};
-use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector4};
+use crate::function_layer::{fetch_v3f, Bounds3, Ray, V3f};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector4, Zero};
+use serde_json::Value;
 
 type M4f = Matrix4<f32>;
 
@@ -10,8 +11,10 @@ pub struct Transform {
     pub rotate: M4f,
     pub inv_rotate: M4f,
     pub scale: M4f,
-    pub inv_scale: M4f,
     pub t: M4f,
+    pub inv_t: M4f,
+    // 变换法线的逆转置矩阵
+    pub normal: M4f,
 }
 
 impl Default for Transform {
@@ -28,8 +31,9 @@ impl Transform {
             rotate: M4f::identity(),
             inv_rotate: M4f::identity(),
             scale: M4f::identity(),
-            inv_scale: M4f::identity(),
             t: M4f::identity(),
+            inv_t: M4f::identity(),
+            normal: M4f::identity(),
         }
     }
 
@@ -49,11 +53,51 @@ impl Transform {
             rotate,
             inv_rotate,
             scale,
-            inv_scale,
             t,
+            inv_t: inv_scale * inv_rotate * inv_translate,
+            normal: rotate * inv_scale,
         }
     }
 
+    /// 由任意仿射矩阵构造，可以包含切变。t、inv_t和normal是精确的，
+    /// 分解出的平移、旋转和缩放只是近似，只有通过t变换的形状(如三角形网格)能正确处理切变
+    pub fn from_matrix(t: M4f) -> Self {
+        let inv_t = t.invert().expect("Transform matrix is singular!");
+        let mut translate = M4f::identity();
+        let mut rotate = M4f::identity();
+        let mut scale = M4f::identity();
+        for i in 0..3 {
+            translate[3][i] = t[3][i];
+            let axis = t[i].truncate();
+            scale[i][i] = axis.magnitude();
+            rotate[i] = (axis / scale[i][i]).extend(0.0);
+        }
+        let mut transform = Self::new(translate, rotate, scale);
+        transform.t = t;
+        transform.inv_t = inv_t;
+        transform.normal = inv_t.transpose();
+        transform
+    }
+
+    pub fn from_json(json: &Value) -> Self {
+        // 按行给出的4x4矩阵
+        if !json["matrix"].is_null() {
+            let rows = serde_json::from_value::<[[f32; 4]; 4]>(json["matrix"].clone())
+                .expect("Error in transform matrix format!");
+            return Transform::from_matrix(M4f::from(rows).transpose());
+        }
+        let translate_mat = Transform::translation(fetch_v3f(json, "translate", V3f::zero()));
+        let scale_mat = Transform::scalation(fetch_v3f(json, "scale", V3f::from([1.0; 3])));
+        let rotate_mat = if !json["rotate"].is_null() {
+            let axis = fetch_v3f(&json["rotate"], "axis", V3f::from([1.0; 3]));
+            let radian = json["rotate"]["radian"].as_f64().unwrap_or(0.0);
+            Transform::rotation(axis, radian as f32)
+        } else {
+            Matrix4::identity()
+        };
+        Transform::new(translate_mat, rotate_mat, scale_mat)
+    }
+
     pub fn translation(offset: V3f) -> M4f {
         let mut mat = M4f::identity();
         for i in 0..3 {
@@ -102,6 +146,24 @@ impl Transform {
         Point3::from_homogeneous(v4)
     }
 
+    // 法线需要用逆转置矩阵变换，平移不影响法线
+    pub fn to_world_normal(&self, n: V3f) -> V3f {
+        let v4 = Vector4::new(n[0], n[1], n[2], 0.0);
+        let v4 = self.normal * v4;
+        v4.xyz().normalize()
+    }
+
+    pub fn to_local_vec(&self, v: V3f) -> V3f {
+        let v4 = Vector4::new(v[0], v[1], v[2], 0.0);
+        let v4 = self.inv_t * v4;
+        v4.xyz()
+    }
+
+    pub fn to_local_point(&self, v: Point3<f32>) -> Point3<f32> {
+        let v4 = self.inv_t * v.to_homogeneous();
+        Point3::from_homogeneous(v4)
+    }
+
     pub fn to_world_bounds3(&self, b: Bounds3) -> Bounds3 {
         let mut res = Bounds3::default();
         let ps = [&b.p_min, &b.p_max];
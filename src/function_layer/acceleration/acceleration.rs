@@ -20,12 +20,23 @@ pub trait Acceleration {
         }
         let (geom_id, prime_id, u, v) = hit.unwrap();
         let mut its = SurfaceInteraction::default();
-        let shape = self.acceleration().shapes[geom_id as usize].borrow();
-        shape.fill_intersection(ray.t_max, prime_id, u, v, ray.medium.clone(), &mut its);
+        its.wo = -ray.direction;
+        let handle = &self.acceleration().shapes[geom_id as usize];
+        handle
+            .borrow()
+            .fill_intersection(ray.t_max, prime_id, u, v, ray.medium.clone(), &mut its);
+        // 实例等形状可能已经设置了实际被击中的形状
+        if its.shape.is_none() {
+            its.shape = Some(handle.clone());
+        }
 
         Some(its)
     }
     fn ray_intersect(&self, ray: &mut Ray) -> Option<(u64, u64, f32, f32)>;
+    /// 判断光线在[t_min, t_max]内是否被遮挡，找到任意交点即可返回
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.ray_intersect(&mut ray.clone()).is_some()
+    }
     fn build(&mut self);
     fn attach_shape(&mut self, shape: RR<dyn Shape>) {
         self.acceleration_mut().shapes.push(shape)
@@ -1,52 +1,60 @@
-use crate::function_layer::{bounds3::Axis, Acceleration, Bounds3, Ray, Shape, RR};
+use crate::function_layer::{bounds3::Axis, Acceleration, Bounds3, Ray, V3f};
 
 use super::acceleration::{AccelerationBase, AccelerationType};
 
-pub enum BVHNode {
-    Node {
-        bounds: Bounds3,
-        left: usize,
-        right: usize,
-        // father: usize,
-        split_axis: Axis,
-    },
-    Leaf {
-        bounds: Bounds3,
-        shape_idx: usize,
-        // father: usize,
-    },
+const N_BUCKETS: usize = 12;
+const MAX_PRIMS_IN_NODE: usize = 4;
+// 超过该深度时直接生成叶节点，保证遍历时固定大小的栈不会溢出
+pub(super) const MAX_DEPTH: usize = 64;
+// 遍历一个内部节点相对于求交一个图元的代价
+const TRAVERSAL_COST: f32 = 0.125;
+
+/// 构建时使用的图元信息，预先计算包围盒和质心，避免构建过程中反复借用shape
+pub(super) struct BVHPrimitive {
+    pub index: usize,
+    pub bounds: Bounds3,
+    pub centroid: V3f,
 }
 
-impl BVHNode {
-    pub fn get_bounds(&self) -> &Bounds3 {
-        match self {
-            BVHNode::Node { bounds: b, .. } | BVHNode::Leaf { bounds: b, .. } => b,
-        }
-    }
-    #[allow(dead_code)]
-    pub fn get_father_idx(&self) -> usize {
-        match self {
-            BVHNode::Node { .. } | BVHNode::Leaf { .. } => 0,
+impl BVHPrimitive {
+    pub fn new(index: usize, bounds: Bounds3) -> Self {
+        let centroid = bounds.centroid();
+        Self {
+            index,
+            bounds,
+            centroid,
         }
     }
 }
 
-impl Default for BVHNode {
-    fn default() -> Self {
-        BVHNode::Node {
-            bounds: Default::default(),
-            left: 0,
-            right: 0,
-            split_axis: Axis::X,
-        }
-    }
+/// 按深度优先顺序线性存储的BVH节点，左孩子紧跟在父节点之后
+pub(super) struct LinearBVHNode {
+    pub bounds: Bounds3,
+    // 叶节点：第一个图元在indices中的位置；内部节点：右孩子的下标
+    pub offset: usize,
+    pub n_prims: usize,
+    pub axis: usize,
+}
+
+/// 使用分桶SAH构建二叉BVH
+#[derive(Default)]
+pub(super) struct BVHBuilder {
+    pub nodes: Vec<LinearBVHNode>,
+    pub indices: Vec<usize>,
 }
 
-const USE_SAH: bool = true;
+/// 按包围盒构建的二叉BVH，只负责遍历，图元的求交由调用者完成。
+/// 场景级的BVHAccel和形状内部的图元(如曲线段)共用
+#[derive(Default)]
+pub struct PrimitiveBVH {
+    nodes: Vec<LinearBVHNode>,
+    // 叶节点中的图元在构建时传入的数组中的下标
+    indices: Vec<usize>,
+}
 
 #[derive(Default)]
 pub struct BVHAccel {
-    nodes: Vec<BVHNode>,
+    bvh: PrimitiveBVH,
     pub acc: AccelerationBase,
 }
 
@@ -60,20 +68,37 @@ impl Acceleration for BVHAccel {
     }
 
     fn ray_intersect(&self, ray: &mut Ray) -> Option<(u64, u64, f32, f32)> {
-        if self.nodes.is_empty() {
-            return None;
-        }
-        BVHAccel::get_intersection(&self.nodes, 0, ray, &self.acc.shapes)
+        let mut hit = None;
+        self.bvh.traverse(ray, |shape_idx, ray| {
+            let shape = self.acc.shapes[shape_idx].borrow();
+            if let Some((p_id, u, v)) = shape.ray_intersect_shape(ray) {
+                hit = Some((shape.geometry_id(), p_id, u, v));
+            }
+            false
+        });
+        hit
+    }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        let mut ray = ray.clone();
+        self.bvh.traverse(&mut ray, |shape_idx, ray| {
+            self.acc.shapes[shape_idx].borrow().ray_occluded(ray)
+        })
     }
 
     fn build(&mut self) {
         for shape in &self.acc.shapes {
             shape.borrow_mut().init_internal_acceleration();
         }
-        recursively_build(&mut self.acc.shapes, 0, &mut self.nodes);
-        // TODO: 单纯的SAH构建的树可能不平衡
-        if let Some(n) = self.nodes.get(0) {
-            self.acc.bounds = n.get_bounds().clone();
+        let bounds: Vec<Bounds3> = self
+            .acc
+            .shapes
+            .iter()
+            .map(|shape| shape.borrow().get_bounds().clone())
+            .collect();
+        self.bvh = PrimitiveBVH::new(&bounds);
+        if let Some(bounds) = self.bvh.bounds() {
+            self.acc.bounds = bounds.clone();
         }
     }
 
@@ -82,138 +107,183 @@ impl Acceleration for BVHAccel {
     }
 }
 
-fn get_bounds_arr(shapes: &[RR<dyn Shape>]) -> Bounds3 {
-    let bounds_v: Vec<_> = shapes
-        .iter()
-        .map(|s: &RR<dyn Shape>| s.borrow().get_bounds().clone())
-        .collect();
-    Bounds3::arr_bounds(bounds_v)
+fn axis_index(axis: Axis) -> usize {
+    match axis {
+        Axis::X => 0,
+        Axis::Y => 1,
+        Axis::Z => 2,
+    }
 }
 
-fn recursively_build(shapes: &mut [RR<dyn Shape>], b: usize, nodes: &mut Vec<BVHNode>) -> usize {
-    let bounds = get_bounds_arr(shapes);
-    let idx = nodes.len();
-    if shapes.len() == 0 {
-        return nodes.len();
+impl BVHBuilder {
+    pub fn build(prims: &mut [BVHPrimitive]) -> Self {
+        let mut builder = Self {
+            nodes: Vec::with_capacity(2 * prims.len()),
+            indices: Vec::with_capacity(prims.len()),
+        };
+        if !prims.is_empty() {
+            builder.recursively_build(prims, 0);
+        }
+        builder
     }
-    if shapes.len() == 1 {
-        nodes.push(BVHNode::Leaf {
+
+    fn push_leaf(&mut self, prims: &[BVHPrimitive], bounds: Bounds3) -> usize {
+        let idx = self.nodes.len();
+        self.nodes.push(LinearBVHNode {
             bounds,
-            shape_idx: b,
+            offset: self.indices.len(),
+            n_prims: prims.len(),
+            axis: 0,
         });
-        return idx;
+        self.indices.extend(prims.iter().map(|p| p.index));
+        idx
     }
-    if shapes.len() == 2 {
-        nodes.push(BVHNode::default());
-        let l = recursively_build(&mut shapes[..1], b, nodes);
-        let r = recursively_build(&mut shapes[1..], b + 1, nodes);
-        nodes[idx] = BVHNode::Node {
-            bounds,
-            left: l,
-            right: r,
-            split_axis: Axis::X,
+
+    fn recursively_build(&mut self, prims: &mut [BVHPrimitive], depth: usize) -> usize {
+        let bounds = prims.iter().fold(Bounds3::empty(), |b, p| {
+            Bounds3::union_bounds(&b, &p.bounds)
+        });
+        if prims.len() == 1 || depth >= MAX_DEPTH {
+            return self.push_leaf(prims, bounds);
+        }
+        let centroid_bounds = prims.iter().fold(Bounds3::empty(), |b, p| {
+            Bounds3::union_point(&b, p.centroid)
+        });
+        let axis = axis_index(centroid_bounds.max_extent());
+        let c_min = centroid_bounds.p_min[axis];
+        let c_max = centroid_bounds.p_max[axis];
+        // 所有质心重合，无法继续划分
+        if c_max <= c_min {
+            return self.push_leaf(prims, bounds);
+        }
+
+        // 将质心分到若干个桶中，在桶的边界处计算SAH代价
+        let bucket_of = |p: &BVHPrimitive| {
+            let b = (N_BUCKETS as f32 * (p.centroid[axis] - c_min) / (c_max - c_min)) as usize;
+            b.min(N_BUCKETS - 1)
         };
-        return idx;
-    }
-    let mut mid = shapes.len() / 2;
-    let axis = bounds.max_extent();
-    let _ = shapes.sort_unstable_by(|s1: &RR<dyn Shape>, s2: &RR<dyn Shape>| {
-        s1.borrow()
-            .shape()
-            .bounds3
-            .centroid_axis(axis)
-            .partial_cmp(&s2.borrow().shape().bounds3.centroid_axis(axis))
-            .unwrap()
-    });
-    for i in 0..shapes.len() {
-        shapes[i].borrow_mut().set_geometry_id((i + b) as u64);
-    }
-    if USE_SAH && shapes.len() > 4 {
-        let len = shapes.len();
-        let part = len.min(32);
-        let mut scores = Vec::with_capacity(part);
-        scores.push(f32::MAX);
-        for idx in 1..part {
-            let pp = len * idx / part;
-            let ls = &shapes[..pp];
-            let rs = &shapes[pp..];
-            assert_eq!(ls.len() + rs.len(), shapes.len());
-            let lbs = get_bounds_arr(ls);
-            let rbs = get_bounds_arr(rs);
-            let l_area = lbs.surface_area();
-            let r_area = rbs.surface_area();
-            // let area = l_area + r_area;
-            scores.push(l_area * ls.len() as f32 + r_area * rs.len() as f32);
+        let mut counts = [0usize; N_BUCKETS];
+        let mut bucket_bounds: Vec<Bounds3> = vec![Bounds3::empty(); N_BUCKETS];
+        for p in prims.iter() {
+            let b = bucket_of(p);
+            counts[b] += 1;
+            bucket_bounds[b] = Bounds3::union_bounds(&bucket_bounds[b], &p.bounds);
         }
-        let cut = scores
+        // 从左往右和从右往左分别累积，得到每个划分位置两侧的面积与图元数
+        let mut costs = [0.0f32; N_BUCKETS - 1];
+        let mut below = Bounds3::empty();
+        let mut count_below = 0;
+        for i in 0..N_BUCKETS - 1 {
+            below = Bounds3::union_bounds(&below, &bucket_bounds[i]);
+            count_below += counts[i];
+            costs[i] = count_below as f32 * below.surface_area();
+        }
+        let mut above = Bounds3::empty();
+        let mut count_above = 0;
+        for i in (1..N_BUCKETS).rev() {
+            above = Bounds3::union_bounds(&above, &bucket_bounds[i]);
+            count_above += counts[i];
+            costs[i - 1] += count_above as f32 * above.surface_area();
+        }
+        let (split, min_cost) = costs
             .iter()
             .enumerate()
+            .filter(|(_, c)| c.is_finite())
             .min_by(|(_, a), (_, b)| a.total_cmp(b))
-            .map(|(i, _)| i)
+            .map(|(i, c)| (i, TRAVERSAL_COST + c / bounds.surface_area()))
             .unwrap();
-        mid = len * cut / part;
+        let leaf_cost = prims.len() as f32;
+        if prims.len() <= MAX_PRIMS_IN_NODE && min_cost >= leaf_cost {
+            return self.push_leaf(prims, bounds);
+        }
+
+        let mut mid = partition(prims, |p| bucket_of(p) <= split);
+        if mid == 0 || mid == prims.len() {
+            mid = prims.len() / 2;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(LinearBVHNode {
+            bounds,
+            offset: 0,
+            n_prims: 0,
+            axis,
+        });
+        let (left, right) = prims.split_at_mut(mid);
+        self.recursively_build(left, depth + 1);
+        let r = self.recursively_build(right, depth + 1);
+        self.nodes[idx].offset = r;
+        idx
     }
-    nodes.push(BVHNode::Node {
-        bounds: Default::default(),
-        left: 0,
-        right: 0,
-        split_axis: Axis::X,
-    });
-    let l = recursively_build(&mut shapes[..mid], b, nodes);
-    let r = recursively_build(&mut shapes[mid..], b + mid, nodes);
-    nodes[idx] = BVHNode::Node {
-        bounds,
-        left: l,
-        right: r,
-        split_axis: axis,
-    };
-    idx
 }
 
-impl BVHAccel {
-    pub fn get_intersection(
-        nodes: &Vec<BVHNode>,
-        root: usize,
-        ray: &mut Ray,
-        shapes: &Vec<RR<dyn Shape>>,
-    ) -> Option<(u64, u64, f32, f32)> {
-        if !nodes[root].get_bounds().intersect_p(ray) {
-            return None;
+impl PrimitiveBVH {
+    /// 由bounds中的包围盒构建BVH，图元的下标即为其在bounds中的位置
+    pub fn new(bounds: &[Bounds3]) -> Self {
+        let mut prims: Vec<BVHPrimitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, b)| BVHPrimitive::new(index, b.clone()))
+            .collect();
+        let builder = BVHBuilder::build(&mut prims);
+        Self {
+            nodes: builder.nodes,
+            indices: builder.indices,
+        }
+    }
+
+    /// 所有图元的包围盒，没有图元时为None
+    pub fn bounds(&self) -> Option<&Bounds3> {
+        self.nodes.first().map(|root| &root.bounds)
+    }
+
+    /// 由近及远遍历BVH，对每个叶节点中的图元调用func；func返回true时立即结束遍历
+    pub fn traverse(&self, ray: &mut Ray, mut func: impl FnMut(usize, &mut Ray) -> bool) -> bool {
+        if self.nodes.is_empty() {
+            return false;
         }
-        let node = &nodes[root];
-        match node {
-            BVHNode::Node {
-                left: l,
-                right: r,
-                split_axis: axis,
-                ..
-            } => {
-                let mut two_children = [*l, *r];
-                let flip: bool = match axis {
-                    Axis::X => ray.direction.x < 0.0,
-                    Axis::Y => ray.direction.y < 0.0,
-                    Axis::Z => ray.direction.z < 0.0,
-                };
-                if flip {
-                    two_children.reverse();
-                }
-                let hit1 = BVHAccel::get_intersection(nodes, two_children[0], ray, shapes);
-                let hit2 = BVHAccel::get_intersection(nodes, two_children[1], ray, shapes);
-                if hit2.is_some() {
-                    hit2
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            // ray.t_max随着求交而缩短，远处的节点会被剔除
+            let node = &self.nodes[current];
+            if node.bounds.intersect_p(ray) {
+                if node.n_prims > 0 {
+                    for &index in &self.indices[node.offset..node.offset + node.n_prims] {
+                        if func(index, ray) {
+                            return true;
+                        }
+                    }
                 } else {
-                    hit1
+                    // 先访问光线方向上较近的孩子
+                    let (near, far) = if ray.neg_dir[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[top] = far;
+                    top += 1;
+                    current = near;
+                    continue;
                 }
             }
-            BVHNode::Leaf { shape_idx: idx, .. } => {
-                let shape = shapes[*idx].borrow();
-                let its = shape.ray_intersect_shape(ray);
-                if let Some((p_id, u, v)) = its {
-                    Some((shape.geometry_id(), p_id, u, v))
-                } else {
-                    None
-                }
+            if top == 0 {
+                return false;
             }
+            top -= 1;
+            current = stack[top];
+        }
+    }
+}
+
+// 将满足条件的元素移到前面，返回第一个不满足条件的位置
+fn partition<T>(v: &mut [T], pred: impl Fn(&T) -> bool) -> usize {
+    let mut first = 0;
+    for i in 0..v.len() {
+        if pred(&v[i]) {
+            v.swap(first, i);
+            first += 1;
         }
     }
+    first
 }
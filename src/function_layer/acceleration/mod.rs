@@ -2,5 +2,8 @@ pub mod acceleration;
 mod bvh;
 mod linear;
 mod octree;
+mod wide_bvh;
 
 pub use acceleration::Acceleration;
+pub use bvh::PrimitiveBVH;
+pub use wide_bvh::{build_triangle_accel, TriangleAccel};
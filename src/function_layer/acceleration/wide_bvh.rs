@@ -0,0 +1,325 @@
+use super::bvh::{BVHBuilder, BVHPrimitive, MAX_DEPTH};
+use crate::function_layer::{Bounds3, Ray};
+use cgmath::{EuclideanSpace, Point3};
+use std::rc::Rc;
+
+/// 三角形网格专用的加速结构
+pub trait TriangleAccel {
+    // 返回(prim_id, u, v)，并更新ray.t_max
+    fn intersect(&self, ray: &mut Ray) -> Option<(u64, f32, f32)>;
+    fn occluded(&self, ray: &Ray) -> bool;
+    fn bounds(&self) -> &Bounds3;
+}
+
+/// 构建宽度为width的三角形BVH，triangles中的顶点为世界坐标，下标即为prim_id
+pub fn build_triangle_accel(triangles: &[[Point3<f32>; 3]], width: usize) -> Rc<dyn TriangleAccel> {
+    match width {
+        4 => Rc::new(TriangleBVH::<4>::new(triangles)),
+        8 => Rc::new(TriangleBVH::<8>::new(triangles)),
+        w => panic!("Unsupported BVH width: {}!", w),
+    }
+}
+
+// 坍缩后的深度不超过二叉BVH的深度，每层最多多压入N - 1个孩子
+const STACK_SIZE: usize = 8 * MAX_DEPTH;
+
+#[derive(Copy, Clone)]
+enum WideChild {
+    Empty,
+    Node(u32),
+    // 叶节点包含packets[offset..offset + count]
+    Leaf { offset: u32, count: u32 },
+}
+
+/// N叉BVH节点，N个孩子的包围盒按分量分开存储，便于同时与N个包围盒求交
+struct WideNode<const N: usize> {
+    min: [[f32; N]; 3],
+    max: [[f32; N]; 3],
+    children: [WideChild; N],
+}
+
+/// N个三角形打包存储(SoA)，不足N个时用退化三角形填充
+struct TrianglePacket<const N: usize> {
+    v0: [[f32; N]; 3],
+    e0: [[f32; N]; 3],
+    e1: [[f32; N]; 3],
+    prim: [u32; N],
+}
+
+impl<const N: usize> TrianglePacket<N> {
+    fn new(ids: &[usize], triangles: &[[Point3<f32>; 3]]) -> Self {
+        let mut packet = Self {
+            v0: [[0.0; N]; 3],
+            e0: [[0.0; N]; 3],
+            e1: [[0.0; N]; 3],
+            prim: [u32::MAX; N],
+        };
+        for (lane, &id) in ids.iter().enumerate() {
+            let [v0, v1, v2] = triangles[id];
+            let (e0, e1) = (v1 - v0, v2 - v0);
+            for a in 0..3 {
+                packet.v0[a][lane] = v0[a];
+                packet.e0[a][lane] = e0[a];
+                packet.e1[a][lane] = e1[a];
+            }
+            packet.prim[lane] = id as u32;
+        }
+        packet
+    }
+
+    // 同时与N个三角形求交(Möller–Trumbore)，各通道之间没有分支，便于编译器向量化
+    fn intersect(
+        &self,
+        o: [f32; 3],
+        d: [f32; 3],
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(usize, f32, f32, f32)> {
+        let mut ts = [f32::INFINITY; N];
+        let mut us = [0.0; N];
+        let mut vs = [0.0; N];
+        for i in 0..N {
+            let e0 = [self.e0[0][i], self.e0[1][i], self.e0[2][i]];
+            let e1 = [self.e1[0][i], self.e1[1][i], self.e1[2][i]];
+            let pvec = cross(d, e1);
+            let det = dot(e0, pvec);
+            let det_inv = 1.0 / det;
+            let tvec = [
+                o[0] - self.v0[0][i],
+                o[1] - self.v0[1][i],
+                o[2] - self.v0[2][i],
+            ];
+            let qvec = cross(tvec, e0);
+            let u = dot(tvec, pvec) * det_inv;
+            let v = dot(d, qvec) * det_inv;
+            let t = dot(e1, qvec) * det_inv;
+            let valid = (det.abs() >= 0.00001)
+                & (u >= 0.0)
+                & (v >= 0.0)
+                & (u + v <= 1.0)
+                & (t >= t_min)
+                & (t <= t_max);
+            ts[i] = if valid { t } else { f32::INFINITY };
+            us[i] = u;
+            vs[i] = v;
+        }
+        let mut hit = None;
+        let mut closest = f32::INFINITY;
+        for i in 0..N {
+            if ts[i] < closest {
+                closest = ts[i];
+                hit = Some((i, ts[i], us[i], vs[i]));
+            }
+        }
+        hit
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// 由二叉BVH坍缩得到的N叉BVH
+pub struct TriangleBVH<const N: usize> {
+    nodes: Vec<WideNode<N>>,
+    packets: Vec<TrianglePacket<N>>,
+    bounds: Bounds3,
+}
+
+impl<const N: usize> TriangleBVH<N> {
+    pub fn new(triangles: &[[Point3<f32>; 3]]) -> Self {
+        let mut prims: Vec<BVHPrimitive> = triangles
+            .iter()
+            .enumerate()
+            .map(|(i, tri)| {
+                let mut bounds = Bounds3::empty();
+                for p in tri {
+                    bounds.expand(p.to_vec());
+                }
+                BVHPrimitive::new(i, bounds)
+            })
+            .collect();
+        let binary = BVHBuilder::build(&mut prims);
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(binary.nodes.len() / (N - 1) + 1),
+            packets: Vec::with_capacity(triangles.len() / N + 1),
+            bounds: Bounds3::empty(),
+        };
+        if let Some(root) = binary.nodes.first() {
+            bvh.bounds = root.bounds.clone();
+            bvh.collapse(&binary, 0, triangles);
+        }
+        bvh
+    }
+
+    // 不断展开表面积最大的内部孩子，直到孩子数达到N
+    fn collapse(&mut self, binary: &BVHBuilder, idx: usize, triangles: &[[Point3<f32>; 3]]) -> u32 {
+        let bn = &binary.nodes;
+        let mut children = if bn[idx].n_prims > 0 {
+            vec![idx]
+        } else {
+            vec![idx + 1, bn[idx].offset]
+        };
+        while children.len() < N {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| bn[c].n_prims == 0)
+                .max_by(|(_, &a), (_, &b)| {
+                    bn[a]
+                        .bounds
+                        .surface_area()
+                        .total_cmp(&bn[b].bounds.surface_area())
+                })
+                .map(|(i, _)| i);
+            match largest {
+                None => break,
+                Some(i) => {
+                    let c = children.swap_remove(i);
+                    children.push(c + 1);
+                    children.push(bn[c].offset);
+                }
+            }
+        }
+
+        let node_idx = self.nodes.len();
+        self.nodes.push(WideNode {
+            min: [[f32::INFINITY; N]; 3],
+            max: [[f32::NEG_INFINITY; N]; 3],
+            children: [WideChild::Empty; N],
+        });
+        for (lane, &c) in children.iter().enumerate() {
+            let child = if bn[c].n_prims > 0 {
+                let ids = &binary.indices[bn[c].offset..bn[c].offset + bn[c].n_prims];
+                let offset = self.packets.len() as u32;
+                for chunk in ids.chunks(N) {
+                    self.packets.push(TrianglePacket::new(chunk, triangles));
+                }
+                WideChild::Leaf {
+                    offset,
+                    count: self.packets.len() as u32 - offset,
+                }
+            } else {
+                WideChild::Node(self.collapse(binary, c, triangles))
+            };
+            let node = &mut self.nodes[node_idx];
+            for a in 0..3 {
+                node.min[a][lane] = bn[c].bounds.p_min[a];
+                node.max[a][lane] = bn[c].bounds.p_max[a];
+            }
+            node.children[lane] = child;
+        }
+        node_idx as u32
+    }
+
+    // 同时与N个孩子的包围盒求交，未相交的通道返回无穷远
+    fn intersect_node(
+        node: &WideNode<N>,
+        o: [f32; 3],
+        inv_dir: [f32; 3],
+        neg_dir: [bool; 3],
+        t_min: f32,
+        t_max: f32,
+    ) -> [f32; N] {
+        let mut near = [t_min; N];
+        let mut far = [t_max; N];
+        for a in 0..3 {
+            // 空的通道min为+inf、max为-inf，按光线方向选取近、远平面后总是不相交
+            let (lo, hi) = if neg_dir[a] {
+                (&node.max[a], &node.min[a])
+            } else {
+                (&node.min[a], &node.max[a])
+            };
+            for i in 0..N {
+                near[i] = near[i].max((lo[i] - o[a]) * inv_dir[a]);
+                far[i] = far[i].min((hi[i] - o[a]) * inv_dir[a]);
+            }
+        }
+        let mut dist = [f32::INFINITY; N];
+        for i in 0..N {
+            if near[i] <= far[i] {
+                dist[i] = near[i];
+            }
+        }
+        dist
+    }
+
+    fn traverse(&self, ray: &mut Ray, any_hit: bool) -> Option<(u64, f32, f32)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let o = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let d = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let inv_dir = [ray.inv_dir.x, ray.inv_dir.y, ray.inv_dir.z];
+        let t_min = ray.t_min;
+        let mut t_max = ray.t_max;
+        let mut hit = None;
+        let mut stack = [(WideChild::Empty, 0.0f32); STACK_SIZE];
+        stack[0] = (WideChild::Node(0), t_min);
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let (child, dist) = stack[top];
+            // 已经找到更近的交点
+            if dist > t_max {
+                continue;
+            }
+            match child {
+                WideChild::Empty => (),
+                WideChild::Node(idx) => {
+                    let node = &self.nodes[idx as usize];
+                    let dists = Self::intersect_node(node, o, inv_dir, ray.neg_dir, t_min, t_max);
+                    // 按距离从远到近入栈，使近处的孩子先被访问
+                    let mut order = [(WideChild::Empty, 0.0); N];
+                    let mut n = 0;
+                    for (&child, &dist) in node.children.iter().zip(dists.iter()) {
+                        if dist < f32::INFINITY {
+                            order[n] = (child, dist);
+                            n += 1;
+                        }
+                    }
+                    order[..n].sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+                    stack[top..top + n].copy_from_slice(&order[..n]);
+                    top += n;
+                }
+                WideChild::Leaf { offset, count } => {
+                    for packet in &self.packets[offset as usize..(offset + count) as usize] {
+                        if let Some((lane, t, u, v)) = packet.intersect(o, d, t_min, t_max) {
+                            t_max = t;
+                            hit = Some((packet.prim[lane] as u64, u, v));
+                            if any_hit {
+                                return hit;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if hit.is_some() {
+            ray.t_max = t_max;
+        }
+        hit
+    }
+}
+
+impl<const N: usize> TriangleAccel for TriangleBVH<N> {
+    fn intersect(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        self.traverse(ray, false)
+    }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.traverse(&mut ray.clone(), true).is_some()
+    }
+
+    fn bounds(&self) -> &Bounds3 {
+        &self.bounds
+    }
+}
@@ -95,13 +95,6 @@ impl Bounds3 {
             p_max: ele_wise_max(b.p_max, p),
         }
     }
-    pub fn centroid_axis(&self, a: Axis) -> f32 {
-        match a {
-            Axis::X => self.centroid().x,
-            Axis::Y => self.centroid().y,
-            Axis::Z => self.centroid().z,
-        }
-    }
     pub fn arr_bounds(v: Vec<Bounds3>) -> Bounds3 {
         v.into_iter()
             .fold(Bounds3::default(), |b1, b2| Bounds3::union_bounds(&b1, &b2))
@@ -2,7 +2,7 @@ use cgmath::{InnerSpace, Vector2, Zero};
 use serde_json::Value;
 
 use crate::core_layer::colorspace::SpectrumRGB;
-use crate::function_layer::{compute_ray_differentials, InfiniteLight, Integrator, Ray, RR, Sampler, Scene, Texture, V3f};
+use crate::function_layer::{compute_ray_differentials, InfiniteLight, Integrator, Ray, RR, Sampler, Scene, V3f};
 use crate::function_layer::integrator::integrator::sample_interaction_illumination;
 use crate::function_layer::material::MaterialType;
 use crate::function_layer::texture::TextureCoord;
@@ -32,7 +32,7 @@ impl Integrator for BlackHoleIntegrator {
         for _ in 0..self.max_iter {
             let intersection_opt = scene.ray_intersect(ray);
             if let Some(inter) = intersection_opt {
-                let shape = inter.shape.as_ref().unwrap();
+                let shape = inter.shape.as_ref().unwrap().borrow();
                 if let Some(mat) = shape.material().as_ref() {
                     if mat.mat_type() == MaterialType::BlackHole {
                         return spectrum;
@@ -62,7 +62,7 @@ impl Integrator for BlackHoleIntegrator {
         }
         let mut inter = intersection_opt.unwrap();
         compute_ray_differentials(&mut inter, ray);
-        let shape = inter.shape.as_ref().unwrap();
+        let shape = inter.shape.as_ref().unwrap().borrow();
         if let Some(light) = shape.get_light() {
             spectrum += light.borrow().evaluate_emission(&inter, -ray.direction);
         }
@@ -19,7 +19,7 @@ impl Integrator for DirectIntegratorSampleLight {
         }
         let mut inter = intersection_opt.unwrap();
         compute_ray_differentials(&mut inter, ray);
-        let shape = inter.shape.as_ref().unwrap();
+        let shape = inter.shape.as_ref().unwrap().borrow();
         if let Some(light) = shape.get_light() {
             spectrum += light.borrow().evaluate_emission(&inter, -ray.direction);
         }
@@ -49,14 +49,13 @@ impl Integrator for DirectIntegratorSampleBSDF {
         }
         let intersection = intersection_opt.unwrap();
 
-        let shape = intersection.shape.as_ref().unwrap();
+        let shape = intersection.shape.as_ref().unwrap().borrow();
         if let Some(light) = shape.get_light() {
             spectrum += light
                 .borrow()
                 .evaluate_emission(&intersection, -ray.direction);
         }
-        let material = shape.material();
-        let bsdf = material.unwrap().compute_bsdf(&intersection);
+        let bsdf = intersection.bsdf();
         let bsdf_sample_result = bsdf.sample(-ray.direction, sampler.borrow_mut().next_2d());
         let mut shadow_ray = Ray::new(intersection.position, bsdf_sample_result.wi);
         let find_light = scene.ray_intersect(&mut shadow_ray);
@@ -68,7 +67,7 @@ impl Integrator for DirectIntegratorSampleBSDF {
                 }
             }
             Some(fl) => {
-                let shape = fl.shape.as_ref().unwrap();
+                let shape = fl.shape.as_ref().unwrap().borrow();
                 if let Some(light) = shape.get_light() {
                     spectrum += bsdf_sample_result.weight
                         * light.borrow().evaluate_emission(&fl, -shadow_ray.direction);
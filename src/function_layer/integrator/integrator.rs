@@ -7,8 +7,9 @@ use super::{
 };
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::function_layer::light::light::{LightSampleResult, LightType};
-use crate::function_layer::{Interaction, Light, Ray, Sampler, Scene, V3f, RR};
-use cgmath::InnerSpace;
+use crate::function_layer::material::bxdf::bssrdf::BurleyBSSRDF;
+use crate::function_layer::{Interaction, Light, Ray, Sampler, Scene, SurfaceInteraction, V3f, RR};
+use cgmath::{InnerSpace, Zero};
 use serde_json::Value;
 use crate::function_layer::integrator::black_hole_integrator::BlackHoleIntegrator;
 
@@ -46,11 +47,12 @@ where
         let res = light.sample(inter, sampler.borrow_mut().next_2d());
         let mut shadow_ray = Ray::new(inter.p() + res.direction * 1e-4, res.direction);
         shadow_ray.t_max = res.distance;
-        let occlude = scene.ray_intersect(&mut shadow_ray);
-        if occlude.is_none() {
+        shadow_ray.medium = inter.medium(res.direction);
+        let tr = scene.transmittance(&shadow_ray, sampler.clone());
+        if !tr.rgb().is_zero() {
             let f = inter.f(wo, shadow_ray.direction);
             let pdf = convert_pdf(&res, inter);
-            spectrum += throughput * res.energy * f / pdf;
+            spectrum += throughput * tr * res.energy * f / pdf;
         }
     }
     let mut pdf_light = 0.0;
@@ -60,17 +62,51 @@ where
         let mut res = light.borrow().sample(inter, sampler.borrow_mut().next_2d());
         let mut shadow_ray = Ray::new(inter.p(), res.direction);
         shadow_ray.t_max = res.distance;
-        let occlude = scene.ray_intersect(&mut shadow_ray);
-        if occlude.is_none() {
+        shadow_ray.medium = inter.medium(res.direction);
+        let tr = scene.transmittance(&shadow_ray, sampler.clone());
+        if !tr.rgb().is_zero() {
             let f = inter.f(wo, shadow_ray.direction);
             res.pdf *= pdf_light;
             let pdf = convert_pdf(&res, inter);
-            spectrum += throughput * res.energy * f / pdf;
+            spectrum += throughput * tr * res.energy * f / pdf;
         }
     }
     spectrum
 }
 
+/// 光线折射进入次表面散射材质后的延续：采样BSSRDF的出射点，在出射点处采样光源，
+/// 再按出射点的BSDF采样出射方向并更新光线。无法继续时返回None
+pub fn sample_subsurface_exit(
+    scene: &Scene,
+    bssrdf: &BurleyBSSRDF,
+    ray: &mut Ray,
+    spectrum: &mut SpectrumRGB,
+    sampler: RR<dyn Sampler>,
+    throughput: &mut SpectrumRGB,
+) -> Option<SurfaceInteraction> {
+    let (exit, weight) = bssrdf.sample_sp(scene, sampler.clone())?;
+    *throughput *= &weight;
+    *spectrum = sample_interaction_illumination(
+        scene,
+        -ray.direction,
+        &exit,
+        *spectrum,
+        sampler.clone(),
+        *throughput,
+    );
+    let sw_sample_result = exit
+        .bsdf()
+        .sample(-ray.direction, sampler.borrow_mut().next_2d());
+    if sw_sample_result.weight.rgb().is_zero() {
+        return None;
+    }
+    *throughput *= &sw_sample_result.weight;
+    ray.origin = exit.position;
+    ray.change_dir(sw_sample_result.wi);
+    ray.reset();
+    Some(exit)
+}
+
 pub fn construct_integrator(json: &Value) -> Box<dyn Integrator> {
     match json["type"].as_str().unwrap() {
         "directSampleLight" => Box::new(DirectIntegratorSampleLight {}),
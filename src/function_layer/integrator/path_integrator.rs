@@ -1,13 +1,13 @@
-use cgmath::Zero;
+use cgmath::{InnerSpace, Zero};
 use serde_json::Value;
 
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::function_layer::material::bxdf::BSDFType;
 use crate::function_layer::{
-    compute_ray_differentials, InfiniteLight, Integrator, Ray, Sampler, Scene, RR,
+    compute_ray_differentials, InfiniteLight, Integrator, Interaction, Ray, Sampler, Scene, RR,
 };
 
-use super::integrator::sample_interaction_illumination;
+use super::integrator::{sample_interaction_illumination, sample_subsurface_exit};
 
 pub struct PathIntegrator {
     max_depth: u32,
@@ -36,9 +36,16 @@ impl Integrator for PathIntegrator {
                 return spectrum;
             }
             let mut inter = inter_opt.unwrap();
+            // 只划分介质的表面：切换介质后继续沿原方向前进，不计入弹射次数
+            if inter.is_interface() {
+                ray.medium = inter.medium(ray.direction);
+                ray.origin = inter.position;
+                ray.reset();
+                continue;
+            }
             compute_ray_differentials(&mut inter, ray);
             if depth == 0 || specular_bounce {
-                if let Some(light) = inter.shape.as_ref().unwrap().get_light() {
+                if let Some(light) = inter.shape.as_ref().unwrap().borrow().get_light() {
                     spectrum += light.borrow().evaluate_emission(&inter, -ray.direction);
                 }
             }
@@ -58,20 +65,32 @@ impl Integrator for PathIntegrator {
                 break;
             }
             throughput /= 0.95;
-            let bsdf = inter
-                .shape
-                .as_ref()
-                .unwrap()
-                .material()
-                .as_ref()
-                .unwrap()
-                .compute_bsdf(&inter);
+            let bsdf = inter.bsdf();
             let bsdf_sample_result = bsdf.sample(-ray.direction, sampler.borrow_mut().next_2d());
             if bsdf_sample_result.weight.rgb().is_zero() {
                 break;
             }
             throughput *= &bsdf_sample_result.weight;
 
+            // 折射进入次表面散射材质：采样出射点，在出射点处采样光源和出射方向
+            if inter.normal.dot(bsdf_sample_result.wi) < 0.0 {
+                if let Some(bssrdf) = inter.material().compute_bssrdf(&inter) {
+                    let exit = sample_subsurface_exit(
+                        scene,
+                        &bssrdf,
+                        ray,
+                        &mut spectrum,
+                        sampler.clone(),
+                        &mut throughput,
+                    );
+                    if exit.is_none() {
+                        break;
+                    }
+                    specular_bounce = false;
+                    continue;
+                }
+            }
+
             ray.origin = inter.position;
             ray.change_dir(bsdf_sample_result.wi);
             ray.reset();
@@ -1,9 +1,9 @@
-use super::integrator::sample_interaction_illumination;
+use super::integrator::{sample_interaction_illumination, sample_subsurface_exit};
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::function_layer::material::bxdf::BSDFType;
 use crate::function_layer::{
-    compute_ray_differentials, InfiniteLight, Integrator, MediumInteraction, Ray, Sampler, Scene,
-    V3f, RR,
+    compute_ray_differentials, InfiniteLight, Integrator, Interaction, MediumInteraction,
+    MediumInterface, Ray, Sampler, Scene, V3f, RR,
 };
 use cgmath::{InnerSpace, Zero};
 use serde_json::Value;
@@ -30,7 +30,9 @@ impl Integrator for VolPathIntegrator {
             let inter_opt = scene.ray_intersect(ray);
             let mut mi = MediumInteraction::default();
             if let Some(medium) = &ray.medium {
-                throughput *= &medium.sample(ray, sampler.clone(), &mut mi);
+                let weight = medium.sample(ray, sampler.clone(), &mut mi);
+                spectrum += throughput * mi.emission;
+                throughput *= &weight;
             }
             if throughput.rgb().is_zero() {
                 break;
@@ -41,6 +43,7 @@ impl Integrator for VolPathIntegrator {
                 if depth >= self.max_depth {
                     break;
                 }
+                mi.medium_interface = MediumInterface::new(ray.medium.clone(), ray.medium.clone());
                 // sample medium illumination
                 spectrum = sample_interaction_illumination(
                     scene,
@@ -70,10 +73,17 @@ impl Integrator for VolPathIntegrator {
                 }
 
                 let mut inter = inter_opt.unwrap();
+                // 只划分介质的表面：切换介质后继续沿原方向前进，不计入弹射次数
+                if inter.is_interface() {
+                    ray.medium = inter.medium(ray.direction);
+                    ray.origin = inter.position;
+                    ray.reset();
+                    continue;
+                }
                 compute_ray_differentials(&mut inter, ray);
 
                 if specular_bounce || depth == 0 {
-                    if let Some(light) = inter.shape.as_ref().unwrap().get_light() {
+                    if let Some(light) = inter.shape.as_ref().unwrap().borrow().get_light() {
                         spectrum += light.borrow().evaluate_emission(&inter, -ray.direction);
                     }
                 }
@@ -91,14 +101,7 @@ impl Integrator for VolPathIntegrator {
                 );
 
                 // sample bsdf
-                let bsdf = inter
-                    .shape
-                    .as_ref()
-                    .unwrap()
-                    .material()
-                    .as_ref()
-                    .unwrap()
-                    .compute_bsdf(&inter);
+                let bsdf = inter.bsdf();
                 let bsdf_sample_result =
                     bsdf.sample(-ray.direction, sampler.borrow_mut().next_2d());
                 if bsdf_sample_result.weight.rgb().is_zero() {
@@ -106,16 +109,37 @@ impl Integrator for VolPathIntegrator {
                 }
                 throughput *= &bsdf_sample_result.weight;
 
-                ray.origin = inter.position;
-                ray.change_dir(bsdf_sample_result.wi);
-                // change the medium
-                ray.medium = if inter.normal.dot(ray.direction) > 0.0 {
-                    inter.medium_interface.outside()
+                let bssrdf = if inter.normal.dot(bsdf_sample_result.wi) < 0.0 {
+                    inter.material().compute_bssrdf(&inter)
                 } else {
-                    inter.medium_interface.inside()
+                    None
                 };
-                ray.reset();
-                specular_bounce = bsdf_sample_result.tp == BSDFType::Specular;
+                if let Some(bssrdf) = bssrdf {
+                    // 折射进入次表面散射材质：采样出射点，在出射点处采样光源和出射方向
+                    let Some(exit) = sample_subsurface_exit(
+                        scene,
+                        &bssrdf,
+                        ray,
+                        &mut spectrum,
+                        sampler.clone(),
+                        &mut throughput,
+                    ) else {
+                        break;
+                    };
+                    ray.medium = exit.medium(ray.direction);
+                    specular_bounce = false;
+                } else {
+                    ray.origin = inter.position;
+                    ray.change_dir(bsdf_sample_result.wi);
+                    // change the medium
+                    ray.medium = if inter.normal.dot(ray.direction) > 0.0 {
+                        inter.medium_interface.outside()
+                    } else {
+                        inter.medium_interface.inside()
+                    };
+                    ray.reset();
+                    specular_bounce = bsdf_sample_result.tp == BSDFType::Specular;
+                }
             }
             // Russian roulette
             if depth > 2 && sampler.borrow_mut().next_1d() > 0.95 {
@@ -20,12 +20,11 @@ impl Integrator for WhittedIntegrator {
                 break;
             }
             let mut its = its_opt.unwrap();
-            if let Some(l) = its.shape.as_ref().unwrap().get_light() {
+            if let Some(l) = its.shape.as_ref().unwrap().borrow().get_light() {
                 spectrum += beta * l.borrow().evaluate_emission(&its, -ray.direction);
             }
             compute_ray_differentials(&mut its, ray);
-            let shape = its.shape.as_ref().unwrap();
-            let bsdf = shape.material().unwrap().compute_bsdf(&its);
+            let bsdf = its.bsdf();
             let bsdf_sample_result = bsdf.sample(-ray.direction, sampler.borrow_mut().next_2d());
             match bsdf_sample_result.tp {
                 BSDFType::Specular => {
@@ -38,8 +37,8 @@ impl Integrator for WhittedIntegrator {
                 BSDFType::Diffuse => {
                     for light in &scene.infinite_lights {
                         let res = light.sample(&its, sampler.borrow_mut().next_2d());
-                        let mut shadow_ray = Ray::new(its.position, res.direction);
-                        if scene.ray_intersect(&mut shadow_ray).is_none() {
+                        let shadow_ray = Ray::new(its.position, res.direction);
+                        if !scene.occluded(&shadow_ray) {
                             let f = bsdf.f(-ray.direction, shadow_ray.direction);
                             let pdf = convert_pdf(&res, &its);
                             spectrum += beta * res.energy * f / pdf;
@@ -54,7 +53,7 @@ impl Integrator for WhittedIntegrator {
                     let mut res = light.sample(&its, sampler.borrow_mut().next_2d());
                     let mut shadow_ray = Ray::new(its.position, res.direction);
                     shadow_ray.t_max = res.distance;
-                    if scene.ray_intersect(&mut shadow_ray).is_none() {
+                    if !scene.occluded(&shadow_ray) {
                         let f = bsdf.f(-ray.direction, shadow_ray.direction);
                         res.pdf *= pdf_light;
                         let pdf = convert_pdf(&res, &its);
@@ -1,7 +1,9 @@
 use crate::core_layer::colorspace::SpectrumRGB;
-use crate::function_layer::{MediumInterface, Ray, Shape, V3f};
+use crate::function_layer::{Material, Medium, MediumInterface, Ray, Shape, BSDF, RR, V3f};
+use crate::function_layer::material::MaterialType;
 use cgmath::Vector2;
 use cgmath::{EuclideanSpace, InnerSpace, Point3, Zero};
+use std::cell::OnceCell;
 use std::rc::Rc;
 
 pub trait Interaction {
@@ -10,6 +12,8 @@ pub trait Interaction {
     }
     fn f(&self, wo: V3f, wi: V3f) -> SpectrumRGB;
     fn p(&self) -> Point3<f32>;
+    /// 从交点出发沿w方向的光线所处的介质
+    fn medium(&self, w: V3f) -> Option<Rc<dyn Medium>>;
 }
 
 pub struct SurfaceInteraction {
@@ -19,7 +23,14 @@ pub struct SurfaceInteraction {
     pub tangent: V3f,
     pub bitangent: V3f,
     pub tex_coord: Vector2<f32>,
-    pub shape: Option<Rc<dyn Shape>>,
+    // 指向光线起点的方向，由加速结构在填充交点之前设置
+    pub wo: V3f,
+    // 插值得到的顶点颜色，网格带有顶点颜色时才有值
+    pub vertex_color: Option<V3f>,
+    // 场景中被击中的形状，由加速结构在求交后设置
+    pub shape: Option<RR<dyn Shape>>,
+    // 逐图元的材质，优先于形状的材质
+    pub material: Option<Rc<dyn Material>>,
     pub medium_interface: MediumInterface,
 
     pub dp_du: V3f,
@@ -32,18 +43,27 @@ pub struct SurfaceInteraction {
 
     pub dp_dx: V3f,
     pub dp_dy: V3f,
+
+    // 每个交点只计算一次BSDF
+    bsdf: OnceCell<Box<dyn BSDF>>,
 }
 
 impl Interaction for SurfaceInteraction {
     fn f(&self, wo: V3f, wi: V3f) -> SpectrumRGB {
-        let material = self.shape.as_ref().unwrap().material();
-        let bsdf = material.unwrap().compute_bsdf(self);
-        bsdf.f(wo, wi)
+        self.bsdf().f(wo, wi)
     }
 
     fn p(&self) -> Point3<f32> {
         self.position
     }
+
+    fn medium(&self, w: V3f) -> Option<Rc<dyn Medium>> {
+        if self.normal.dot(w) > 0.0 {
+            self.medium_interface.outside()
+        } else {
+            self.medium_interface.inside()
+        }
+    }
 }
 
 impl Default for SurfaceInteraction {
@@ -55,7 +75,10 @@ impl Default for SurfaceInteraction {
             tangent: V3f::zero(),
             bitangent: V3f::zero(),
             tex_coord: Vector2::zero(),
+            wo: V3f::zero(),
+            vertex_color: None,
             shape: None,
+            material: None,
             medium_interface: Default::default(),
             dp_du: V3f::zero(),
             dp_dv: V3f::zero(),
@@ -65,10 +88,38 @@ impl Default for SurfaceInteraction {
             dv_dy: 0.0,
             dp_dx: V3f::zero(),
             dp_dy: V3f::zero(),
+            bsdf: OnceCell::new(),
         }
     }
 }
 
+impl SurfaceInteraction {
+    /// 交点处的BSDF，第一次调用时由形状的材质计算，应在计算光线微分之后调用
+    pub fn bsdf(&self) -> &dyn BSDF {
+        self.bsdf
+            .get_or_init(|| self.material().compute_bsdf(self))
+            .as_ref()
+    }
+
+    /// 直接指定交点处的BSDF，用于次表面散射的出射点
+    pub fn set_bsdf(&self, bsdf: Box<dyn BSDF>) {
+        let _ = self.bsdf.set(bsdf);
+    }
+
+    /// 交点处的材质，逐图元的材质优先于形状的材质
+    pub fn material(&self) -> Rc<dyn Material> {
+        match &self.material {
+            Some(material) => material.clone(),
+            None => self.shape.as_ref().unwrap().borrow().material().unwrap(),
+        }
+    }
+
+    /// 交点是否位于只划分介质的表面上
+    pub fn is_interface(&self) -> bool {
+        self.material().mat_type() == MaterialType::Interface
+    }
+}
+
 pub fn compute_ray_differentials(intersection: &mut SurfaceInteraction, ray: &Ray) {
     loop {
         if ray.differential.is_none() {
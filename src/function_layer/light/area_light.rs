@@ -16,6 +16,10 @@ pub struct AreaLight {
 
 impl AreaLight {
     pub fn from_json(json: &Value) -> Self {
+        // 隐式曲面和CSG无法在表面上均匀采样
+        if let Some(tp @ ("sdf" | "csg")) = json["shape"]["type"].as_str() {
+            panic!("Shape of type {} can not be used as an area light!", tp);
+        }
         let shape = construct_shape(&json["shape"]);
         let energy = SpectrumRGB::from_rgb(fetch_v3f(json, "energy", V3f::zero()));
         Self {
@@ -44,3 +44,21 @@ pub struct BSDFBase {
     pub(crate) tangent: V3f,
     pub(crate) bitangent: V3f,
 }
+
+/// 电介质界面的Fresnel反射率，cos_theta_i为负时光线从折射率为eta的一侧入射
+pub fn fr_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let (eta_i, eta_t, cos_i) = if cos_theta_i > 0.0 {
+        (1.0, eta, cos_theta_i)
+    } else {
+        (eta, 1.0, -cos_theta_i)
+    };
+    let sin_t = eta_i / eta_t * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+    let r_parl = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let r_perp = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
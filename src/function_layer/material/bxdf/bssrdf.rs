@@ -0,0 +1,204 @@
+use super::bsdf::{fr_dielectric, BSDFBase, BSDFSampleResult, BSDFType, BSDF};
+use super::warp::{square_to_cosine_hemisphere, square_to_cosine_hemisphere_pdf};
+use crate::core_layer::colorspace::SpectrumRGB;
+use crate::core_layer::constants::INV_PI;
+use crate::function_layer::{Material, Ray, Sampler, Scene, SurfaceInteraction, V3f, RR};
+use cgmath::{ElementWise, InnerSpace, Point3, Vector2};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+// 一条探测光线上最多记录的交点数
+const MAX_PROBE_HITS: usize = 16;
+
+/// 出射方向上的归一化Fresnel透射项Sw = (1 - Fr(cos)) / (c * pi)，
+/// c使其余弦加权的半球积分为1，因此反射率完全由BSSRDF的剖面决定
+pub struct NormalizedFresnelBSDF {
+    pub(crate) bsdf: BSDFBase,
+    pub(crate) eta: f32,
+    pub(crate) c: f32,
+}
+
+impl NormalizedFresnelBSDF {
+    /// 归一化系数c = 1 - 2 * ∫Fr(cos) * cos dcos
+    pub fn normalization(eta: f32) -> f32 {
+        let n = 256;
+        let moment: f32 = (0..n)
+            .map(|i| {
+                let cos = (i as f32 + 0.5) / n as f32;
+                fr_dielectric(cos, eta) * cos / n as f32
+            })
+            .sum();
+        1.0 - 2.0 * moment
+    }
+}
+
+impl BSDF for NormalizedFresnelBSDF {
+    fn f(&self, _wo: V3f, wi: V3f) -> SpectrumRGB {
+        let cos = self.to_local(wi)[1];
+        if cos <= 0.0 {
+            return SpectrumRGB::same(0.0);
+        }
+        SpectrumRGB::same((1.0 - fr_dielectric(cos, self.eta)) / self.c * INV_PI * cos)
+    }
+
+    fn sample(&self, _wo: V3f, sample: Vector2<f32>) -> BSDFSampleResult {
+        let wi = square_to_cosine_hemisphere(sample);
+        BSDFSampleResult {
+            weight: SpectrumRGB::same((1.0 - fr_dielectric(wi[1], self.eta)) / self.c),
+            wi: self.to_world(wi),
+            pdf: square_to_cosine_hemisphere_pdf(wi),
+            tp: BSDFType::Diffuse,
+        }
+    }
+
+    fn bsdf(&self) -> &BSDFBase {
+        &self.bsdf
+    }
+}
+
+/// Christensen-Burley归一化扩散剖面的可分离BSSRDF：
+/// S = (1 - Fr(cos_o)) * Sp(|po - pi|) * Sw(wi)，其中Fresnel透射由入射点的BSDF负责。
+/// 每个通道的剖面R(r) = A * (exp(-r / d) + exp(-r / 3d)) / (8 * pi * d * r)在平面上积分为A
+pub struct BurleyBSSRDF {
+    pub(crate) frame: BSDFBase,
+    pub(crate) position: Point3<f32>,
+    // 与入射点属于同一物体的判据
+    pub(crate) material: Rc<dyn Material>,
+    pub(crate) albedo: V3f,
+    pub(crate) d: V3f,
+    pub(crate) eta: f32,
+    pub(crate) c: f32,
+}
+
+impl BurleyBSSRDF {
+    fn sr(&self, r: f32) -> V3f {
+        let r = r.max(1e-6);
+        self.albedo.mul_element_wise(V3f::new(
+            profile(r, self.d.x),
+            profile(r, self.d.y),
+            profile(r, self.d.z),
+        ))
+    }
+
+    // 截断半径，两个指数波瓣在此之外的能量都小于0.1%
+    fn r_max(&self, channel: usize) -> f32 {
+        3.0 * self.d[channel] * 1000f32.ln()
+    }
+
+    // 按channel的剖面采样半径，1/4的概率采样exp(-r / d)，否则采样exp(-r / 3d)
+    fn sample_sr(&self, channel: usize, u: f32) -> f32 {
+        let d = self.d[channel];
+        if u < 0.25 {
+            -d * (1.0 - u * 4.0).max(1e-12).ln()
+        } else {
+            -3.0 * d * (1.0 - (u - 0.25) / 0.75).max(1e-12).ln()
+        }
+    }
+
+    // 单位面积上的pdf，等于albedo为1时的剖面
+    fn pdf_sr(&self, channel: usize, r: f32) -> f32 {
+        if r >= self.r_max(channel) {
+            return 0.0;
+        }
+        profile(r.max(1e-6), self.d[channel])
+    }
+
+    fn pdf_sp(&self, position: Point3<f32>, normal: V3f) -> f32 {
+        let BSDFBase {
+            normal: n,
+            tangent: t,
+            bitangent: b,
+        } = &self.frame;
+        let d = position - self.position;
+        let d_local = V3f::new(d.dot(*t), d.dot(*b), d.dot(*n));
+        let n_local = V3f::new(normal.dot(*t), normal.dot(*b), normal.dot(*n));
+        // 沿各投影轴看去的半径
+        let r_proj = [
+            (d_local.y * d_local.y + d_local.z * d_local.z).sqrt(),
+            (d_local.z * d_local.z + d_local.x * d_local.x).sqrt(),
+            (d_local.x * d_local.x + d_local.y * d_local.y).sqrt(),
+        ];
+        let axis_prob = [0.25, 0.25, 0.5];
+        let mut pdf = 0.0;
+        for axis in 0..3 {
+            for channel in 0..3 {
+                pdf += self.pdf_sr(channel, r_proj[axis]) * n_local[axis].abs() * axis_prob[axis]
+                    / 3.0;
+            }
+        }
+        pdf
+    }
+
+    /// 采样出射点：随机选择投影轴和通道，在垂直于投影轴的平面上按剖面采样一点，
+    /// 沿投影轴发射探测光线，在与入射点材质相同的交点中均匀选择一个。
+    /// 返回的出射点的BSDF已设置为Sw，权重为Sp / pdf
+    pub fn sample_sp(
+        &self,
+        scene: &Scene,
+        sampler: RR<dyn Sampler>,
+    ) -> Option<(SurfaceInteraction, SpectrumRGB)> {
+        let BSDFBase {
+            normal: n,
+            tangent: t,
+            bitangent: b,
+        } = self.frame.clone();
+        let u = sampler.borrow_mut().next_1d();
+        let (vx, vy, vz) = if u < 0.5 {
+            (t, b, n)
+        } else if u < 0.75 {
+            (b, n, t)
+        } else {
+            (n, t, b)
+        };
+        let channel = ((sampler.borrow_mut().next_1d() * 3.0) as usize).min(2);
+        let sample = sampler.borrow_mut().next_2d();
+        let r = self.sample_sr(channel, sample.x);
+        let r_max = self.r_max(channel);
+        if r >= r_max {
+            return None;
+        }
+        let phi = 2.0 * PI * sample.y;
+        let l = 2.0 * (r_max * r_max - r * r).sqrt();
+        let start = self.position + r * (vx * phi.cos() + vy * phi.sin()) - vz * (l * 0.5);
+
+        let mut hits = vec![];
+        let mut traveled = 0.0;
+        while hits.len() < MAX_PROBE_HITS && traveled < l {
+            let mut probe = Ray::new(start + vz * traveled, vz);
+            probe.t_max = l - traveled;
+            let Some(its) = scene.ray_intersect(&mut probe) else {
+                break;
+            };
+            traveled += probe.t_max;
+            if Rc::ptr_eq(&its.material(), &self.material) {
+                hits.push(its);
+            }
+        }
+        if hits.is_empty() {
+            return None;
+        }
+        let index =
+            ((sampler.borrow_mut().next_1d() * hits.len() as f32) as usize).min(hits.len() - 1);
+        let count = hits.len() as f32;
+        let its = hits.swap_remove(index);
+        let pdf = self.pdf_sp(its.position, its.normal) / count;
+        if pdf <= 0.0 {
+            return None;
+        }
+        let sp = self.sr((its.position - self.position).magnitude());
+        its.set_bsdf(Box::new(NormalizedFresnelBSDF {
+            bsdf: BSDFBase {
+                normal: its.normal,
+                tangent: its.tangent,
+                bitangent: its.bitangent,
+            },
+            eta: self.eta,
+            c: self.c,
+        }));
+        Some((its, SpectrumRGB::from_rgb(sp / pdf)))
+    }
+}
+
+fn profile(r: f32, d: f32) -> f32 {
+    ((-r / d).exp() + (-r / (3.0 * d)).exp()) / (8.0 * PI * d * r)
+}
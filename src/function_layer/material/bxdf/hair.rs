@@ -0,0 +1,369 @@
+use super::bsdf::{fr_dielectric, BSDFBase, BSDFSampleResult};
+use super::{BSDFType, BSDF};
+use crate::core_layer::colorspace::SpectrumRGB;
+use crate::function_layer::V3f;
+use cgmath::{ElementWise, Vector2, Zero};
+use std::f32::consts::PI;
+
+// 单独计算的散射阶数，更高阶的散射合并为一项
+const P_MAX: usize = 3;
+
+/// Chiang等人(2016)基于d'Eon和Marschner模型的发丝BSDF，与pbrt-v3的HairBSDF相同。
+/// 内部使用pbrt的坐标系：x轴沿发丝方向，z轴为曲线的几何法线
+pub struct HairBSDF {
+    bsdf: BSDFBase,
+    // 光线在发丝截面上的入射偏移，-1到1
+    h: f32,
+    gamma_o: f32,
+    eta: f32,
+    sigma_a: V3f,
+    // 各阶纵向散射的方差
+    v: [f32; P_MAX + 1],
+    // 方位角散射的logistic分布参数
+    s: f32,
+    // 毛鳞片倾角alpha对应的sin(2^k alpha)和cos(2^k alpha)
+    sin_2k_alpha: [f32; 3],
+    cos_2k_alpha: [f32; 3],
+}
+
+impl HairBSDF {
+    pub fn new(
+        bsdf: BSDFBase,
+        h: f32,
+        eta: f32,
+        sigma_a: V3f,
+        beta_m: f32,
+        beta_n: f32,
+        alpha: f32,
+    ) -> Self {
+        let mut v = [0.0; P_MAX + 1];
+        v[0] = sqr(0.726 * beta_m + 0.812 * sqr(beta_m) + 3.7 * beta_m.powi(20));
+        v[1] = 0.25 * v[0];
+        v[2] = 4.0 * v[0];
+        for p in 3..=P_MAX {
+            v[p] = v[2];
+        }
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * sqr(beta_n) + 5.372 * beta_n.powi(22));
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = alpha.to_radians().sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0 - sqr(sin_2k_alpha[0]));
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = sqr(cos_2k_alpha[i - 1]) - sqr(sin_2k_alpha[i - 1]);
+        }
+        Self {
+            bsdf,
+            h,
+            gamma_o: safe_asin(h),
+            eta,
+            sigma_a,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// 由期望的漫反射颜色反推吸收系数
+    pub fn sigma_a_from_reflectance(c: V3f, beta_n: f32) -> V3f {
+        let d = 5.969 - 0.215 * beta_n + 2.532 * sqr(beta_n) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        c.map(|c| sqr(c.max(1e-4).ln() / d))
+    }
+
+    /// 由真黑素和褐黑素的浓度得到吸收系数
+    pub fn sigma_a_from_concentration(eumelanin: f32, pheomelanin: f32) -> V3f {
+        let eumelanin_sigma_a = V3f::new(0.419, 0.697, 1.37);
+        let pheomelanin_sigma_a = V3f::new(0.187, 0.4, 1.05);
+        eumelanin_sigma_a * eumelanin + pheomelanin_sigma_a * pheomelanin
+    }
+
+    // 本仓库的局部坐标系(x切线, y法线, z副切线)与pbrt坐标系之间的转换
+    fn to_hair(&self, w: V3f) -> V3f {
+        let l = self.to_local(w);
+        V3f::new(l.x, l.z, -l.y)
+    }
+
+    fn hair_to_world(&self, w: V3f) -> V3f {
+        self.to_world(V3f::new(w.x, -w.z, w.y))
+    }
+
+    // 第p阶散射时因毛鳞片倾斜而旋转后的出射方向的sin和cos
+    fn tilt(&self, p: usize, sin_theta_o: f32, cos_theta_o: f32) -> (f32, f32) {
+        let (sin_a, cos_a) = (&self.sin_2k_alpha, &self.cos_2k_alpha);
+        let (sin_op, cos_op) = match p {
+            0 => (
+                sin_theta_o * cos_a[1] - cos_theta_o * sin_a[1],
+                cos_theta_o * cos_a[1] + sin_theta_o * sin_a[1],
+            ),
+            1 => (
+                sin_theta_o * cos_a[0] + cos_theta_o * sin_a[0],
+                cos_theta_o * cos_a[0] - sin_theta_o * sin_a[0],
+            ),
+            2 => (
+                sin_theta_o * cos_a[2] + cos_theta_o * sin_a[2],
+                cos_theta_o * cos_a[2] - sin_theta_o * sin_a[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_op, cos_op.abs())
+    }
+
+    // 各阶散射的衰减系数，同时返回折射光线在截面上的角度gamma_t
+    fn attenuation(&self, sin_theta_o: f32, cos_theta_o: f32) -> ([V3f; P_MAX + 1], f32) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sqr(sin_theta_t));
+        let etap = (sqr(self.eta) - sqr(sin_theta_o)).sqrt() / cos_theta_o;
+        let sin_gamma_t = self.h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - sqr(sin_gamma_t));
+        let gamma_t = safe_asin(sin_gamma_t);
+        // 光线在发丝内部穿过一次的透射率
+        let t = (-self.sigma_a * (2.0 * cos_gamma_t / cos_theta_t)).map(f32::exp);
+
+        let cos_gamma_o = safe_sqrt(1.0 - sqr(self.h));
+        let f = fr_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let mut ap = [V3f::zero(); P_MAX + 1];
+        ap[0] = V3f::from([f; 3]);
+        ap[1] = t * sqr(1.0 - f);
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1].mul_element_wise(t) * f;
+        }
+        // 更高阶的散射按几何级数求和
+        let ones = V3f::from([1.0; 3]);
+        ap[P_MAX] = (ap[P_MAX - 1].mul_element_wise(t) * f).div_element_wise(ones - t * f);
+        (ap, gamma_t)
+    }
+
+    // 按各阶衰减的亮度选择散射阶数的概率
+    fn ap_pdf(ap: &[V3f; P_MAX + 1]) -> [f32; P_MAX + 1] {
+        let y = ap.map(|a| SpectrumRGB::from_rgb(a).luminance());
+        let sum: f32 = y.iter().sum();
+        if sum > 0.0 {
+            y.map(|y| y / sum)
+        } else {
+            [1.0 / (P_MAX + 1) as f32; P_MAX + 1]
+        }
+    }
+
+    fn f_local(&self, wo: V3f, wi: V3f) -> V3f {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+        let mut f = V3f::zero();
+        for (p, a) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            f += a
+                * (mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                    * np(phi, p, self.s, self.gamma_o, gamma_t));
+        }
+        f + ap[P_MAX]
+            * (mp(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                self.v[P_MAX],
+            ) / (2.0 * PI))
+    }
+
+    fn pdf_local(&self, wo: V3f, wi: V3f) -> f32 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, cos_theta_o);
+        let ap_pdf = Self::ap_pdf(&ap);
+        let phi = phi_i - phi_o;
+        let mut pdf = 0.0;
+        for (p, a) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                * a
+                * np(phi, p, self.s, self.gamma_o, gamma_t);
+        }
+        pdf + mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * ap_pdf[P_MAX]
+            / (2.0 * PI)
+    }
+}
+
+impl BSDF for HairBSDF {
+    fn f(&self, wo: V3f, wi: V3f) -> SpectrumRGB {
+        // pbrt中的f除以了|cos(theta_i)|，这里的f包含余弦项，因此不需要再除
+        SpectrumRGB::from_rgb(self.f_local(self.to_hair(wo), self.to_hair(wi)))
+    }
+
+    fn sample(&self, wo: V3f, sample: Vector2<f32>) -> BSDFSampleResult {
+        let wo_hair = self.to_hair(wo);
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo_hair);
+        // 将两个随机数拆分为四个
+        let (mut u00, u01) = demux_float(sample.x);
+        let (u10, u11) = demux_float(sample.y);
+
+        // 选择散射阶数
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, cos_theta_o);
+        let ap_pdf = Self::ap_pdf(&ap);
+        let mut p = 0;
+        while p < P_MAX {
+            if u00 < ap_pdf[p] {
+                break;
+            }
+            u00 -= ap_pdf[p];
+            p += 1;
+        }
+
+        // 采样纵向角
+        let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u10 = u10.max(1e-5);
+        let cos_theta = 1.0 + self.v[p] * (u10 + (1.0 - u10) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - sqr(cos_theta));
+        let cos_phi = (2.0 * PI * u11).cos();
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+
+        // 采样方位角
+        let dphi = if p < P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(u01, self.s, -PI, PI)
+        } else {
+            2.0 * PI * u01
+        };
+        let phi_i = phi_o + dphi;
+        let wi_hair = V3f::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+        let pdf = self.pdf_local(wo_hair, wi_hair);
+        let weight = if pdf > 0.0 {
+            SpectrumRGB::from_rgb(self.f_local(wo_hair, wi_hair) / pdf)
+        } else {
+            SpectrumRGB::same(0.0)
+        };
+        BSDFSampleResult {
+            weight,
+            wi: self.hair_to_world(wi_hair),
+            pdf,
+            tp: BSDFType::Diffuse,
+        }
+    }
+
+    fn bsdf(&self) -> &BSDFBase {
+        &self.bsdf
+    }
+}
+
+fn sqr(x: f32) -> f32 {
+    x * x
+}
+
+fn safe_sqrt(x: f32) -> f32 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f32) -> f32 {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+// pbrt坐标系下方向的sin(theta)、cos(theta)和phi
+fn angles(w: V3f) -> (f32, f32, f32) {
+    let sin_theta = w.x.clamp(-1.0, 1.0);
+    (sin_theta, safe_sqrt(1.0 - sqr(sin_theta)), w.z.atan2(w.y))
+}
+
+// 第一类修正贝塞尔函数I0
+fn i0(x: f32) -> f32 {
+    let mut val = 0.0;
+    let mut x2i = 1.0;
+    let mut ifact: i64 = 1;
+    let mut i4 = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= i;
+        }
+        val += x2i / (i4 * sqr(ifact as f32));
+        x2i *= x * x;
+        i4 *= 4.0;
+    }
+    val
+}
+
+fn log_i0(x: f32) -> f32 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+// 纵向散射函数
+fn mp(cos_theta_i: f32, cos_theta_o: f32, sin_theta_i: f32, sin_theta_o: f32, v: f32) -> f32 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_i0(a) - b - 1.0 / v + std::f32::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// 第p阶散射的出射方位角
+fn phi(p: usize, gamma_o: f32, gamma_t: f32) -> f32 {
+    2.0 * p as f32 * gamma_t - 2.0 * gamma_o + p as f32 * PI
+}
+
+fn logistic(x: f32, s: f32) -> f32 {
+    let x = x.abs();
+    (-x / s).exp() / (s * sqr(1.0 + (-x / s).exp()))
+}
+
+fn logistic_cdf(x: f32, s: f32) -> f32 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f32, s: f32, a: f32, b: f32) -> f32 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f32, s: f32, a: f32, b: f32) -> f32 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// 方位角散射函数
+fn np(phi_rel: f32, p: usize, s: f32, gamma_o: f32, gamma_t: f32) -> f32 {
+    let mut dphi = phi_rel - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+// 取出奇数位和偶数位，将一个[0, 1)的随机数拆分为两个
+fn demux_float(f: f32) -> (f32, f32) {
+    let v = (f as f64 * (1u64 << 32) as f64) as u64;
+    let bits = [compact_1_by_1(v as u32), compact_1_by_1((v >> 1) as u32)];
+    (
+        bits[0] as f32 / (1 << 16) as f32,
+        bits[1] as f32 / (1 << 16) as f32,
+    )
+}
+
+fn compact_1_by_1(x: u32) -> u32 {
+    let mut x = x & 0x5555_5555;
+    x = (x ^ (x >> 1)) & 0x3333_3333;
+    x = (x ^ (x >> 2)) & 0x0f0f_0f0f;
+    x = (x ^ (x >> 4)) & 0x00ff_00ff;
+    x = (x ^ (x >> 8)) & 0x0000_ffff;
+    x
+}
@@ -0,0 +1,49 @@
+use super::bsdf::{BSDFBase, BSDFSampleResult, BSDF};
+use crate::core_layer::colorspace::SpectrumRGB;
+use crate::function_layer::V3f;
+use cgmath::Vector2;
+
+/// 两个BSDF的线性混合，weight为第二个BSDF所占的比例
+pub struct MixBSDF {
+    bsdf: BSDFBase,
+    first: Box<dyn BSDF>,
+    second: Box<dyn BSDF>,
+    weight: f32,
+}
+
+impl MixBSDF {
+    pub fn new(bsdf: BSDFBase, first: Box<dyn BSDF>, second: Box<dyn BSDF>, weight: f32) -> Self {
+        Self {
+            bsdf,
+            first,
+            second,
+            weight,
+        }
+    }
+}
+
+impl BSDF for MixBSDF {
+    fn f(&self, wo: V3f, wi: V3f) -> SpectrumRGB {
+        self.first.f(wo, wi) * (1.0 - self.weight) + self.second.f(wo, wi) * self.weight
+    }
+
+    fn sample(&self, wo: V3f, sample: Vector2<f32>) -> BSDFSampleResult {
+        // 按权重随机选择其中一个BSDF进行采样，并将样本重新映射到[0, 1)
+        let (chosen, prob, remapped) = if sample.x < self.weight {
+            (&self.second, self.weight, sample.x / self.weight)
+        } else {
+            (
+                &self.first,
+                1.0 - self.weight,
+                (sample.x - self.weight) / (1.0 - self.weight),
+            )
+        };
+        let mut result = chosen.sample(wo, Vector2::new(remapped, sample.y));
+        result.pdf *= prob;
+        result
+    }
+
+    fn bsdf(&self) -> &BSDFBase {
+        &self.bsdf
+    }
+}
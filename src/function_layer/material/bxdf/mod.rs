@@ -1,5 +1,8 @@
 pub mod bsdf;
+pub mod bssrdf;
+pub mod hair;
 pub mod lambert;
+pub mod mix;
 pub mod oren_nayar;
 pub mod phong;
 pub mod rough_conductor;
@@ -17,7 +17,7 @@ pub struct ConductorMaterial {
     ndf: Rc<dyn NDF>,
     eta: V3f,
     k: V3f,
-    roughness: Vector2<f32>,
+    roughness: Rc<dyn Texture<Vector2<f32>>>,
 }
 
 impl ConductorMaterial {
@@ -57,7 +57,7 @@ impl Material for ConductorMaterial {
         Box::new(RoughConductorBSDF::new(
             bsdf,
             s,
-            self.roughness,
+            self.roughness.evaluate(intersection),
             self.eta,
             self.k,
             Some(self.ndf.clone()),
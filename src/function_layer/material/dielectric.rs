@@ -6,14 +6,14 @@ use cgmath::Vector2;
 use serde_json::Value;
 use std::rc::Rc;
 
-use super::material::{fetch_albedo, fetch_ndf, fetch_normal_map, fetch_roughness};
+use super::material::{fetch_albedo, fetch_float, fetch_ndf, fetch_normal_map, fetch_roughness};
 
 pub struct DielectricMaterial {
     normal_map: Option<Rc<NormalTexture>>,
     albedo: Rc<dyn Texture<SpectrumRGB>>,
     ndf: Rc<dyn NDF>,
-    eta: f32,
-    roughness: Vector2<f32>,
+    eta: Rc<dyn Texture<f32>>,
+    roughness: Rc<dyn Texture<Vector2<f32>>>,
 }
 
 impl DielectricMaterial {
@@ -22,7 +22,7 @@ impl DielectricMaterial {
         let normal_map = fetch_normal_map(json);
         let roughness = fetch_roughness(json);
         let ndf: Rc<dyn NDF> = fetch_ndf(json);
-        let eta = json["eta"].as_f64().unwrap() as f32;
+        let eta = fetch_float(json, "eta", 1.5);
         Self {
             normal_map,
             albedo,
@@ -49,8 +49,8 @@ impl Material for DielectricMaterial {
         Box::new(RoughDielectricBSDF::new(
             bsdf,
             s,
-            self.roughness,
-            self.eta,
+            self.roughness.evaluate(intersection),
+            self.eta.evaluate(intersection),
             Some(self.ndf.clone()),
         ))
     }
@@ -0,0 +1,83 @@
+use super::bxdf::{bsdf::BSDFBase, hair::HairBSDF, BSDF};
+use super::material::fetch_spectrum;
+use crate::core_layer::colorspace::SpectrumRGB;
+use crate::function_layer::texture::normal_texture::NormalTexture;
+use crate::function_layer::{fetch_v3f, SurfaceInteraction, Texture, V3f};
+use cgmath::Zero;
+use serde_json::Value;
+use std::rc::Rc;
+
+use super::Material;
+
+/// 发丝的吸收系数，可以直接给出、由颜色反推或由黑色素浓度计算
+enum HairAbsorption {
+    SigmaA(V3f),
+    Color(Rc<dyn Texture<SpectrumRGB>>),
+    Concentration(f32, f32),
+}
+
+pub struct HairMaterial {
+    absorption: HairAbsorption,
+    eta: f32,
+    // 纵向和方位角粗糙度
+    beta_m: f32,
+    beta_n: f32,
+    // 毛鳞片的倾角(角度制)
+    alpha: f32,
+}
+
+impl HairMaterial {
+    pub fn from_json(json: &Value) -> Self {
+        let absorption = if !json["sigmaA"].is_null() {
+            HairAbsorption::SigmaA(fetch_v3f(json, "sigmaA", V3f::zero()))
+        } else if !json["color"].is_null() {
+            HairAbsorption::Color(fetch_spectrum(json, "color"))
+        } else {
+            let eumelanin = json["eumelanin"].as_f64().unwrap_or(1.3) as f32;
+            let pheomelanin = json["pheomelanin"].as_f64().unwrap_or(0.0) as f32;
+            HairAbsorption::Concentration(eumelanin, pheomelanin)
+        };
+        Self {
+            absorption,
+            eta: json["eta"].as_f64().unwrap_or(1.55) as f32,
+            beta_m: json["betaM"].as_f64().unwrap_or(0.3) as f32,
+            beta_n: json["betaN"].as_f64().unwrap_or(0.3) as f32,
+            alpha: json["alpha"].as_f64().unwrap_or(2.0) as f32,
+        }
+    }
+}
+
+impl Material for HairMaterial {
+    fn normal_map(&self) -> Option<Rc<NormalTexture>> {
+        None
+    }
+
+    fn compute_bsdf(&self, intersection: &SurfaceInteraction) -> Box<dyn BSDF> {
+        let (normal, tangent, bitangent) = self.compute_shading_geometry(intersection);
+        let sigma_a = match &self.absorption {
+            HairAbsorption::SigmaA(sigma_a) => *sigma_a,
+            HairAbsorption::Color(color) => {
+                HairBSDF::sigma_a_from_reflectance(color.evaluate(intersection).rgb(), self.beta_n)
+            }
+            HairAbsorption::Concentration(eumelanin, pheomelanin) => {
+                HairBSDF::sigma_a_from_concentration(*eumelanin, *pheomelanin)
+            }
+        };
+        // 曲线的纹理坐标v为光线在发丝截面上的位置
+        let h = -1.0 + 2.0 * intersection.tex_coord.y;
+        let bsdf = BSDFBase {
+            normal,
+            tangent,
+            bitangent,
+        };
+        Box::new(HairBSDF::new(
+            bsdf,
+            h,
+            self.eta,
+            sigma_a,
+            self.beta_m,
+            self.beta_n,
+            self.alpha,
+        ))
+    }
+}
@@ -0,0 +1,30 @@
+use super::material::MaterialType;
+use crate::function_layer::material::bxdf::bsdf::BSDFBase;
+use crate::function_layer::material::bxdf::transparent::TransparentBSDF;
+use crate::function_layer::texture::normal_texture::NormalTexture;
+use crate::function_layer::{Material, SurfaceInteraction, BSDF};
+use std::rc::Rc;
+
+/// 只用于划分介质的表面，两侧折射率相同，光线和阴影光线都直接穿过
+pub struct InterfaceMaterial;
+
+impl Material for InterfaceMaterial {
+    fn normal_map(&self) -> Option<Rc<NormalTexture>> {
+        None
+    }
+
+    fn compute_bsdf(&self, intersection: &SurfaceInteraction) -> Box<dyn BSDF> {
+        Box::new(TransparentBSDF {
+            bsdf: BSDFBase {
+                normal: intersection.normal,
+                tangent: intersection.tangent,
+                bitangent: intersection.bitangent,
+            },
+            ior: 1.0,
+        })
+    }
+
+    fn mat_type(&self) -> MaterialType {
+        MaterialType::Interface
+    }
+}
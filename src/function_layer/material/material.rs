@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use cgmath::{InnerSpace, Vector2, Zero};
@@ -6,14 +8,18 @@ use serde_json::Value;
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::function_layer::material::black_hole::BlackHole;
 use crate::function_layer::material::conductor::ConductorMaterial;
+use crate::function_layer::material::interface::InterfaceMaterial;
+use crate::function_layer::material::subsurface::SubsurfaceMaterial;
 use crate::function_layer::material::transparent::TransparentMaterial;
 use crate::function_layer::texture::constant_texture::ConstantTexture;
 use crate::function_layer::texture::normal_texture::NormalTexture;
+use crate::function_layer::texture::texture::{fetch_texture, TextureValue};
 use crate::function_layer::{construct_texture, fetch_v3f, SurfaceInteraction, Texture, V3f, NDF};
 
 use super::ndf::{beckmann::BeckmannDistribution, ggx::GGXDistribution};
 use super::{
-    bxdf::bsdf::BSDF, dielectric::DielectricMaterial, matte::MatteMaterial, mirror::MirrorMaterial,
+    bxdf::bsdf::BSDF, bxdf::bssrdf::BurleyBSSRDF, dielectric::DielectricMaterial,
+    hair::HairMaterial, matte::MatteMaterial, mirror::MirrorMaterial, mix::MixMaterial,
     oren_nayar::OrenNayarMaterial, phong::PhongMaterial,
 };
 
@@ -29,16 +35,28 @@ pub trait Material {
                 intersection.bitangent,
             ),
             Some(normal_map) => {
+                // 切线空间的x轴为切线(纹理坐标u增大的方向)，y轴为纹理坐标v增大的方向
+                let tangent = intersection.tangent;
+                let bitangent = if intersection.dp_dv.dot(intersection.bitangent) > 0.0 {
+                    intersection.bitangent
+                } else {
+                    -intersection.bitangent
+                };
                 let local_normal = normal_map.evaluate(intersection);
-                let normal = (local_normal.x * intersection.tangent
-                    + local_normal.y * intersection.bitangent
+                let normal = (local_normal.x * tangent
+                    + local_normal.y * bitangent
                     + local_normal.z * intersection.normal)
                     .normalize();
-                let tangent = intersection.tangent;
+                // 切线与扰动后的法线重新正交化
+                let tangent = (tangent - normal * normal.dot(tangent)).normalize();
                 (normal, tangent, tangent.cross(normal).normalize())
             }
         }
     }
+    /// 带次表面散射的材质返回入射点处的BSSRDF，光线折射进入表面时由积分器采样出射点
+    fn compute_bssrdf(&self, _intersection: &SurfaceInteraction) -> Option<BurleyBSSRDF> {
+        None
+    }
     fn mat_type(&self) -> MaterialType {
         MaterialType::Others
     }
@@ -46,6 +64,8 @@ pub trait Material {
 #[derive(Eq, PartialEq)]
 pub enum MaterialType {
     BlackHole,
+    // 折射率匹配的介质边界，积分器直接穿过而不计入弹射
+    Interface,
     Others,
 }
 pub fn fetch_normal_map(json: &Value) -> Option<Rc<NormalTexture>> {
@@ -60,17 +80,18 @@ pub fn fetch_albedo(json: &Value) -> Rc<dyn Texture<SpectrumRGB>> {
     fetch_spectrum(json, "albedo")
 }
 
-pub fn fetch_roughness(json: &Value) -> Vector2<f32> {
+pub fn fetch_roughness(json: &Value) -> Rc<dyn Texture<Vector2<f32>>> {
     let rn = &json["roughness"];
-    let roughness = if rn.is_number() {
-        let r = rn.as_f64().unwrap() as f32;
-        Vector2::new(r, r)
-    } else if rn.is_array() {
-        Vector2::from(serde_json::from_value::<[f32; 2]>(rn.clone()).unwrap())
-    } else {
-        panic!("Error in roughness format!");
-    };
-    roughness
+    if rn.is_object() || rn.is_string() {
+        return fetch_texture(json, "roughness", Vector2::zero());
+    }
+    // 标量或二维数组形式的常量粗糙度
+    let roughness = Vector2::<f32>::from_json(rn).expect("Error in roughness format!");
+    Rc::new(ConstantTexture::new(&roughness))
+}
+
+pub fn fetch_float(json: &Value, field: &str, dft: f32) -> Rc<dyn Texture<f32>> {
+    fetch_texture(json, field, dft)
 }
 
 pub fn fetch_ndf(json: &Value) -> Rc<dyn NDF> {
@@ -82,7 +103,7 @@ pub fn fetch_ndf(json: &Value) -> Rc<dyn NDF> {
 }
 
 pub fn fetch_spectrum(json: &Value, field: &str) -> Rc<dyn Texture<SpectrumRGB>> {
-    if json[field].is_object() {
+    if json[field].is_object() || json[field].is_string() {
         construct_texture::<SpectrumRGB>(&json[field])
     } else {
         let s = fetch_v3f(json, field, V3f::zero());
@@ -90,7 +111,42 @@ pub fn fetch_spectrum(json: &Value, field: &str) -> Rc<dyn Texture<SpectrumRGB>>
     }
 }
 
+thread_local! {
+    // 场景中"materials"字段定义的具名材质，第一次被引用时构造，之后共享同一个实例
+    static MATERIAL_LIBRARY: RefCell<HashMap<String, Value>> = RefCell::new(HashMap::new());
+    static MATERIAL_INSTANCES: RefCell<HashMap<String, Rc<dyn Material>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn register_materials(json: &Value) {
+    let Some(materials) = json.as_object() else {
+        return;
+    };
+    MATERIAL_LIBRARY.with(|lib| {
+        let mut lib = lib.borrow_mut();
+        for (name, mat) in materials {
+            lib.insert(name.clone(), mat.clone());
+        }
+    });
+}
+
+fn fetch_named_material(name: &str) -> Rc<dyn Material> {
+    if let Some(mat) = MATERIAL_INSTANCES.with(|ins| ins.borrow().get(name).cloned()) {
+        return mat;
+    }
+    let json = MATERIAL_LIBRARY
+        .with(|lib| lib.borrow().get(name).cloned())
+        .unwrap_or_else(|| panic!("Undefined material: {}!", name));
+    let mat = construct_material(&json);
+    MATERIAL_INSTANCES.with(|ins| ins.borrow_mut().insert(name.to_string(), mat.clone()));
+    mat
+}
+
+/// 根据json构造材质，json为字符串时引用场景中的具名材质
 pub fn construct_material(json: &Value) -> Rc<dyn Material> {
+    if let Some(name) = json.as_str() {
+        return fetch_named_material(name);
+    }
     match json["type"].as_str().expect("No material type annotation!") {
         "matte" => Rc::new(MatteMaterial::from_json(json)),
         "mirror" => Rc::new(MirrorMaterial::from_json(json)),
@@ -99,7 +155,11 @@ pub fn construct_material(json: &Value) -> Rc<dyn Material> {
         "dielectric" => Rc::new(DielectricMaterial::from_json(json)),
         "conductor" => Rc::new(ConductorMaterial::from_json(json)),
         "transparent" => Rc::new(TransparentMaterial::from_json(json)),
+        "mix" => Rc::new(MixMaterial::from_json(json)),
+        "hair" => Rc::new(HairMaterial::from_json(json)),
+        "subsurface" => Rc::new(SubsurfaceMaterial::from_json(json)),
         "black-hole" => Rc::new(BlackHole {}),
+        "interface" => Rc::new(InterfaceMaterial {}),
         tp => panic!("Invalid type: {}", tp),
     }
 }
@@ -28,8 +28,8 @@ impl MatteMaterial {
 
     pub fn from_json(json: &Value) -> Self {
         let normal_map = fetch_normal_map(json);
-        let albedo = if json["albedo"].is_object() {
-            construct_texture::<SpectrumRGB>(json)
+        let albedo = if json["albedo"].is_object() || json["albedo"].is_string() {
+            construct_texture::<SpectrumRGB>(&json["albedo"])
         } else if json["albedo"].is_array() {
             let rgb = fetch_v3f(json, "albedo", V3f::zero());
             let s = SpectrumRGB::from_rgb(rgb);
@@ -0,0 +1,62 @@
+use super::bxdf::{bsdf::BSDFBase, mix::MixBSDF};
+use super::material::{construct_material, fetch_float, fetch_normal_map};
+use crate::function_layer::texture::normal_texture::NormalTexture;
+use crate::function_layer::{Material, SurfaceInteraction, Texture, BSDF};
+use serde_json::Value;
+use std::rc::Rc;
+
+pub struct MixMaterial {
+    normal_map: Option<Rc<NormalTexture>>,
+    materials: [Rc<dyn Material>; 2],
+    // 第二个材质所占的比例
+    weight: Rc<dyn Texture<f32>>,
+}
+
+impl MixMaterial {
+    pub fn from_json(json: &Value) -> Self {
+        let normal_map = fetch_normal_map(json);
+        let materials = json["materials"]
+            .as_array()
+            .filter(|arr| arr.len() == 2)
+            .expect("Mix material needs exactly two materials!");
+        let materials = [
+            construct_material(&materials[0]),
+            construct_material(&materials[1]),
+        ];
+        let weight = fetch_float(json, "weight", 0.5);
+        Self {
+            normal_map,
+            materials,
+            weight,
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn normal_map(&self) -> Option<Rc<NormalTexture>> {
+        self.normal_map.clone()
+    }
+
+    fn compute_bsdf(&self, intersection: &SurfaceInteraction) -> Box<dyn BSDF> {
+        let w = self.weight.evaluate(intersection).clamp(0.0, 1.0);
+        // 权重退化时只需计算其中一个BSDF
+        if w == 0.0 {
+            return self.materials[0].compute_bsdf(intersection);
+        }
+        if w == 1.0 {
+            return self.materials[1].compute_bsdf(intersection);
+        }
+        let (normal, tangent, bitangent) = self.compute_shading_geometry(intersection);
+        let bsdf = BSDFBase {
+            normal,
+            tangent,
+            bitangent,
+        };
+        Box::new(MixBSDF::new(
+            bsdf,
+            self.materials[0].compute_bsdf(intersection),
+            self.materials[1].compute_bsdf(intersection),
+            w,
+        ))
+    }
+}
@@ -2,12 +2,16 @@ mod black_hole;
 pub mod bxdf;
 mod conductor;
 mod dielectric;
+mod hair;
+mod interface;
 pub mod material;
 pub mod matte;
 mod mirror;
+mod mix;
 mod ndf;
 mod oren_nayar;
 mod phong;
+mod subsurface;
 mod transparent;
 
 pub use bxdf::BSDF;
@@ -2,7 +2,7 @@ use super::bxdf::BSDF;
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::function_layer::material::bxdf::bsdf::BSDFBase;
 use crate::function_layer::material::bxdf::oren_nayar::OrenNayarBSDF;
-use crate::function_layer::material::material::{fetch_albedo, fetch_normal_map};
+use crate::function_layer::material::material::{fetch_albedo, fetch_float, fetch_normal_map};
 use crate::function_layer::texture::constant_texture::ConstantTexture;
 use crate::function_layer::texture::normal_texture::NormalTexture;
 use crate::function_layer::{Material, SurfaceInteraction, Texture};
@@ -12,14 +12,14 @@ use std::rc::Rc;
 pub struct OrenNayarMaterial {
     normal_map: Option<Rc<NormalTexture>>,
     albedo: Rc<dyn Texture<SpectrumRGB>>,
-    roughness: f32,
+    roughness: Rc<dyn Texture<f32>>,
 }
 
 impl OrenNayarMaterial {
     pub fn from_json(json: &Value) -> Self {
         let normal_map = fetch_normal_map(json);
         let albedo = fetch_albedo(json);
-        let roughness = json["roughness"].as_f64().unwrap() as f32;
+        let roughness = fetch_float(json, "roughness", 0.0);
         Self {
             normal_map,
             albedo,
@@ -33,7 +33,7 @@ impl Default for OrenNayarMaterial {
         Self {
             normal_map: None,
             albedo: Rc::new(ConstantTexture::new(&SpectrumRGB::same(0.5))),
-            roughness: 0.0,
+            roughness: Rc::new(ConstantTexture::new(&0.0)),
         }
     }
 }
@@ -51,6 +51,6 @@ impl Material for OrenNayarMaterial {
             tangent,
             bitangent,
         };
-        Box::new(OrenNayarBSDF::new(s, self.roughness, bsdf))
+        Box::new(OrenNayarBSDF::new(s, self.roughness.evaluate(intersection), bsdf))
     }
 }
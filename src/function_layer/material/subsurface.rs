@@ -0,0 +1,105 @@
+use super::bxdf::bsdf::BSDFBase;
+use super::bxdf::bssrdf::{BurleyBSSRDF, NormalizedFresnelBSDF};
+use super::bxdf::transparent::TransparentBSDF;
+use super::material::fetch_normal_map;
+use crate::function_layer::medium::medium::fetch_coefficients;
+use crate::function_layer::texture::normal_texture::NormalTexture;
+use crate::function_layer::{Material, SurfaceInteraction, V3f, BSDF};
+use serde_json::Value;
+use std::rc::Rc;
+
+/// 使用Christensen-Burley扩散剖面的次表面散射材质。
+/// 散射参数与介质相同，可以是medium给出的预设名称、sigma_a和sigma_s或albedo和meanFreePath，
+/// 按场景单位用scale缩放(预设参数的单位为mm^-1)
+pub struct SubsurfaceMaterial {
+    normal_map: Option<Rc<NormalTexture>>,
+    // 多次散射的表面反照率A
+    albedo: V3f,
+    // 剖面的形状参数d
+    d: V3f,
+    eta: f32,
+    c: f32,
+}
+
+impl SubsurfaceMaterial {
+    pub fn from_json(json: &Value) -> Self {
+        let normal_map = fetch_normal_map(json);
+        let (sig_a, sig_s) = fetch_coefficients(json);
+        let (sig_a, sig_s) = (sig_a.rgb(), sig_s.rgb());
+        let mut albedo = V3f::new(0.0, 0.0, 0.0);
+        let mut d = V3f::new(0.0, 0.0, 0.0);
+        for i in 0..3 {
+            let sig_t = sig_a[i] + sig_s[i];
+            if sig_t <= 0.0 {
+                panic!("Subsurface material needs a positive extinction coefficient!");
+            }
+            albedo[i] = surface_albedo(sig_s[i] / sig_t);
+            // 以平均自由程为单位的拟合缩放，见Christensen和Burley的"Approximate Reflectance Profiles"
+            let s = 1.85 - albedo[i] + 7.0 * (albedo[i] - 0.8).abs().powi(3);
+            d[i] = 1.0 / (sig_t * s);
+        }
+        let eta = json["ior"].as_f64().unwrap_or(1.33) as f32;
+        Self {
+            normal_map,
+            albedo,
+            d,
+            eta,
+            c: NormalizedFresnelBSDF::normalization(eta),
+        }
+    }
+}
+
+// 单次散射反照率与多次散射表面反照率之间的关系
+// alpha = 1 - (4.09712 + 4.20863A - sqrt(9.59217 + 41.6808A + 17.7126A^2))^2，
+// 在[0, 1]上单调递增，二分求逆
+fn surface_albedo(alpha: f32) -> f32 {
+    let f = |a: f32| {
+        let t = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+        1.0 - t * t
+    };
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..32 {
+        let mid = (lo + hi) / 2.0;
+        if f(mid) < alpha {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+impl Material for SubsurfaceMaterial {
+    fn normal_map(&self) -> Option<Rc<NormalTexture>> {
+        self.normal_map.clone()
+    }
+
+    fn compute_bsdf(&self, intersection: &SurfaceInteraction) -> Box<dyn BSDF> {
+        let (normal, tangent, bitangent) = self.compute_shading_geometry(intersection);
+        Box::new(TransparentBSDF {
+            bsdf: BSDFBase {
+                normal,
+                tangent,
+                bitangent,
+            },
+            ior: self.eta,
+        })
+    }
+
+    fn compute_bssrdf(&self, intersection: &SurfaceInteraction) -> Option<BurleyBSSRDF> {
+        let (normal, tangent, bitangent) = self.compute_shading_geometry(intersection);
+        Some(BurleyBSSRDF {
+            frame: BSDFBase {
+                normal,
+                tangent,
+                bitangent,
+            },
+            position: intersection.position,
+            material: intersection.material(),
+            albedo: self.albedo,
+            d: self.d,
+            eta: self.eta,
+            c: self.c,
+        })
+    }
+}
@@ -1,20 +1,20 @@
 use super::bxdf::bsdf::BSDFBase;
 use super::bxdf::transparent::TransparentBSDF;
-use crate::function_layer::material::material::fetch_normal_map;
+use crate::function_layer::material::material::{fetch_float, fetch_normal_map};
 use crate::function_layer::texture::normal_texture::NormalTexture;
-use crate::function_layer::{Material, SurfaceInteraction, BSDF};
+use crate::function_layer::{Material, SurfaceInteraction, Texture, BSDF};
 use serde_json::Value;
 use std::rc::Rc;
 
 pub struct TransparentMaterial {
     normal_map: Option<Rc<NormalTexture>>,
-    ior: f32,
+    ior: Rc<dyn Texture<f32>>,
 }
 
 impl TransparentMaterial {
     pub fn from_json(json: &Value) -> Self {
         let normal_map = fetch_normal_map(json);
-        let ior = json["ior"].as_f64().unwrap() as f32;
+        let ior = fetch_float(json, "ior", 1.5);
         Self { normal_map, ior }
     }
 }
@@ -32,7 +32,7 @@ impl Material for TransparentMaterial {
                 tangent,
                 bitangent,
             },
-            ior: self.ior,
+            ior: self.ior.evaluate(intersection),
         })
     }
 }
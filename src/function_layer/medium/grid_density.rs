@@ -1,139 +1,451 @@
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::core_layer::function::lerp;
 use crate::core_layer::transform::Transform;
-use crate::function_layer::medium::medium::{HenyeyGreenstein, Medium, MediumInteraction};
-use crate::function_layer::{Bounds3, Ray, Sampler, V3f};
-use cgmath::{Point3, Vector3};
+use crate::function_layer::medium::medium::{Medium, MediumInteraction, PhaseFunction};
+use crate::function_layer::{Ray, Sampler, V3f};
+use cgmath::{Array, ElementWise, Point3, Zero};
 use std::cell::RefCell;
+use std::ops::Mul;
 use std::rc::Rc;
 
+/// 密度网格的重建方式。tricubic使用三次B样条，是相邻4^3个体素的凸组合，
+/// 因此粗网格中的上下界仍然有效
+#[derive(Clone, Copy)]
+pub enum Interpolation {
+    Trilinear,
+    Tricubic,
+}
+
+/// 体素网格定义密度的非均匀介质，网格占据介质空间中的单位立方体。
+/// 另外维护一个低分辨率的密度上下界网格，用于delta tracking和ratio tracking时跳过空区域
 #[derive(Clone)]
 pub struct GridDensityMedium {
-    // sigma_a: SpectrumRGB,
+    sigma_a: SpectrumRGB,
     sigma_s: SpectrumRGB,
-    g: f32,
+    sigma_t: SpectrumRGB,
+    // sigma_t各通道的最大值，所有通道共用同一个majorant
+    sigma_t_max: f32,
+    phase: Rc<dyn PhaseFunction>,
     nx: usize,
     ny: usize,
     nz: usize,
     medium2world: Transform,
     density: Vec<f32>,
-    sigma_t: f32,
-    inv_max_density: f32,
+    // 每个体素的自发光辐射亮度，与密度网格分辨率相同，为空时介质不发光
+    emission: Vec<V3f>,
+    interpolation: Interpolation,
+    majorant_res: [usize; 3],
+    max_density: Vec<f32>,
+    // 计算透射率时作为控制变量的密度(residual ratio tracking)，全为0时即为ratio tracking
+    min_density: Vec<f32>,
 }
 
 impl GridDensityMedium {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         sigma_a: SpectrumRGB,
         sigma_s: SpectrumRGB,
-        g: f32,
+        phase: Rc<dyn PhaseFunction>,
         nx: usize,
         ny: usize,
         nz: usize,
         medium2world: Transform,
         density: Vec<f32>,
+        emission: Vec<V3f>,
+        interpolation: Interpolation,
+        majorant_res: usize,
+        residual: bool,
     ) -> Self {
-        let sigma_t = (sigma_a + sigma_s).rgb().x;
+        let sigma_t = sigma_a + sigma_s;
+        let rgb = sigma_t.rgb();
+        let sigma_t_max = rgb.x.max(rgb.y).max(rgb.z);
         let mut density = density;
         density.resize(nx * ny * nz, 0.0);
-        let inv_max_density = 1.0 / density.iter().max_by(|&a, &b| a.total_cmp(b)).unwrap();
-        Self {
-            // sigma_a,
+        let mut medium = Self {
+            sigma_a,
             sigma_s,
-            g,
+            sigma_t,
+            sigma_t_max,
+            phase,
             nx,
             ny,
             nz,
             medium2world,
             density,
-            sigma_t,
-            inv_max_density,
+            emission,
+            interpolation,
+            majorant_res: [
+                majorant_res.clamp(1, nx),
+                majorant_res.clamp(1, ny),
+                majorant_res.clamp(1, nz),
+            ],
+            max_density: vec![],
+            min_density: vec![],
+        };
+        medium.build_majorant_grid(residual);
+        medium
+    }
+
+    // 粗网格单元覆盖的区域内，三线性插值只会用到该区域向外扩展半个体素内的体素，
+    // 三次B样条再向外多用一个体素。超出网格范围的体素密度为0
+    fn build_majorant_grid(&mut self, residual: bool) {
+        let [mx, my, mz] = self.majorant_res;
+        let n = [self.nx, self.ny, self.nz];
+        let support = match self.interpolation {
+            Interpolation::Trilinear => 0,
+            Interpolation::Tricubic => 1,
+        };
+        let voxel_range = |axis: usize, cell: usize, res: usize| -> (i64, i64) {
+            let scale = n[axis] as f32 / res as f32;
+            let lo = (cell as f32 * scale - 0.5).floor() as i64 - support;
+            let hi = ((cell + 1) as f32 * scale - 0.5).floor() as i64 + 1 + support;
+            (lo, hi)
+        };
+        self.max_density = vec![0.0; mx * my * mz];
+        self.min_density = vec![0.0; mx * my * mz];
+        for z in 0..mz {
+            let rz = voxel_range(2, z, mz);
+            for y in 0..my {
+                let ry = voxel_range(1, y, my);
+                for x in 0..mx {
+                    let rx = voxel_range(0, x, mx);
+                    let (mut lo, mut hi) = (f32::INFINITY, 0.0f32);
+                    for k in rz.0..=rz.1 {
+                        for j in ry.0..=ry.1 {
+                            for i in rx.0..=rx.1 {
+                                let d = self.d(i, j, k);
+                                lo = lo.min(d);
+                                hi = hi.max(d);
+                            }
+                        }
+                    }
+                    let index = (z * my + y) * mx + x;
+                    self.max_density[index] = hi;
+                    if residual {
+                        self.min_density[index] = lo;
+                    }
+                }
+            }
         }
     }
 
-    pub fn d(&self, p: Point3<usize>) -> f32 {
-        if p.x >= self.nx || p.y >= self.ny || p.z >= self.nz {
-            return 0.0;
+    fn index(&self, x: i64, y: i64, z: i64) -> Option<usize> {
+        if x < 0
+            || y < 0
+            || z < 0
+            || x >= self.nx as i64
+            || y >= self.ny as i64
+            || z >= self.nz as i64
+        {
+            return None;
         }
-        self.density[(p.z * self.ny + p.y) * self.nx + p.x]
+        Some((z as usize * self.ny + y as usize) * self.nx + x as usize)
     }
 
-    pub fn density(&self, p: Point3<f32>) -> f32 {
+    fn d(&self, x: i64, y: i64, z: i64) -> f32 {
+        self.index(x, y, z).map_or(0.0, |i| self.density[i])
+    }
+
+    fn le(&self, x: i64, y: i64, z: i64) -> V3f {
+        self.index(x, y, z)
+            .map_or(V3f::zero(), |i| self.emission[i])
+    }
+
+    // 按插值方式对体素值加权求和，超出网格范围的体素由value返回0
+    fn interpolate<T>(&self, p: Point3<f32>, value: impl Fn(i64, i64, i64) -> T) -> T
+    where
+        T: Zero + Mul<f32, Output = T>,
+    {
         let p_samples = Point3::new(
             p.x * self.nx as f32 - 0.5,
             p.y * self.ny as f32 - 0.5,
             p.z * self.nz as f32 - 0.5,
         );
-        let pi = Point3::new(
-            p_samples.x.floor(),
-            p_samples.y.floor(),
-            p_samples.z.floor(),
-        );
+        let pi = p_samples.map(|c| c.floor());
         let d = p_samples - pi;
-        let pi = Point3::new(pi.x as usize, pi.y as usize, pi.z as usize);
-        let d00 = lerp(d.x, self.d(pi), self.d(pi + Vector3::new(1, 0, 0)));
-        let d10 = lerp(
-            d.x,
-            self.d(pi + Vector3::new(0, 1, 0)),
-            self.d(pi + Vector3::new(1, 1, 0)),
-        );
-        let d01 = lerp(
-            d.x,
-            self.d(pi + Vector3::new(0, 0, 1)),
-            self.d(pi + Vector3::new(1, 0, 1)),
-        );
-        let d11 = lerp(
-            d.x,
-            self.d(pi + Vector3::new(0, 1, 1)),
-            self.d(pi + Vector3::new(1, 1, 1)),
-        );
-        let d0 = lerp(d.y, d00, d10);
-        let d1 = lerp(d.y, d01, d11);
-        lerp(d.z, d0, d1)
+        let (x, y, z) = (pi.x as i64, pi.y as i64, pi.z as i64);
+        let v = |i: i64, j: i64, k: i64| value(x + i, y + j, z + k);
+        if let Interpolation::Trilinear = self.interpolation {
+            // 三线性插值用到x, x + 1处的体素
+            let lx = |j, k| lerp(d.x, v(0, j, k), v(1, j, k));
+            let ly = |k| lerp(d.y, lx(0, k), lx(1, k));
+            return lerp(d.z, ly(0), ly(1));
+        }
+        // 三次B样条用到x - 1到x + 2处的体素
+        let [wx, wy, wz] = [d.x, d.y, d.z].map(bspline_weights);
+        let mut sum = T::zero();
+        for (k, wz) in wz.iter().enumerate() {
+            for (j, wy) in wy.iter().enumerate() {
+                for (i, wx) in wx.iter().enumerate() {
+                    sum = sum + v(i as i64 - 1, j as i64 - 1, k as i64 - 1) * (wx * wy * wz);
+                }
+            }
+        }
+        sum
     }
+
+    /// 介质空间中p处插值得到的密度
+    pub fn density(&self, p: Point3<f32>) -> f32 {
+        self.interpolate(p, |x, y, z| self.d(x, y, z))
+    }
+
+    fn emission(&self, p: Point3<f32>) -> V3f {
+        self.interpolate(p, |x, y, z| self.le(x, y, z))
+    }
+
+    /// 用3D-DDA遍历光线o + t * d (介质空间)在[t_min, t_max]内经过的粗网格单元，
+    /// 对每个单元以(进入时刻, 离开时刻, 单元下标)调用f，f返回false时停止遍历
+    fn traverse(
+        &self,
+        o: Point3<f32>,
+        d: V3f,
+        t_min: f32,
+        t_max: f32,
+        mut f: impl FnMut(f32, f32, usize) -> bool,
+    ) {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv = 1.0 / d[axis];
+            let (near, far) = (-o[axis] * inv, (1.0 - o[axis]) * inv);
+            t0 = t0.max(near.min(far));
+            t1 = t1.min(near.max(far));
+        }
+        if t0 >= t1 {
+            return;
+        }
+        let p = o + d * t0;
+        let res = self.majorant_res;
+        let mut cell = [0i64; 3];
+        let mut next_t = [f32::INFINITY; 3];
+        let mut delta_t = [0.0f32; 3];
+        let mut step = [0i64; 3];
+        for axis in 0..3 {
+            let r = res[axis] as f32;
+            cell[axis] = ((p[axis] * r) as i64).clamp(0, res[axis] as i64 - 1);
+            if d[axis] > 0.0 {
+                next_t[axis] = t0 + ((cell[axis] + 1) as f32 / r - p[axis]) / d[axis];
+                delta_t[axis] = 1.0 / (r * d[axis]);
+                step[axis] = 1;
+            } else if d[axis] < 0.0 {
+                next_t[axis] = t0 + (cell[axis] as f32 / r - p[axis]) / d[axis];
+                delta_t[axis] = -1.0 / (r * d[axis]);
+                step[axis] = -1;
+            }
+        }
+        let mut t = t0;
+        loop {
+            let axis = if next_t[0] < next_t[1] {
+                if next_t[0] < next_t[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next_t[1] < next_t[2] {
+                1
+            } else {
+                2
+            };
+            let t_exit = next_t[axis].min(t1);
+            let index = (cell[2] as usize * res[1] + cell[1] as usize) * res[0] + cell[0] as usize;
+            if !f(t, t_exit, index) || t_exit >= t1 {
+                return;
+            }
+            t = t_exit;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= res[axis] as i64 {
+                return;
+            }
+            next_t[axis] += delta_t[axis];
+        }
+    }
+}
+
+// 均匀三次B样条在x - 1, x, x + 1, x + 2处体素上的权重，t为到x的距离
+fn bspline_weights(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    let s = 1.0 - t;
+    [
+        s * s * s / 6.0,
+        (3.0 * t3 - 6.0 * t2 + 4.0) / 6.0,
+        (-3.0 * t3 + 3.0 * t2 + 3.0 * t + 1.0) / 6.0,
+        t3 / 6.0,
+    ]
+}
+
+fn mean(v: V3f) -> f32 {
+    v.sum() / 3.0
 }
 
 impl Medium for GridDensityMedium {
+    // residual ratio tracking：每个粗网格单元内以最小密度对应的消光作为控制变量解析计算，
+    // 剩余部分用ratio tracking估计。不使用控制变量时即为普通的ratio tracking
     fn tr(&self, ray: &Ray, sampler: Rc<RefCell<dyn Sampler>>) -> SpectrumRGB {
-        let ray = self.medium2world.local_ray(ray);
-        (0..256)
-            .map(|_| sampler.borrow_mut().next_1d())
-            .map(|p| ray.at(ray.t_max * p))
-            .map(|p| self.density(p))
-            .map(|p| SpectrumRGB::same(p))
-            .fold(SpectrumRGB::same(0.0), |a, b| a + b)
+        let o = self.medium2world.to_local_point(ray.origin);
+        let d = self.medium2world.to_local_vec(ray.direction);
+        let sigma_t = self.sigma_t.rgb();
+        let mut tr = V3f::from_value(1.0);
+        self.traverse(o, d, ray.t_min, ray.t_max, |t_enter, t_exit, index| {
+            let d_min = self.min_density[index];
+            let control = sigma_t * (d_min * (t_exit - t_enter));
+            tr.mul_assign_element_wise(control.map(|c| (-c).exp()));
+            let majorant = self.sigma_t_max * (self.max_density[index] - d_min);
+            if majorant <= 0.0 {
+                return true;
+            }
+            let mut t = t_enter;
+            loop {
+                t -= (1.0 - sampler.borrow_mut().next_1d()).ln() / majorant;
+                if t >= t_exit {
+                    return true;
+                }
+                let residual = sigma_t * (self.density(o + d * t) - d_min);
+                tr.mul_assign_element_wise(residual.map(|r| (1.0 - r / majorant).max(0.0)));
+                if tr.x.max(tr.y).max(tr.z) <= 0.0 {
+                    return false;
+                }
+            }
+        });
+        SpectrumRGB::from_rgb(tr)
     }
 
+    // 光谱delta tracking：所有通道共用标量majorant，在每个候选碰撞点以各通道的平均值
+    // 决定真实碰撞或空碰撞的概率，并用权重修正各通道之间的差异。
+    // 自发光在每个候选碰撞点以当前权重估计sigma_a * Le / majorant并累加
     fn sample(
         &self,
         ray: &Ray,
         sampler: Rc<RefCell<dyn Sampler>>,
         mi: &mut MediumInteraction,
     ) -> SpectrumRGB {
-        let local_ray = self.medium2world.local_ray(ray);
-        let b = Bounds3::new(V3f::from([0.0; 3]), V3f::from([1.0; 3]));
-        let (t_near, t_far) = b.intersect_t(ray);
-        if t_near > t_far {
-            return SpectrumRGB::same(1.0);
-        }
-        let mut t = t_near;
-        loop {
-            t -= (1.0 - sampler.borrow_mut().next_1d()).ln() * self.inv_max_density / self.sigma_t;
-            if t >= t_far {
-                break;
+        let o = self.medium2world.to_local_point(ray.origin);
+        let d = self.medium2world.to_local_vec(ray.direction);
+        let (sigma_a, sigma_s, sigma_t) =
+            (self.sigma_a.rgb(), self.sigma_s.rgb(), self.sigma_t.rgb());
+        let mut weight = V3f::from_value(1.0);
+        let mut emission = V3f::zero();
+        self.traverse(o, d, ray.t_min, ray.t_max, |t_enter, t_exit, index| {
+            let majorant = self.sigma_t_max * self.max_density[index];
+            if majorant <= 0.0 {
+                return true;
             }
-            if self.density(local_ray.at(t)) * self.inv_max_density > sampler.borrow_mut().next_1d()
-            {
-                let phase = HenyeyGreenstein::new(self.g);
-                *mi = MediumInteraction::new(
-                    ray.at(t),
-                    ray.t,
-                    -ray.direction,
-                    Rc::new(self.clone()),
-                    Some(Box::new(phase)),
-                );
-                return self.sigma_s / self.sigma_t;
+            let mut t = t_enter;
+            loop {
+                t -= (1.0 - sampler.borrow_mut().next_1d()).ln() / majorant;
+                if t >= t_exit {
+                    return true;
+                }
+                let p = o + d * t;
+                let density = self.density(p);
+                if !self.emission.is_empty() {
+                    let le = self.emission(p).mul_element_wise(sigma_a * density);
+                    emission += weight.mul_element_wise(le) / majorant;
+                }
+                let sigma_t_p = sigma_t * density;
+                let p_real = mean(sigma_t_p) / majorant;
+                if sampler.borrow_mut().next_1d() < p_real {
+                    weight.mul_assign_element_wise(sigma_s * density / mean(sigma_t_p));
+                    mi.position = ray.at(t);
+                    mi.time = ray.t;
+                    mi.wo = -ray.direction;
+                    mi.phase = Some(self.phase.clone());
+                    return false;
+                }
+                let sigma_n = V3f::from_value(majorant) - sigma_t_p;
+                weight.mul_assign_element_wise(sigma_n / mean(sigma_n));
             }
+        });
+        mi.emission = SpectrumRGB::from_rgb(emission);
+        SpectrumRGB::from_rgb(weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_layer::medium::medium::HenyeyGreenstein;
+    use crate::function_layer::sampler::sampler::construct_sampler;
+    use cgmath::InnerSpace;
+    use serde_json::json;
+
+    const N: usize = 16;
+
+    // 密度处处为1的网格，介质空间与世界空间重合
+    fn constant_grid(sigma_a: f32, sigma_s: f32, residual: bool) -> GridDensityMedium {
+        GridDensityMedium::new(
+            SpectrumRGB::same(sigma_a),
+            SpectrumRGB::same(sigma_s),
+            Rc::new(HenyeyGreenstein::new(0.0)),
+            N,
+            N,
+            N,
+            Transform::identity(),
+            vec![1.0; N * N * N],
+            vec![],
+            Interpolation::Trilinear,
+            4,
+            residual,
+        )
+    }
+
+    fn sampler() -> Rc<RefCell<dyn Sampler>> {
+        construct_sampler(&json!({"type": "independent", "xSamples": 1, "ySamples": 1}))
+    }
+
+    // 距离网格边界超过半个体素的区域内插值结果等于体素值
+    #[test]
+    fn interpolated_density_is_constant_inside() {
+        let medium = constant_grid(1.0, 1.0, false);
+        for p in [[0.1, 0.5, 0.5], [0.37, 0.61, 0.23], [0.9, 0.9, 0.1]] {
+            assert!((medium.density(Point3::from(p)) - 1.0).abs() < 1e-5);
         }
-        SpectrumRGB::same(1.0)
+    }
+
+    #[test]
+    fn transmittance_matches_beer_lambert() {
+        let (sigma_t, distance) = (3.0, 0.5);
+        let mut ray = Ray::new(Point3::new(0.25, 0.5, 0.5), V3f::new(1.0, 0.0, 0.0));
+        ray.t_max = distance;
+        let expected = (-sigma_t * (distance - ray.t_min)).exp();
+        let sampler = sampler();
+        for residual in [true, false] {
+            let medium = constant_grid(1.0, 2.0, residual);
+            let count = 20000;
+            let mean = (0..count)
+                .map(|_| medium.tr(&ray, sampler.clone()).rgb().x)
+                .sum::<f32>()
+                / count as f32;
+            assert!(
+                (mean - expected).abs() < 0.01,
+                "residual: {}, tr: {}, expected: {}",
+                residual,
+                mean,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn delta_tracking_mean_free_path() {
+        let sigma_t = 20.0;
+        let medium = constant_grid(5.0, 15.0, false);
+        let origin = Point3::new(0.1, 0.5, 0.5);
+        let ray = Ray::new(origin, V3f::new(1.0, 0.0, 0.0));
+        let sampler = sampler();
+        let count = 20000;
+        let mut total = 0.0;
+        for _ in 0..count {
+            let mut mi = MediumInteraction::default();
+            let weight = medium.sample(&ray, sampler.clone(), &mut mi);
+            assert!(mi.phase.is_some(), "ray escaped the medium");
+            // 真实碰撞的权重为单次散射反照率
+            assert!((weight.rgb().x - 0.75).abs() < 1e-5);
+            total += (mi.position - origin).magnitude();
+        }
+        let mean = total / count as f32;
+        assert!(
+            (mean - 1.0 / sigma_t).abs() < 0.002,
+            "mean free path: {}",
+            mean
+        );
     }
 }
@@ -1,19 +1,18 @@
-use super::medium::{Medium, MediumInteraction};
+use super::medium::{Medium, MediumInteraction, PhaseFunction};
 use crate::core_layer::colorspace::SpectrumRGB;
-use crate::function_layer::medium::medium::HenyeyGreenstein;
-use crate::function_layer::{MediumInterface, Ray, Sampler};
-use cgmath::Array;
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use crate::function_layer::{Ray, Sampler};
+use cgmath::{Array, Zero};
 use std::cell::RefCell;
 use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct HomogeneousMedium {
-    // sigma_a: SpectrumRGB,
+    sigma_a: SpectrumRGB,
     sigma_s: SpectrumRGB,
     sigma_t: SpectrumRGB,
-    g: f32,
+    phase: Rc<dyn PhaseFunction>,
+    // 单位吸收系数对应的自发光辐射亮度
+    emission: SpectrumRGB,
 }
 
 impl Medium for HomogeneousMedium {
@@ -27,7 +26,21 @@ impl Medium for HomogeneousMedium {
         sampler: Rc<RefCell<dyn Sampler>>,
         mi: &mut MediumInteraction,
     ) -> SpectrumRGB {
-        let channel = ThreadRng::default().gen_range(0..3usize);
+        // 自发光沿整个光线段解析积分：sigma_a * Le * (1 - exp(-sigma_t * t)) / sigma_t
+        if !self.emission.rgb().is_zero() {
+            let (sigma_t, t_max) = (self.sigma_t.rgb(), f32::MAX.min(ray.t_max));
+            let segment = sigma_t.map(|s| {
+                if s > 0.0 {
+                    -(-s * t_max).exp_m1() / s
+                } else {
+                    t_max
+                }
+            });
+            mi.emission = self.sigma_a * self.emission * segment;
+        }
+        // 均匀选择一个通道按其消光系数采样距离，pdf取各通道pdf的平均，
+        // 即对各通道的采样策略做单样本的balance heuristic MIS
+        let channel = ((sampler.borrow_mut().next_1d() * 3.0) as usize).min(2);
         let dist = -(1.0 - sampler.borrow_mut().next_1d()).ln() / self.sigma_t.rgb()[channel];
         let t = dist.min(ray.t_max);
         let sampled_medium = t < ray.t_max;
@@ -35,9 +48,7 @@ impl Medium for HomogeneousMedium {
             mi.position = ray.at(t);
             mi.wo = -ray.direction;
             mi.time = ray.t;
-            mi.medium_interface =
-                MediumInterface::new(Some(Rc::new(self.clone())), Some(Rc::new(self.clone())));
-            mi.phase = Some(Box::new(HenyeyGreenstein::new(self.g)));
+            mi.phase = Some(self.phase.clone());
         }
         let tr = (self.sigma_t * -f32::MAX.min(t)).exp();
         let density = if sampled_medium {
@@ -58,13 +69,19 @@ impl Medium for HomogeneousMedium {
 }
 
 impl HomogeneousMedium {
-    pub fn new(sigma_a: SpectrumRGB, sigma_s: SpectrumRGB, g: f32) -> Self {
+    pub fn new(
+        sigma_a: SpectrumRGB,
+        sigma_s: SpectrumRGB,
+        phase: Rc<dyn PhaseFunction>,
+        emission: SpectrumRGB,
+    ) -> Self {
         let sigma_t = sigma_s + sigma_a;
         Self {
-            // sigma_a,
+            sigma_a,
             sigma_s,
             sigma_t,
-            g,
+            phase,
+            emission,
         }
     }
 }
@@ -1,10 +1,12 @@
-use super::{grid_density::GridDensityMedium, homogeneous::HomogeneousMedium};
+use super::grid_density::{GridDensityMedium, Interpolation};
+use super::homogeneous::HomogeneousMedium;
 use crate::core_layer::colorspace::SpectrumRGB;
 use crate::core_layer::constants::INV_PI;
 use crate::core_layer::function::{coordinate_system, spherical_direction};
 use crate::core_layer::transform::Transform;
 use crate::function_layer::{fetch_v3f, Interaction, Ray, Sampler, V3f};
-use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector2, Zero};
+use crate::resource_layer::volume::load_volume;
+use cgmath::{Array, ElementWise, EuclideanSpace, InnerSpace, Point3, Vector2, Zero};
 use serde_json::Value;
 use std::cell::RefCell;
 use std::f32::consts::PI;
@@ -27,7 +29,9 @@ pub struct MediumInteraction {
     pub wo: V3f,
     pub normal: V3f,
     pub medium_interface: MediumInterface,
-    pub phase: Option<Box<dyn PhaseFunction>>,
+    pub phase: Option<Rc<dyn PhaseFunction>>,
+    // 沿采样的光线段累积的介质自发光，已乘上透射率，由积分器乘上光线起点的throughput
+    pub emission: SpectrumRGB,
 }
 
 impl Interaction for MediumInteraction {
@@ -43,6 +47,10 @@ impl Interaction for MediumInteraction {
     fn p(&self) -> Point3<f32> {
         self.position
     }
+
+    fn medium(&self, _w: V3f) -> Option<Rc<dyn Medium>> {
+        self.medium_interface.inside()
+    }
 }
 
 impl Default for MediumInteraction {
@@ -55,28 +63,12 @@ impl Default for MediumInteraction {
             normal: V3f::new(0.0, 0.0, 0.0),
             medium_interface: Default::default(),
             phase: None,
+            emission: SpectrumRGB::same(0.0),
         }
     }
 }
 
 impl MediumInteraction {
-    pub fn new(
-        p: Point3<f32>,
-        time: f32,
-        wo: V3f,
-        medium_interface: Rc<dyn Medium>,
-        phase: Option<Box<dyn PhaseFunction>>,
-    ) -> Self {
-        Self {
-            position: p,
-            time,
-            p_error: V3f::from([0.0; 3]),
-            wo,
-            normal: V3f::from([0.0; 3]),
-            medium_interface: MediumInterface::new(Some(medium_interface), None),
-            phase,
-        }
-    }
     pub fn is_valid(&self) -> bool {
         self.phase.is_some()
     }
@@ -86,6 +78,15 @@ pub trait PhaseFunction {
     fn p(&self, wo: V3f, wi: V3f) -> f32;
     fn sample_p(&self, wo: V3f, wi: &mut V3f, u: Vector2<f32>) -> f32;
 }
+
+// 以wo为轴，由与wo夹角的余弦和方位角得到方向
+fn direction_around(wo: V3f, cos_theta: f32, phi: f32) -> V3f {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let (mut v1, mut v2) = (V3f::zero(), V3f::zero());
+    coordinate_system(wo, &mut v1, &mut v2);
+    spherical_direction(sin_theta, cos_theta, phi, v1, v2, wo)
+}
+
 pub struct IsotropicPhaseFunc;
 impl PhaseFunction for IsotropicPhaseFunc {
     fn p(&self, _wo: V3f, _wi: V3f) -> f32 {
@@ -105,7 +106,17 @@ impl PhaseFunction for IsotropicPhaseFunc {
 #[inline]
 fn phase_hg(cos_theta: f32, g: f32) -> f32 {
     let denom = 1.0 + g * g + 2.0 * g * cos_theta;
-    std::f32::consts::FRAC_PI_4 * (1.0 - g * g) / (denom * denom.sqrt())
+    INV_PI / 4.0 * (1.0 - g * g) / (denom * denom.sqrt())
+}
+
+// 按HG分布采样wo与wi夹角的余弦
+fn sample_cos_hg(g: f32, u: f32) -> f32 {
+    if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let sqr_term = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+        -(1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+    }
 }
 
 pub struct HenyeyGreenstein {
@@ -124,19 +135,139 @@ impl PhaseFunction for HenyeyGreenstein {
     }
 
     fn sample_p(&self, wo: V3f, wi: &mut V3f, u: Vector2<f32>) -> f32 {
-        let g = self.g;
-        let cos_theta = if g.abs() < 1e-3 {
-            1.0 - 2.0 * u.x
+        let cos_theta = sample_cos_hg(self.g, u.x);
+        *wi = direction_around(wo, cos_theta, PI * 2.0 * u.y);
+        phase_hg(cos_theta, self.g)
+    }
+}
+
+/// 两个HG的线性组合，weight为第一个波瓣的权重，常用于同时具有前向和后向散射的介质
+pub struct TwoLobeHenyeyGreenstein {
+    g1: f32,
+    g2: f32,
+    weight: f32,
+}
+
+impl PhaseFunction for TwoLobeHenyeyGreenstein {
+    fn p(&self, wo: V3f, wi: V3f) -> f32 {
+        let cos_theta = wo.dot(wi);
+        self.weight * phase_hg(cos_theta, self.g1)
+            + (1.0 - self.weight) * phase_hg(cos_theta, self.g2)
+    }
+
+    fn sample_p(&self, wo: V3f, wi: &mut V3f, u: Vector2<f32>) -> f32 {
+        // 按权重选择波瓣，并把样本重新映射到[0, 1)
+        let cos_theta = if u.x < self.weight {
+            sample_cos_hg(self.g1, u.x / self.weight)
         } else {
-            let sqr_term = (1.0 - g * g) / (1.0 + g - 2.0 * g * u[0]);
-            -(1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+            sample_cos_hg(self.g2, (u.x - self.weight) / (1.0 - self.weight))
+        };
+        *wi = direction_around(wo, cos_theta, PI * 2.0 * u.y);
+        self.p(wo, *wi)
+    }
+}
+
+/// 远小于波长的粒子的Rayleigh散射，p = 3 / (16pi) * (1 + cos^2)
+pub struct Rayleigh;
+
+impl PhaseFunction for Rayleigh {
+    fn p(&self, wo: V3f, wi: V3f) -> f32 {
+        let cos_theta = wo.dot(wi);
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    fn sample_p(&self, wo: V3f, wi: &mut V3f, u: Vector2<f32>) -> f32 {
+        // CDF为(cos^3 + 3cos + 4) / 8，用Cardano公式求解三次方程
+        let q = 4.0 - 8.0 * u.x;
+        let d = (q * q * 0.25 + 1.0).sqrt();
+        let cos_theta = ((-0.5 * q + d).cbrt() + (-0.5 * q - d).cbrt()).clamp(-1.0, 1.0);
+        *wi = direction_around(wo, cos_theta, PI * 2.0 * u.y);
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+}
+
+/// Draine相函数，alpha为0时退化为HG，alpha为1时为Cornette-Shanks相函数。
+/// 与HG混合可以近似云和雾中水滴的Mie散射(Jendersie and d'Eon 2023)
+pub struct Draine {
+    g: f32,
+    alpha: f32,
+}
+
+impl Draine {
+    // mu为散射角的余弦，与光线前进方向相同时为1
+    fn eval(&self, mu: f32) -> f32 {
+        let (g, alpha) = (self.g, self.alpha);
+        let denom = 1.0 + g * g - 2.0 * g * mu;
+        INV_PI / 4.0 * (1.0 - g * g) / (denom * denom.sqrt()) * (1.0 + alpha * mu * mu)
+            / (1.0 + alpha * (1.0 + 2.0 * g * g) / 3.0)
+    }
+
+    // 未归一化的CDF，在-1处为0
+    fn cdf(&self, mu: f64) -> f64 {
+        let (g, alpha) = (self.g as f64, self.alpha as f64);
+        if g.abs() < 1e-3 {
+            return mu + alpha * mu * mu * mu / 3.0 + 1.0 + alpha / 3.0;
+        }
+        // 令s = 1 + g^2 - 2g * mu后逐项积分
+        let (a, b) = (1.0 + g * g, 2.0 * g);
+        let antiderivative = |s: f64| {
+            -2.0 / s.sqrt()
+                + alpha / (b * b)
+                    * (-2.0 * a * a / s.sqrt() - 4.0 * a * s.sqrt() + 2.0 / 3.0 * s * s.sqrt())
         };
-        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
-        let phi = PI * 2.0 * u.y;
-        let (mut v1, mut v2) = (V3f::zero(), V3f::zero());
-        coordinate_system(wo, &mut v1, &mut v2);
-        *wi = spherical_direction(sin_theta, cos_theta, phi, v1, v2, wo);
-        phase_hg(cos_theta, g)
+        (antiderivative(a + b) - antiderivative(a - b * mu)) / b
+    }
+}
+
+impl PhaseFunction for Draine {
+    fn p(&self, wo: V3f, wi: V3f) -> f32 {
+        self.eval(-wo.dot(wi))
+    }
+
+    fn sample_p(&self, wo: V3f, wi: &mut V3f, u: Vector2<f32>) -> f32 {
+        // 没有简单的解析逆，二分求解CDF
+        let target = u.x as f64 * self.cdf(1.0);
+        let (mut lo, mut hi) = (-1.0, 1.0);
+        for _ in 0..32 {
+            let mid = 0.5 * (lo + hi);
+            if self.cdf(mid) < target {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let mu = (0.5 * (lo + hi)) as f32;
+        *wi = direction_around(wo, -mu, PI * 2.0 * u.y);
+        self.eval(mu)
+    }
+}
+
+/// 由介质的json构造相函数。没有给出phase时使用参数为g的HG
+pub fn construct_phase_function(json: &Value) -> Rc<dyn PhaseFunction> {
+    let phase = &json["phase"];
+    let g = |key: &str, dft: f64| phase[key].as_f64().unwrap_or(dft) as f32;
+    if phase.is_null() {
+        return Rc::new(HenyeyGreenstein::new(
+            json["g"].as_f64().unwrap_or(0.0) as f32
+        ));
+    }
+    match phase["type"]
+        .as_str()
+        .expect("No phase function type given!")
+    {
+        "isotropic" => Rc::new(IsotropicPhaseFunc),
+        "hg" => Rc::new(HenyeyGreenstein::new(g("g", 0.0))),
+        "twoLobeHG" => Rc::new(TwoLobeHenyeyGreenstein {
+            g1: g("g1", 0.8),
+            g2: g("g2", -0.3),
+            weight: g("weight", 0.8).clamp(0.0, 1.0),
+        }),
+        "rayleigh" => Rc::new(Rayleigh),
+        "draine" => Rc::new(Draine {
+            g: g("g", 0.0),
+            alpha: g("alpha", 1.0),
+        }),
+        tp => panic!("Invalid phase function type: {}!", tp),
     }
 }
 
@@ -167,56 +298,175 @@ impl MediumInterface {
     }
 }
 
-pub fn construct_medium(json: &Value) -> Option<Rc<dyn Medium>> {
-    let g = json["g"].as_f64().unwrap_or(0.0) as f32;
-    let medium = json["medium"].as_str().unwrap();
-    let (sig_a, sig_s) = match SUBSURFACE_PARAMETER_TABLE
-        .iter()
-        .position(|&x| x.0 == medium)
-    {
-        Some(pos) => {
-            let r = SUBSURFACE_PARAMETER_TABLE[pos];
-            (r.2, r.1)
+// 标量或RGB形式给出的系数
+fn fetch_coefficient(json: &Value, field: &str) -> Option<V3f> {
+    let val = json.get(field)?;
+    Some(match val.as_f64() {
+        Some(v) => V3f::from_value(v as f32),
+        None => V3f::from(serde_json::from_value::<[f32; 3]>(val.clone()).unwrap()),
+    })
+}
+
+/// 介质的吸收和散射系数，按以下优先级给出，最后统一乘上scale：
+/// 单次散射反照率albedo和平均自由程meanFreePath、显式的sigma_a和sigma_s、预设的介质名称medium
+pub fn fetch_coefficients(json: &Value) -> (SpectrumRGB, SpectrumRGB) {
+    let preset = json["medium"]
+        .as_str()
+        .and_then(|medium| SUBSURFACE_PARAMETER_TABLE.iter().find(|&x| x.0 == medium))
+        .map_or(
+            (V3f::new(0.0011, 0.0024, 0.014), V3f::new(2.55, 3.21, 3.77)),
+            |r| (r.2, r.1),
+        );
+    let (sig_a, sig_s) = match (
+        fetch_coefficient(json, "albedo"),
+        fetch_coefficient(json, "meanFreePath"),
+    ) {
+        (Some(albedo), Some(mfp)) => {
+            let sig_t = mfp.map(|l| 1.0 / l);
+            let sig_s = albedo.mul_element_wise(sig_t);
+            (sig_t - sig_s, sig_s)
         }
-        None => (V3f::new(0.0011, 0.0024, 0.014), V3f::new(2.55, 3.21, 3.77)),
+        _ => (
+            fetch_coefficient(json, "sigma_a").unwrap_or(preset.0),
+            fetch_coefficient(json, "sigma_s").unwrap_or(preset.1),
+        ),
     };
-    let (sig_a, sig_s) = (SpectrumRGB::from_rgb(sig_a), SpectrumRGB::from_rgb(sig_s));
+    let scale = json["scale"].as_f64().unwrap_or(1.0) as f32;
+    (
+        SpectrumRGB::from_rgb(sig_a * scale),
+        SpectrumRGB::from_rgb(sig_s * scale),
+    )
+}
+
+pub fn construct_medium(json: &Value) -> Option<Rc<dyn Medium>> {
+    let (sig_a, sig_s) = fetch_coefficients(json);
+    let phase = construct_phase_function(json);
+    // 自发光的颜色，使用发光网格或温度网格时作为乘在其上的系数
+    let constant_emission = json.get("emission").is_some();
+    let emission = if constant_emission {
+        fetch_v3f(json, "emission", V3f::zero())
+    } else {
+        V3f::from_value(1.0)
+    } * json["emissionScale"].as_f64().unwrap_or(1.0) as f32;
     match json["type"].as_str().unwrap() {
-        "homogeneous" => Some(Rc::new(HomogeneousMedium::new(sig_a, sig_s, g))),
+        "homogeneous" => {
+            let emission = if constant_emission {
+                emission
+            } else {
+                V3f::zero()
+            };
+            Some(Rc::new(HomogeneousMedium::new(
+                sig_a,
+                sig_s,
+                phase,
+                SpectrumRGB::from_rgb(emission),
+            )))
+        }
         "gridDensity" => {
-            let density = match json.get("density") {
-                Some(val) => serde_json::from_value::<Vec<f32>>(val.clone()).unwrap(),
+            // 密度网格来自体数据文件或内联的density数组，网格占据[p0, p1]，
+            // 文件中给出的包围盒可以被p0、p1覆盖。
+            // 自发光可以来自文件中的温度网格(黑体辐射)、发光网格或常量
+            let (nx, ny, nz, density, bounds, mut voxel_emission) = match json["file"].as_str() {
+                Some(file) => {
+                    let resolution = json
+                        .get("resolution")
+                        .map(|r| serde_json::from_value::<[usize; 3]>(r.clone()).unwrap());
+                    let mut grids = load_volume(file, resolution);
+                    let mut take = |name: &str, res: Option<[usize; 3]>| {
+                        let grid = grids
+                            .remove(name)
+                            .unwrap_or_else(|| panic!("No grid named {} in {}!", name, file));
+                        if res.is_some_and(|res| res != grid.res) {
+                            panic!(
+                                "Grid {} in {} must match the density resolution!",
+                                name, file
+                            );
+                        }
+                        grid
+                    };
+                    let grid = take(json["grid"].as_str().unwrap_or("density"), None);
+                    let voxel_emission: Vec<V3f> =
+                        if let Some(name) = json["temperatureGrid"].as_str() {
+                            let scale = json["temperatureScale"].as_f64().unwrap_or(1.0) as f32;
+                            let offset = json["temperatureOffset"].as_f64().unwrap_or(0.0) as f32;
+                            take(name, Some(grid.res))
+                                .data
+                                .iter()
+                                .map(|t| {
+                                    SpectrumRGB::blackbody((t - offset) * scale)
+                                        .rgb()
+                                        .mul_element_wise(emission)
+                                })
+                                .collect()
+                        } else if let Some(name) = json["emissionGrid"].as_str() {
+                            take(name, Some(grid.res))
+                                .data
+                                .iter()
+                                .map(|e| emission * *e)
+                                .collect()
+                        } else {
+                            vec![]
+                        };
+                    let [nx, ny, nz] = grid.res;
+                    let bounds = (grid.p_min.to_vec(), grid.p_max.to_vec());
+                    (nx, ny, nz, grid.data, bounds, voxel_emission)
+                }
                 None => {
-                    eprintln!("No density given for grid density medium");
-                    return None;
+                    let density = json
+                        .get("density")
+                        .map(|val| serde_json::from_value::<Vec<f32>>(val.clone()).unwrap())
+                        .expect("No density given for grid density medium!");
+                    let nx = json["nx"].as_u64().unwrap_or(1) as usize;
+                    let ny = json["ny"].as_u64().unwrap_or(1) as usize;
+                    let nz = json["nz"].as_u64().unwrap_or(1) as usize;
+                    if density.len() != nx * ny * nz {
+                        panic!(
+                            "GridDensityMedium has {} density values; expected nx*ny*nz = {}!",
+                            density.len(),
+                            nx * ny * nz
+                        );
+                    }
+                    let bounds = (V3f::zero(), V3f::zero());
+                    (nx, ny, nz, density, bounds, vec![])
                 }
             };
-            let nx = json["nx"].as_u64().unwrap_or(1) as usize;
-            let ny = json["ny"].as_u64().unwrap_or(1) as usize;
-            let nz = json["nz"].as_u64().unwrap_or(1) as usize;
-            if density.len() != nx * ny * nz {
-                eprintln!(
-                    "GridDensityMedium has {} density values; expected nx*ny*nz = {}",
-                    density.len(),
-                    nx * ny * nz
-                );
-                return None;
+            if voxel_emission.is_empty() && constant_emission {
+                voxel_emission = vec![emission; nx * ny * nz];
             }
-            let p0 = fetch_v3f(json, "p0", V3f::zero());
-            let p1 = fetch_v3f(json, "p1", V3f::zero());
-            let translate = Transform::translation(p0);
-            let rotate = Matrix4::identity();
-            let scale = Transform::scalation(p1 - p0);
-            let medium2world = Transform::new(translate, rotate, scale);
+            let p0 = fetch_v3f(json, "p0", bounds.0);
+            let p1 = fetch_v3f(json, "p1", bounds.1);
+            // 单位立方体先映射到[p0, p1]，再施加transform
+            let trans = json
+                .get("transform")
+                .map(Transform::from_json)
+                .unwrap_or_default();
+            let offset = (trans.t * p0.extend(1.0)).truncate();
+            let extent = p1 - p0;
+            let mut scale = trans.scale;
+            for i in 0..3 {
+                scale[i][i] *= extent[i];
+            }
+            let medium2world = Transform::new(Transform::translation(offset), trans.rotate, scale);
+            let interpolation = match json["interpolation"].as_str().unwrap_or("trilinear") {
+                "trilinear" => Interpolation::Trilinear,
+                "tricubic" => Interpolation::Tricubic,
+                tp => panic!("Invalid interpolation type: {}!", tp),
+            };
+            let majorant_res = json["majorantResolution"].as_u64().unwrap_or(16) as usize;
+            let residual = json["residualRatioTracking"].as_bool().unwrap_or(true);
             Some(Rc::new(GridDensityMedium::new(
                 sig_a,
                 sig_s,
-                g,
+                phase,
                 nx,
                 ny,
                 nz,
                 medium2world,
                 density,
+                voxel_emission,
+                interpolation,
+                majorant_res,
+                residual,
             )))
         }
         tp => panic!("Invalid medium type: {}!", tp),
@@ -0,0 +1,77 @@
+use super::low_discrepancy::{owen_scrambled_radical_inverse, pixel_hash, primes};
+use crate::function_layer::Sampler;
+use cgmath::Vector2;
+use serde_json::Value;
+
+// 支持的最大维数，超出后从第2维开始循环使用
+const MAX_HALTON_DIMENSION: usize = 1000;
+
+/// 每个像素独立的Halton序列，第d维以第d个素数为底，
+/// 以像素、维度和种子为哈希做Owen扰乱，使不同像素之间不相关
+pub struct HaltonSampler {
+    pub x_samples: usize,
+    pub y_samples: usize,
+    seed: u64,
+    primes: Vec<u32>,
+    pixel: Vector2<usize>,
+    sample_index: usize,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn from_json(json: &Value) -> Self {
+        let x_samples = json["xSamples"].as_u64().unwrap() as usize;
+        let y_samples = json["ySamples"].as_u64().unwrap() as usize;
+        let seed = json["seed"].as_u64().unwrap_or(0);
+        Self {
+            x_samples,
+            y_samples,
+            seed,
+            primes: primes(MAX_HALTON_DIMENSION),
+            pixel: Vector2::new(0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f32 {
+        if self.dimension >= MAX_HALTON_DIMENSION {
+            self.dimension = 2;
+        }
+        let h = pixel_hash(self.pixel, self.dimension, self.seed);
+        let u = owen_scrambled_radical_inverse(
+            self.primes[self.dimension],
+            self.sample_index as u64,
+            h,
+        );
+        self.dimension += 1;
+        u
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn xsp(&self) -> usize {
+        self.x_samples
+    }
+
+    fn ysp(&self) -> usize {
+        self.y_samples
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Vector2<usize>, sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.sample_dimension()
+    }
+
+    fn next_2d(&mut self) -> Vector2<f32> {
+        if self.dimension + 1 >= MAX_HALTON_DIMENSION {
+            self.dimension = 2;
+        }
+        Vector2::new(self.sample_dimension(), self.sample_dimension())
+    }
+}
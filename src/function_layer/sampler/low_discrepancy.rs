@@ -0,0 +1,137 @@
+use cgmath::Vector2;
+
+// 小于1的最大f32，保证采样值落在[0, 1)中
+pub const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+/// 将像素坐标、维度等整数混合为一个64位哈希值，作为置换和扰乱的种子
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+pub fn pixel_hash(pixel: Vector2<usize>, dimension: usize, seed: u64) -> u64 {
+    hash(&[pixel.x as u64, pixel.y as u64, dimension as u64, seed])
+}
+
+/// 哈希值映射为[0, 1)中的均匀分布
+pub fn hash_float(h: u64) -> f32 {
+    ((h >> 40) as f32 / (1u64 << 24) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// 由种子p确定的[0, l)的随机置换中第i个元素，无需存储整个置换(Kensler, Correlated Multi-Jittered Sampling)
+pub fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    ((i as u64 + p as u64) % l as u64) as u32
+}
+
+/// 基于哈希的二进制Owen扰乱(Laine and Karras)，每一位按更高位决定是否翻转
+pub fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+/// 二维Sobol序列的前两维，即(0, 2)序列：第0维为van der Corput序列，
+/// 第1维的生成矩阵由本原多项式x + 1递推得到
+pub fn sobol_2d(index: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut v = 1u32 << 31;
+    let mut index = index;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= 1u32 << (31 - bit);
+            y ^= v;
+        }
+        v ^= v >> 1;
+        index >>= 1;
+        bit += 1;
+    }
+    (x, y)
+}
+
+pub fn u32_to_float(v: u32) -> f32 {
+    (v as f32 * 2f32.powi(-32)).min(ONE_MINUS_EPSILON)
+}
+
+/// 以base为底的逆序数，每一位数字用由已确定的更高位数字和hash决定的随机置换替换，
+/// 即随机数字置换形式的Owen扰乱
+pub fn owen_scrambled_radical_inverse(base: u32, mut a: u64, hash: u64) -> f32 {
+    let base64 = base as u64;
+    // 覆盖32位精度所需的位数
+    let digits = (32.0 / (base as f64).log2()).ceil() as usize;
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    for level in 0..digits {
+        let next = a / base64;
+        let digit = (a - next * base64) as u32;
+        // 置换由层数和已确定的更高位数字共同决定
+        let digit_hash =
+            mix_bits(hash ^ mix_bits(reversed.wrapping_add((level as u64) << 56))) as u32;
+        let digit = permutation_element(digit, base, digit_hash) as u64;
+        reversed = reversed.wrapping_mul(base64).wrapping_add(digit);
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    ((reversed as f64 * inv_base_m) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// 前n个素数，用作Halton序列各维的底数
+pub fn primes(n: usize) -> Vec<u32> {
+    let mut primes = Vec::with_capacity(n);
+    let mut candidate = 2u32;
+    while primes.len() < n {
+        if primes
+            .iter()
+            .take_while(|&&p| p * p <= candidate)
+            .all(|&p| !candidate.is_multiple_of(p))
+        {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
@@ -1,4 +1,8 @@
+mod halton_sampler;
 mod independent_sampler;
+mod low_discrepancy;
 pub mod sampler;
+mod sobol_sampler;
+mod stratified_sampler;
 
 pub use sampler::Sampler;
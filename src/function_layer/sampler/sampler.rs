@@ -1,4 +1,7 @@
+use super::halton_sampler::HaltonSampler;
 use super::independent_sampler::IndependentSampler;
+use super::sobol_sampler::SobolSampler;
+use super::stratified_sampler::StratifiedSampler;
 use crate::function_layer::RR;
 use cgmath::Vector2;
 use serde_json::Value;
@@ -8,6 +11,8 @@ use std::rc::Rc;
 pub trait Sampler {
     fn xsp(&self) -> usize;
     fn ysp(&self) -> usize;
+    /// 开始生成某个像素的第sample_index个样本，之后的采样值依次取该样本的各个维度
+    fn start_pixel_sample(&mut self, _pixel: Vector2<usize>, _sample_index: usize) {}
     fn next_1d(&mut self) -> f32;
     fn next_2d(&mut self) -> Vector2<f32>;
 }
@@ -15,6 +20,9 @@ pub trait Sampler {
 pub fn construct_sampler(json: &Value) -> RR<dyn Sampler> {
     match json["type"].as_str().expect("No sampler type given!") {
         "independent" => Rc::new(RefCell::new(IndependentSampler::from_json(json))),
+        "stratified" => Rc::new(RefCell::new(StratifiedSampler::from_json(json))),
+        "halton" => Rc::new(RefCell::new(HaltonSampler::from_json(json))),
+        "sobol" => Rc::new(RefCell::new(SobolSampler::from_json(json))),
         _ => panic!("Invalid sampler type"),
     }
 }
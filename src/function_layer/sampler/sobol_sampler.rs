@@ -0,0 +1,75 @@
+use super::low_discrepancy::{
+    mix_bits, owen_scramble, permutation_element, pixel_hash, sobol_2d, u32_to_float,
+};
+use crate::function_layer::Sampler;
+use cgmath::Vector2;
+use serde_json::Value;
+
+/// Owen扰乱的Sobol采样：每一维(或每两维)使用Sobol (0, 2)序列的前两维，
+/// 按像素和维度的哈希置换样本下标并做Owen扰乱，以避免不同维之间的相关性。
+/// 每像素样本数为2的幂时分层效果最好
+pub struct SobolSampler {
+    pub x_samples: usize,
+    pub y_samples: usize,
+    seed: u64,
+    pixel: Vector2<usize>,
+    sample_index: usize,
+    dimension: usize,
+}
+
+impl SobolSampler {
+    pub fn from_json(json: &Value) -> Self {
+        let x_samples = json["xSamples"].as_u64().unwrap() as usize;
+        let y_samples = json["ySamples"].as_u64().unwrap() as usize;
+        let seed = json["seed"].as_u64().unwrap_or(0);
+        Self {
+            x_samples,
+            y_samples,
+            seed,
+            pixel: Vector2::new(0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // 当前维度对应的样本下标及扰乱种子
+    fn index_and_hash(&self) -> (u32, u64) {
+        let spp = (self.x_samples * self.y_samples) as u32;
+        let h = pixel_hash(self.pixel, self.dimension, self.seed);
+        let index = permutation_element(self.sample_index as u32 % spp, spp, h as u32);
+        (index, h)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn xsp(&self) -> usize {
+        self.x_samples
+    }
+
+    fn ysp(&self) -> usize {
+        self.y_samples
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Vector2<usize>, sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let (index, h) = self.index_and_hash();
+        let (x, _) = sobol_2d(index);
+        self.dimension += 1;
+        u32_to_float(owen_scramble(x, (h >> 32) as u32))
+    }
+
+    fn next_2d(&mut self) -> Vector2<f32> {
+        let (index, h) = self.index_and_hash();
+        let (x, y) = sobol_2d(index);
+        self.dimension += 2;
+        Vector2::new(
+            u32_to_float(owen_scramble(x, (h >> 32) as u32)),
+            u32_to_float(owen_scramble(y, mix_bits(h) as u32)),
+        )
+    }
+}
@@ -0,0 +1,97 @@
+use super::low_discrepancy::{hash, hash_float, permutation_element, pixel_hash};
+use crate::function_layer::Sampler;
+use cgmath::Vector2;
+use serde_json::Value;
+
+/// 分层采样：每个像素的第i个样本在每一维上落入随机置换后的第i个层中，
+/// 一维划分为spp层，二维划分为xSamples * ySamples的网格，层内随机抖动
+pub struct StratifiedSampler {
+    pub x_samples: usize,
+    pub y_samples: usize,
+    jitter: bool,
+    seed: u64,
+    pixel: Vector2<usize>,
+    sample_index: usize,
+    dimension: usize,
+}
+
+impl StratifiedSampler {
+    pub fn from_json(json: &Value) -> Self {
+        let x_samples = json["xSamples"].as_u64().unwrap() as usize;
+        let y_samples = json["ySamples"].as_u64().unwrap() as usize;
+        let jitter = json["jitter"].as_bool().unwrap_or(true);
+        let seed = json["seed"].as_u64().unwrap_or(0);
+        Self {
+            x_samples,
+            y_samples,
+            jitter,
+            seed,
+            pixel: Vector2::new(0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn spp(&self) -> usize {
+        self.x_samples * self.y_samples
+    }
+
+    // 层内的偏移，不抖动时取层的中心
+    fn delta(&self, axis: u64) -> f32 {
+        if self.jitter {
+            hash_float(hash(&[
+                self.pixel.x as u64,
+                self.pixel.y as u64,
+                self.sample_index as u64,
+                self.dimension as u64,
+                axis,
+                self.seed,
+            ]))
+        } else {
+            0.5
+        }
+    }
+
+    fn stratum(&self) -> usize {
+        let h = pixel_hash(self.pixel, self.dimension, self.seed);
+        permutation_element(
+            (self.sample_index % self.spp()) as u32,
+            self.spp() as u32,
+            h as u32,
+        ) as usize
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn xsp(&self) -> usize {
+        self.x_samples
+    }
+
+    fn ysp(&self) -> usize {
+        self.y_samples
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Vector2<usize>, sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let stratum = self.stratum();
+        let u = (stratum as f32 + self.delta(0)) / self.spp() as f32;
+        self.dimension += 1;
+        u
+    }
+
+    fn next_2d(&mut self) -> Vector2<f32> {
+        let stratum = self.stratum();
+        let (x, y) = (stratum % self.x_samples, stratum / self.x_samples);
+        let u = Vector2::new(
+            (x as f32 + self.delta(0)) / self.x_samples as f32,
+            (y as f32 + self.delta(1)) / self.y_samples as f32,
+        );
+        self.dimension += 2;
+        u
+    }
+}
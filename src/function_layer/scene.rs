@@ -3,31 +3,41 @@ use std::rc::Rc;
 use cgmath::{EuclideanSpace, Point3};
 use serde_json::Value;
 
+use crate::core_layer::colorspace::SpectrumRGB;
 use crate::core_layer::distribution::Distribution;
 use crate::function_layer::{
-    Acceleration, construct_light, construct_shape, create_acceleration, Light, Ray, RR,
-    set_acc_type, SurfaceInteraction,
+    Acceleration, construct_light, construct_shape, create_acceleration, Interaction, Light, Ray,
+    RR, Sampler, set_acc_type, SurfaceInteraction,
 };
 use crate::function_layer::light::{
     area_light::AreaLight, environment_light::EnvironmentLight, light::LightType,
 };
-use crate::function_layer::material::MaterialType;
+use crate::function_layer::material::{material::register_materials, MaterialType};
+use crate::function_layer::shape::instance::register_prototypes;
+use crate::function_layer::texture::texture::register_textures;
 
 pub struct Scene {
     pub infinite_lights: Vec<Rc<EnvironmentLight>>,
     acceleration: Box<dyn Acceleration>,
     light_distribution: Distribution<RR<dyn Light>>,
     black_hole_centers: Vec<Point3<f32>>,
+    // 是否有只划分介质的表面，没有时阴影光线遇到任何表面都被完全遮挡
+    has_interfaces: bool,
 }
 
 impl Scene {
     pub fn from_json(json: &Value) -> Self {
+        // 先注册具名纹理和材质，形状和光源中可以通过名称引用它们
+        register_textures(&json["textures"]);
+        register_materials(&json["materials"]);
+        register_prototypes(&json["prototypes"]);
         let mut geom_id = 0;
         let acc = json["acceleration"].as_str().unwrap_or("bvh");
         set_acc_type(acc);
         let mut acceleration = create_acceleration();
         let shapes = json["shapes"].as_array().unwrap();
         let mut black_hole_centers = vec![];
+        let mut has_interfaces = false;
         for shape in shapes {
             let shape = construct_shape(shape);
             if let Some(mat) = shape.borrow().material().as_ref() {
@@ -35,6 +45,8 @@ impl Scene {
                     black_hole_centers.push(Point3::from_vec(shape.borrow().get_bounds().centroid()));
                 }
             }
+            // 只有只划分介质的表面会被阴影光线穿过，实例和CSG中的形状也要检查
+            has_interfaces |= shape.borrow().has_interface();
             shape.borrow_mut().set_geometry_id(geom_id);
             geom_id += 1;
             acceleration.attach_shape(shape);
@@ -80,6 +92,7 @@ impl Scene {
             acceleration,
             light_distribution,
             black_hole_centers,
+            has_interfaces,
         }
     }
 
@@ -87,6 +100,46 @@ impl Scene {
         self.acceleration.get_intersect(ray)
     }
 
+    /// 阴影光线的遮挡查询，找到任意交点即返回
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        self.acceleration.occluded(ray)
+    }
+
+    /// 阴影光线上的透射率。穿过只划分介质的表面并切换介质，
+    /// 每段乘上所在介质的透射率，遇到其他表面时完全遮挡
+    pub fn transmittance(&self, ray: &Ray, sampler: RR<dyn Sampler>) -> SpectrumRGB {
+        if !self.has_interfaces {
+            if self.occluded(ray) {
+                return SpectrumRGB::same(0.0);
+            }
+            return match &ray.medium {
+                Some(medium) => medium.tr(ray, sampler),
+                None => SpectrumRGB::same(1.0),
+            };
+        }
+        let mut ray = ray.clone();
+        let mut tr = SpectrumRGB::same(1.0);
+        loop {
+            let mut segment = ray.clone();
+            // 没有交点时光线只经过当前介质
+            let Some(its) = self.ray_intersect(&mut segment) else {
+                if let Some(medium) = &ray.medium {
+                    tr *= &medium.tr(&ray, sampler);
+                }
+                return tr;
+            };
+            if !its.is_interface() {
+                return SpectrumRGB::same(0.0);
+            }
+            if let Some(medium) = &ray.medium {
+                tr *= &medium.tr(&segment, sampler.clone());
+            }
+            ray.medium = its.medium(ray.direction);
+            ray.origin = its.position;
+            ray.t_max -= segment.t_max;
+        }
+    }
+
     pub fn sample_light(&self, sample: f32, pdf: &mut f32) -> Option<RR<dyn Light>> {
         self.light_distribution.sample(sample, pdf)
     }
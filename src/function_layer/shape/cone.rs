@@ -14,6 +14,8 @@ pub struct Cone {
     radius: f32,
     height: f32,
     cos_theta: f32,
+    // 是否带有底面，封闭的圆锥才能作为CSG的子形状
+    closed: bool,
 }
 
 impl Cone {
@@ -23,6 +25,7 @@ impl Cone {
         let phi_max = json["phi_max"].as_f64().unwrap_or(2.0 * PI) as f32;
         let tan_theta = radius / height;
         let cos_theta = (1.0 / (1.0 + tan_theta * tan_theta)).sqrt();
+        let closed = json["closed"].as_bool().unwrap_or(false);
         let mut shape = ShapeBase::from_json(json);
         let bounds3 = Bounds3::new(
             V3f::new(-radius, -radius, 0.0),
@@ -35,8 +38,20 @@ impl Cone {
             radius,
             phi_max,
             cos_theta,
+            closed,
         }
     }
+
+    fn phi_of(p: Point3<f32>) -> f32 {
+        let mut its_phi = (p.y / p.x).atan();
+        if its_phi < 0.0 {
+            its_phi += PI as f32;
+        }
+        if p.y < 0.0 {
+            its_phi += PI as f32;
+        }
+        its_phi
+    }
 }
 
 impl Transformable for Cone {
@@ -68,15 +83,18 @@ impl Shape for Cone {
         let c = -co.z * -co.z - co.dot(co) * pw2_cos;
         let roots = solve_quadratic(a, b, c);
 
-        if roots.is_none() {
+        // 与侧面无交点的光线仍可能击中底面
+        if roots.is_none() && !self.closed {
             return None;
         }
 
-        let (t0, t1) = roots.unwrap(); // t0 <= t1
-                                       // check t0 first, if success, then skip t1
+        let (t0, t1) = roots.unwrap_or((f32::INFINITY, f32::INFINITY)); // t0 <= t1
+        let mut t_max = local_ray.t_max;
+        let mut hit = None;
 
+        // check t0 first, if success, then skip t1
         for tt in [t0, t1] {
-            if tt <= local_ray.t_min || tt >= local_ray.t_max {
+            if tt <= local_ray.t_min || tt >= t_max {
                 continue;
             }
             let p = local_ray.at(tt);
@@ -84,30 +102,40 @@ impl Shape for Cone {
                 continue;
             }
 
-            let mut its_phi = (p.y / p.x).atan();
-            if its_phi < 0.0 {
-                its_phi += PI as f32;
-            }
-            if p.y < 0.0 {
-                its_phi += PI as f32;
-            }
-
+            let its_phi = Self::phi_of(p);
             if its_phi > self.phi_max {
                 continue;
             }
 
-            ray.t_max = tt;
+            t_max = tt;
             let u = its_phi / self.phi_max;
             let v = p.z / self.height;
-            return Some((self.geometry_id(), u, v));
+            hit = Some((0, u, v));
+            break;
+        }
+        // 底面的prim_id为1，v为到轴线的距离与半径之比
+        if self.closed && d.z != 0.0 {
+            let tt = -o.z / d.z;
+            if tt > local_ray.t_min && tt < t_max {
+                let p = local_ray.at(tt);
+                let rho = (p.x * p.x + p.y * p.y).sqrt();
+                let its_phi = Self::phi_of(p);
+                if rho <= self.radius && its_phi <= self.phi_max {
+                    t_max = tt;
+                    hit = Some((1, its_phi / self.phi_max, rho / self.radius));
+                }
+            }
         }
-        None
+        if hit.is_some() {
+            ray.t_max = t_max;
+        }
+        hit
     }
 
     fn fill_intersection(
         &self,
         distance: f32,
-        _prim_id: u64,
+        prim_id: u64,
         u: f32,
         v: f32,
         medium: Option<Rc<dyn Medium>>,
@@ -115,6 +143,19 @@ impl Shape for Cone {
     ) {
         let trans = self.transform();
         let phi = u * self.phi_max;
+        if prim_id == 1 {
+            let rho = v * self.radius;
+            intersection.normal = trans.to_world_normal(V3f::new(0.0, 0.0, -1.0));
+            intersection.position =
+                trans.to_world_point(Point3::new(rho * phi.cos(), rho * phi.sin(), 0.0));
+            intersection.tex_coord = Vector2::new(u, v);
+            intersection.dp_du =
+                trans.to_world_vec(self.phi_max * V3f::new(-rho * phi.sin(), rho * phi.cos(), 0.0));
+            intersection.dp_dv =
+                trans.to_world_vec(self.radius * V3f::new(phi.cos(), phi.sin(), 0.0));
+            self._fill_intersection(distance, medium, intersection);
+            return;
+        }
         let z = v * self.height;
         let ck_norm = (self.height - z) / (self.cos_theta * self.cos_theta);
         let k = cgmath::Point3::from([0.0, 0.0, self.height - ck_norm]);
@@ -132,11 +173,18 @@ impl Shape for Cone {
             z,
         );
         let normal: V3f = (position - k).normalize();
-        intersection.normal = trans.to_world_vec(normal);
+        intersection.normal = trans.to_world_normal(normal);
 
         intersection.tex_coord = Vector2::new(u, v);
+        let r = self.radius * (1.0 - v);
+        intersection.dp_du =
+            trans.to_world_vec(self.phi_max * V3f::new(-r * phi.sin(), r * phi.cos(), 0.0));
+        intersection.dp_dv = trans.to_world_vec(V3f::new(
+            -self.radius * phi.cos(),
+            -self.radius * phi.sin(),
+            self.height,
+        ));
 
-        intersection.shape = Some(Rc::new(self.clone()));
         self._fill_intersection(distance, medium, intersection);
     }
 
@@ -0,0 +1,226 @@
+use super::shape::{construct_shape, Shape, ShapeBase};
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::material::MaterialType;
+use crate::function_layer::{Bounds3, Medium, Ray, SurfaceInteraction, V3f, RR};
+use cgmath::{InnerSpace, Vector2};
+use serde_json::Value;
+use std::rc::Rc;
+
+// 沿光线查找子形状边界的最大次数，防止退化情况下死循环
+const MAX_EVENTS: usize = 64;
+// 跳过已经处理过的交点，偏移量相对于交点的距离和CSG包围盒的尺寸
+const EVENT_EPSILON: f32 = 1e-4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, a: bool, b: bool) -> bool {
+        match self {
+            Self::Union => a || b,
+            Self::Intersection => a && b,
+            Self::Difference => a && !b,
+        }
+    }
+}
+
+// 子形状沿光线的一个边界点
+struct Event {
+    t: f32,
+    prim_id: u64,
+    u: f32,
+    v: f32,
+    entering: bool,
+}
+
+/// 两个封闭子形状的构造实体几何。子形状在世界空间中定义，不使用CSG自身的变换。
+/// prim_id的最低位为子形状下标，次低位表示法线是否需要翻转，其余位为子形状的prim_id，
+/// 因此嵌套的CSG每层占用两位
+#[derive(Clone)]
+pub struct Csg {
+    shape: ShapeBase,
+    operation: CsgOperation,
+    children: [RR<dyn Shape>; 2],
+    // 是否用CSG的材质替换子形状的材质
+    override_material: bool,
+}
+
+impl Csg {
+    pub fn from_json(json: &Value) -> Self {
+        let operation = match json["operation"].as_str().unwrap_or("union") {
+            "union" => CsgOperation::Union,
+            "intersection" => CsgOperation::Intersection,
+            "difference" => CsgOperation::Difference,
+            op => panic!("Invalid csg operation: {}", op),
+        };
+        // 子形状各自带有变换，CSG节点本身的变换不会作用到子形状上
+        if json.get("transform").is_some() {
+            panic!("Csg node can not have a transform, transform its children instead!");
+        }
+        let children = json["children"]
+            .as_array()
+            .filter(|c| c.len() == 2)
+            .expect("Csg needs exactly two children!");
+        let children = [construct_shape(&children[0]), construct_shape(&children[1])];
+        let (a, b) = (
+            children[0].borrow().get_bounds().clone(),
+            children[1].borrow().get_bounds().clone(),
+        );
+        let bounds = match operation {
+            CsgOperation::Union => Bounds3::union_bounds(&a, &b),
+            CsgOperation::Intersection => Bounds3::new(
+                V3f::new(
+                    a.p_min.x.max(b.p_min.x),
+                    a.p_min.y.max(b.p_min.y),
+                    a.p_min.z.max(b.p_min.z),
+                ),
+                V3f::new(
+                    a.p_max.x.min(b.p_max.x),
+                    a.p_max.y.min(b.p_max.y),
+                    a.p_max.z.min(b.p_max.z),
+                ),
+            ),
+            CsgOperation::Difference => a,
+        };
+        let mut shape = ShapeBase::from_json(json);
+        shape.set_bounds(bounds);
+        Self {
+            shape,
+            operation,
+            children,
+            override_material: json.get("material").is_some(),
+        }
+    }
+
+    // 子形状在t_min之后的第一个边界点，由法线与光线方向判断是进入还是离开
+    fn next_event(&self, index: usize, ray: &Ray, t_min: f32) -> Option<Event> {
+        let child = self.children[index].borrow();
+        let mut r = ray.clone();
+        r.t_min = t_min;
+        r.t_max = f32::INFINITY;
+        let (prim_id, u, v) = child.ray_intersect_shape(&mut r)?;
+        let mut its = SurfaceInteraction::default();
+        its.wo = -r.direction;
+        child.fill_intersection(r.t_max, prim_id, u, v, None, &mut its);
+        Some(Event {
+            t: r.t_max,
+            prim_id,
+            u,
+            v,
+            entering: its.normal.dot(ray.direction) < 0.0,
+        })
+    }
+}
+
+impl Transformable for Csg {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Csg {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let mut events = [
+            self.next_event(0, ray, ray.t_min),
+            self.next_event(1, ray, ray.t_min),
+        ];
+        // 第一个边界点是离开时，光线起点在子形状内部
+        let mut inside = [0, 1].map(|i| events[i].as_ref().is_some_and(|e| !e.entering));
+        let mut state = self.operation.inside(inside[0], inside[1]);
+        for _ in 0..MAX_EVENTS {
+            let index = match (&events[0], &events[1]) {
+                (Some(a), Some(b)) => {
+                    if a.t <= b.t {
+                        0
+                    } else {
+                        1
+                    }
+                }
+                (Some(_), None) => 0,
+                (None, Some(_)) => 1,
+                (None, None) => return None,
+            };
+            let event = events[index].take().unwrap();
+            if event.t >= ray.t_max {
+                return None;
+            }
+            inside[index] = event.entering;
+            let new_state = self.operation.inside(inside[0], inside[1]);
+            if new_state != state {
+                // 差集中被减去形状的表面，法线需要指向其内部
+                let flip = (self.operation == CsgOperation::Difference && index == 1) as u64;
+                ray.t_max = event.t;
+                return Some((
+                    (event.prim_id << 2) | (flip << 1) | index as u64,
+                    event.u,
+                    event.v,
+                ));
+            }
+            state = new_state;
+            // 远处或较大的形状上t的浮点误差更大，偏移量随之增大
+            let extent = self.get_bounds().diagonal().magnitude() / ray.direction.magnitude();
+            let offset = EVENT_EPSILON * event.t.max(extent);
+            events[index] = self.next_event(index, ray, event.t + offset);
+        }
+        None
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let handle = &self.children[(prim_id & 1) as usize];
+        handle
+            .borrow()
+            .fill_intersection(distance, prim_id >> 2, u, v, medium, intersection);
+        if prim_id & 2 != 0 {
+            intersection.normal = -intersection.normal;
+            intersection.bitangent = -intersection.bitangent;
+        }
+        // 替换材质时由加速结构将交点的形状设置为CSG本身
+        if self.override_material {
+            // 子形状可能按面设置了材质，一并清除
+            intersection.shape = None;
+            intersection.material = None;
+        } else if intersection.shape.is_none() {
+            intersection.shape = Some(handle.clone());
+        }
+    }
+
+    fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        panic!("Csg shape does not support area sampling!")
+    }
+
+    fn has_interface(&self) -> bool {
+        // 替换材质时子形状的材质不会被用到
+        if self.override_material {
+            return self
+                .material()
+                .is_some_and(|m| m.mat_type() == MaterialType::Interface);
+        }
+        self.children.iter().any(|c| c.borrow().has_interface())
+    }
+
+    fn init_internal_acceleration(&mut self) {
+        for child in &self.children {
+            child.borrow_mut().init_internal_acceleration();
+        }
+    }
+}
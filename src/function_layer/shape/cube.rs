@@ -111,8 +111,15 @@ impl Shape for Cube {
         intersection.position =
             Point3::from_homogeneous(trans.translate * trans.rotate * hit_point.to_homogeneous());
         intersection.tex_coord = Vector2::new(u, v);
+        let axis_u = (p_id / 2 + 1) % 3;
+        let axis_v = (axis_u + 1) % 3;
+        let mut dp_du = V3f::zero();
+        dp_du[axis_u] = self.box_max[axis_u] - self.box_min[axis_u];
+        let mut dp_dv = V3f::zero();
+        dp_dv[axis_v] = self.box_max[axis_v] - self.box_min[axis_v];
+        intersection.dp_du = (trans.rotate * dp_du.extend(0.0)).truncate();
+        intersection.dp_dv = (trans.rotate * dp_dv.extend(0.0)).truncate();
 
-        intersection.shape = Some(Rc::new(self.clone()));
         self._fill_intersection(distance, medium, intersection);
     }
 
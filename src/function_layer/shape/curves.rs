@@ -0,0 +1,437 @@
+use super::shape::{Shape, ShapeBase};
+use crate::core_layer::function::lerp;
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::acceleration::PrimitiveBVH;
+use crate::function_layer::{Bounds3, Medium, Ray, SurfaceInteraction, V3f};
+use crate::resource_layer::hair::{load_hair, HairStrand};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2};
+use serde_json::Value;
+use std::rc::Rc;
+
+#[derive(Copy, Clone, PartialEq)]
+enum CurveType {
+    // 始终正对光线的平面带
+    Flat,
+    // 与平面带求交，但着色法线沿宽度方向旋转，看起来是圆柱
+    Cylinder,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum CurveBasis {
+    Bezier,
+    BSpline,
+}
+
+/// 一段三次Bézier曲线
+struct CurveSegment {
+    cp: [V3f; 4],
+    // 两个端点处的宽度，中间线性插值
+    width: [f32; 2],
+    // 在整根曲线上的参数范围，用作纹理坐标u
+    u_range: [f32; 2],
+    // 递归细分求交的深度
+    max_depth: u32,
+}
+
+pub struct Curves {
+    shape: ShapeBase,
+    curve_type: CurveType,
+    // 构建加速结构后变换到世界坐标
+    segments: Vec<CurveSegment>,
+    bvh: Option<PrimitiveBVH>,
+}
+
+impl Curves {
+    pub fn from_json(json: &Value) -> Self {
+        let shape = ShapeBase::from_json(json);
+        let file_path = json["file"].as_str().expect("No curve file given!");
+        let curve_type = match json["curveType"].as_str().unwrap_or("cylinder") {
+            "flat" => CurveType::Flat,
+            "cylinder" => CurveType::Cylinder,
+            tp => panic!("Invalid curve type: {}!", tp),
+        };
+        let basis = match json["basis"].as_str().unwrap_or("bspline") {
+            "bezier" => CurveBasis::Bezier,
+            "bspline" => CurveBasis::BSpline,
+            tp => panic!("Invalid curve basis: {}!", tp),
+        };
+        // 文件中没有宽度时，宽度从根部的width线性变化到末端的widthTip
+        let width = json["width"].as_f64().unwrap_or(0.01) as f32;
+        let width_tip = json["widthTip"].as_f64().map_or(width, |w| w as f32);
+        let segments = load_hair(file_path)
+            .iter()
+            .flat_map(|strand| strand_segments(strand, basis, [width, width_tip]))
+            .collect();
+        Self {
+            shape,
+            curve_type,
+            segments,
+            bvh: None,
+        }
+    }
+
+    // 在光线坐标系(光线起点为原点，方向为z轴)中递归细分曲线求交，
+    // 找到更近的交点时更新z_max和hit(u, v)
+    #[allow(clippy::too_many_arguments)]
+    fn recursive_intersect(
+        seg: &CurveSegment,
+        cp: &[V3f; 4],
+        u0: f32,
+        u1: f32,
+        depth: u32,
+        z_min: f32,
+        z_max: &mut f32,
+        hit: &mut Option<(f32, f32)>,
+    ) {
+        if depth > 0 {
+            let halves = subdivide_bezier(cp);
+            let u = [u0, (u0 + u1) * 0.5, u1];
+            for (i, half) in halves.iter().enumerate() {
+                let max_width = seg.width_at(u[i]).max(seg.width_at(u[i + 1])) * 0.5;
+                let mut b = Bounds3::empty();
+                half.iter().for_each(|&p| b.expand(p));
+                if b.p_max.x + max_width < 0.0
+                    || b.p_min.x - max_width > 0.0
+                    || b.p_max.y + max_width < 0.0
+                    || b.p_min.y - max_width > 0.0
+                    || b.p_max.z + max_width < z_min
+                    || b.p_min.z - max_width > *z_max
+                {
+                    continue;
+                }
+                Self::recursive_intersect(seg, half, u[i], u[i + 1], depth - 1, z_min, z_max, hit);
+            }
+            return;
+        }
+
+        // 原点需要位于曲线段两端切线的垂面之间
+        let edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        if edge < 0.0 {
+            return;
+        }
+        let edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if edge < 0.0 {
+            return;
+        }
+        // 将曲线段近似为直线，求与原点最近的参数w
+        let dir = Vector2::new(cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let denom = dir.magnitude2();
+        if denom == 0.0 {
+            return;
+        }
+        let w = -(cp[0].x * dir.x + cp[0].y * dir.y) / denom;
+        let u = lerp(w, u0, u1).clamp(u0, u1);
+        let hit_width = seg.width_at(u);
+        let (pc, dpcdw) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        let dist2 = pc.x * pc.x + pc.y * pc.y;
+        if dist2 > hit_width * hit_width * 0.25 || pc.z < z_min || pc.z > *z_max {
+            return;
+        }
+        // 光线起点位于这段曲线内部(如从发丝表面出发的光线)时，只接受光线离开曲线之后的交点
+        if pc.z < exit_distance(cp, hit_width * 0.5) {
+            return;
+        }
+        let dist = dist2.sqrt();
+        let edge = dpcdw.x * -pc.y + pc.x * dpcdw.y;
+        let v = if edge > 0.0 {
+            0.5 + dist / hit_width
+        } else {
+            0.5 - dist / hit_width
+        };
+        *z_max = pc.z;
+        *hit = Some((u, v));
+    }
+
+    fn intersect_segment(&self, seg: &CurveSegment, ray: &Ray) -> Option<(f32, f32, f32)> {
+        // 以光线方向为z轴的正交坐标系
+        let len = ray.direction.magnitude();
+        let z = ray.direction / len;
+        let x = if z.x.abs() > 0.9 {
+            V3f::unit_y().cross(z).normalize()
+        } else {
+            V3f::unit_x().cross(z).normalize()
+        };
+        let y = z.cross(x);
+        let o = ray.origin.to_vec();
+        let cp = seg.cp.map(|p| {
+            let p = p - o;
+            V3f::new(p.dot(x), p.dot(y), p.dot(z))
+        });
+        let mut z_max = ray.t_max * len;
+        let mut hit = None;
+        Self::recursive_intersect(
+            seg,
+            &cp,
+            0.0,
+            1.0,
+            seg.max_depth,
+            ray.t_min * len,
+            &mut z_max,
+            &mut hit,
+        );
+        hit.map(|(u, v)| (z_max / len, u, v))
+    }
+}
+
+impl CurveSegment {
+    fn width_at(&self, u: f32) -> f32 {
+        lerp(u, self.width[0], self.width[1])
+    }
+
+    // 与pbrt相同，按曲线的弯曲程度和宽度估计细分深度
+    fn compute_max_depth(&mut self) {
+        let cp = &self.cp;
+        let mut l0: f32 = 0.0;
+        for i in 0..2 {
+            let d = cp[i] - cp[i + 1] * 2.0 + cp[i + 2];
+            l0 = l0.max(d.x.abs()).max(d.y.abs()).max(d.z.abs());
+        }
+        let eps = self.width[0].max(self.width[1]) * 0.05;
+        let r0 = (std::f32::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0;
+        self.max_depth = if r0.is_finite() {
+            r0.clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+    }
+
+    fn bounds(&self) -> Bounds3 {
+        let mut b = Bounds3::empty();
+        self.cp.iter().for_each(|&p| b.expand(p));
+        let r = V3f::from([self.width[0].max(self.width[1]) * 0.5; 3]);
+        Bounds3::new(b.p_min - r, b.p_max + r)
+    }
+}
+
+// 将一根发丝的控制点转换为若干段三次Bézier曲线
+fn strand_segments(strand: &HairStrand, basis: CurveBasis, width: [f32; 2]) -> Vec<CurveSegment> {
+    let p: Vec<V3f> = strand.points.iter().map(|p| p.to_vec()).collect();
+    let n = p.len();
+    let count = match basis {
+        CurveBasis::Bezier => {
+            if !(n - 1).is_multiple_of(3) {
+                eprintln!(
+                    "Warning: bezier curve with {} points, extra points ignored",
+                    n
+                );
+            }
+            (n - 1) / 3
+        }
+        CurveBasis::BSpline => n - 1,
+    };
+    // B样条在两端各补一个镜像的控制点，使曲线经过首尾两点
+    let ext: Vec<V3f> = match basis {
+        CurveBasis::Bezier => vec![],
+        CurveBasis::BSpline => std::iter::once(p[0] * 2.0 - p[1])
+            .chain(p.iter().copied())
+            .chain(std::iter::once(p[n - 1] * 2.0 - p[n - 2]))
+            .collect(),
+    };
+    (0..count)
+        .map(|i| {
+            let (cp, a, b) = match basis {
+                CurveBasis::Bezier => (
+                    [p[3 * i], p[3 * i + 1], p[3 * i + 2], p[3 * i + 3]],
+                    3 * i,
+                    3 * i + 3,
+                ),
+                CurveBasis::BSpline => {
+                    let q = &ext[i..i + 4];
+                    let cp = [
+                        (q[0] + q[1] * 4.0 + q[2]) / 6.0,
+                        (q[1] * 2.0 + q[2]) / 3.0,
+                        (q[1] + q[2] * 2.0) / 3.0,
+                        (q[1] + q[2] * 4.0 + q[3]) / 6.0,
+                    ];
+                    (cp, i, i + 1)
+                }
+            };
+            let u_range = [i as f32 / count as f32, (i + 1) as f32 / count as f32];
+            let width = if strand.widths.is_empty() {
+                u_range.map(|u| lerp(u, width[0], width[1]))
+            } else {
+                [strand.widths[a], strand.widths[b]]
+            };
+            CurveSegment {
+                cp,
+                width,
+                u_range,
+                max_depth: 0,
+            }
+        })
+        .collect()
+}
+
+// 光线坐标系中，起点位于以cp[0]到cp[3]的连线为轴、半径为radius的圆柱内部时，
+// 返回光线离开圆柱的距离，否则返回0
+fn exit_distance(cp: &[V3f; 4], radius: f32) -> f32 {
+    let axis = cp[3] - cp[0];
+    if axis.magnitude2() == 0.0 {
+        return 0.0;
+    }
+    let axis = axis.normalize();
+    // 起点和光线方向垂直于轴的分量
+    let a = -cp[0] + axis * cp[0].dot(axis);
+    let d = V3f::unit_z() - axis * axis.z;
+    // 留出一点余量，使从曲线边缘出发的光线也被视为在内部
+    let c = a.magnitude2() - radius * radius * 1.001;
+    if c > 0.0 {
+        return 0.0;
+    }
+    let (qa, qb) = (d.magnitude2(), a.dot(d));
+    if qa < 1e-12 {
+        // 光线与轴平行，始终在曲线内部
+        return f32::INFINITY;
+    }
+    (-qb + (qb * qb - qa * c).sqrt()) / qa
+}
+
+// 平面带的宽度方向同时垂直于曲线和光线，两者平行时任取一个垂直于曲线的方向
+fn ribbon_direction(direction: V3f, dpdu: V3f) -> V3f {
+    let dpdv = direction.cross(dpdu);
+    if dpdv.magnitude2() < 1e-12 {
+        let t = dpdu.normalize();
+        let axis = if t.x.abs() > 0.9 {
+            V3f::unit_y()
+        } else {
+            V3f::unit_x()
+        };
+        return axis.cross(t).normalize();
+    }
+    dpdv.normalize()
+}
+
+// 返回曲线在u处的位置和导数
+fn eval_bezier(cp: &[V3f; 4], u: f32) -> (V3f, V3f) {
+    let l = |a: V3f, b: V3f| a * (1.0 - u) + b * u;
+    let cp1 = [l(cp[0], cp[1]), l(cp[1], cp[2]), l(cp[2], cp[3])];
+    let cp2 = [l(cp1[0], cp1[1]), l(cp1[1], cp1[2])];
+    let deriv = if (cp2[1] - cp2[0]).magnitude2() > 0.0 {
+        (cp2[1] - cp2[0]) * 3.0
+    } else {
+        // 控制点重合时导数退化，近似为首尾连线
+        cp[3] - cp[0]
+    };
+    (l(cp2[0], cp2[1]), deriv)
+}
+
+// 在中点处将曲线分为两段
+fn subdivide_bezier(cp: &[V3f; 4]) -> [[V3f; 4]; 2] {
+    let mid = (cp[0] + cp[1] * 3.0 + cp[2] * 3.0 + cp[3]) / 8.0;
+    [
+        [
+            cp[0],
+            (cp[0] + cp[1]) / 2.0,
+            (cp[0] + cp[1] * 2.0 + cp[2]) / 4.0,
+            mid,
+        ],
+        [
+            mid,
+            (cp[1] + cp[2] * 2.0 + cp[3]) / 4.0,
+            (cp[2] + cp[3]) / 2.0,
+            cp[3],
+        ],
+    ]
+}
+
+impl Transformable for Curves {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Curves {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let bvh = self.bvh.as_ref()?;
+        let mut hit = None;
+        bvh.traverse(ray, |index, ray| {
+            if let Some((t, u, v)) = self.intersect_segment(&self.segments[index], ray) {
+                ray.t_max = t;
+                hit = Some((index as u64, u, v));
+            }
+            false
+        });
+        hit
+    }
+
+    fn ray_occluded(&self, ray: &Ray) -> bool {
+        let Some(bvh) = &self.bvh else {
+            return false;
+        };
+        bvh.traverse(&mut ray.clone(), |index, ray| {
+            self.intersect_segment(&self.segments[index], ray).is_some()
+        })
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let seg = &self.segments[prim_id as usize];
+        let (center, dpdu) = eval_bezier(&seg.cp, u);
+        let width = seg.width_at(u);
+        // 由光线方向恢复平面带的宽度方向，交点位于其上v处
+        let mut dpdv = ribbon_direction(-intersection.wo, dpdu) * width;
+        let position = Point3::from_vec(center + dpdv * (v - 0.5));
+        if self.curve_type == CurveType::Cylinder {
+            // 绕曲线方向旋转宽度方向，v从0到1对应圆柱截面上-90°到90°
+            let axis = dpdu.normalize();
+            let (sin, cos) = (-lerp(v, -90.0f32, 90.0).to_radians()).sin_cos();
+            dpdv = dpdv * cos + axis.cross(dpdv) * sin + axis * axis.dot(dpdv) * (1.0 - cos);
+        }
+        // 法线朝向光线的来向
+        let normal = -dpdu.cross(dpdv).normalize();
+
+        let [u0, u1] = seg.u_range;
+        intersection.position = position;
+        intersection.normal = normal;
+        intersection.tex_coord = Vector2::new(lerp(u, u0, u1), v);
+        intersection.dp_du = dpdu / (u1 - u0);
+        intersection.dp_dv = dpdv;
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        // TODO finish this
+        (SurfaceInteraction::default(), 0.0)
+    }
+
+    fn init_internal_acceleration(&mut self) {
+        if self.bvh.is_some() {
+            return;
+        }
+        // 控制点变换到世界坐标，宽度按平均缩放比例缩放
+        let trans = self.shape.transform().clone();
+        let scale = (trans.scale[0][0] + trans.scale[1][1] + trans.scale[2][2]) / 3.0;
+        for seg in self.segments.iter_mut() {
+            seg.cp = seg
+                .cp
+                .map(|p| trans.to_world_point(Point3::from_vec(p)).to_vec());
+            seg.width = seg.width.map(|w| w * scale);
+            seg.compute_max_depth();
+        }
+        let bounds: Vec<Bounds3> = self.segments.iter().map(|s| s.bounds()).collect();
+        let bvh = PrimitiveBVH::new(&bounds);
+        if let Some(b) = bvh.bounds() {
+            self.shape.set_bounds(b.clone());
+        }
+        self.bvh = Some(bvh);
+    }
+
+    fn shape_type(&self) -> String {
+        "Curves".to_owned()
+    }
+}
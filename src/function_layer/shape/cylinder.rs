@@ -13,6 +13,8 @@ pub struct Cylinder {
     height: f32,
     radius: f32,
     phi_max: f32,
+    // 是否带有上下两个端面，封闭的圆柱才能作为CSG的子形状
+    closed: bool,
 }
 
 impl Cylinder {
@@ -20,6 +22,7 @@ impl Cylinder {
         let radius = json["radius"].as_f64().unwrap_or(1.0) as f32;
         let height = json["height"].as_f64().unwrap_or(1.0) as f32;
         let phi_max = json["phi_max"].as_f64().unwrap_or(2.0 * PI) as f32;
+        let closed = json["closed"].as_bool().unwrap_or(false);
 
         let mut shape = ShapeBase::from_json(json);
         let bounds3 = Bounds3::new(
@@ -33,8 +36,20 @@ impl Cylinder {
             height,
             radius,
             phi_max,
+            closed,
         }
     }
+
+    fn phi_of(p: Point3<f32>) -> f32 {
+        let mut its_phi = (p.y / p.x).atan();
+        if its_phi < 0.0 {
+            its_phi += PI as f32;
+        }
+        if p.y < 0.0 {
+            its_phi += PI as f32;
+        }
+        its_phi
+    }
 }
 
 impl Transformable for Cylinder {
@@ -62,15 +77,18 @@ impl Shape for Cylinder {
         let c = l_origin.x * l_origin.x + l_origin.y * l_origin.y - self.radius * self.radius;
         let roots = solve_quadratic(a, b, c);
 
-        if roots.is_none() {
+        // 与轴平行的光线仍可能击中端面
+        if roots.is_none() && !self.closed {
             return None;
         }
 
-        let (t0, t1) = roots.unwrap(); // t0 <= t1
+        let (t0, t1) = roots.unwrap_or((f32::INFINITY, f32::INFINITY)); // t0 <= t1
+        let mut t_max = local_ray.t_max;
+        let mut hit = None;
 
         // check t0 first, if success, then skip t1
         for tt in [t0, t1] {
-            if tt <= local_ray.t_min || tt >= local_ray.t_max {
+            if tt <= local_ray.t_min || tt >= t_max {
                 continue;
             }
             let p = local_ray.at(tt);
@@ -78,28 +96,42 @@ impl Shape for Cylinder {
                 continue;
             }
 
-            let mut its_phi = (p.y / p.x).atan();
-            if its_phi < 0.0 {
-                its_phi += PI as f32;
-            }
-            if p.y < 0.0 {
-                its_phi += PI as f32;
-            }
+            let its_phi = Self::phi_of(p);
             // if its_phi > 5.0 { print!("{its_phi}:{}\t", self.phi_max); }
             if its_phi <= self.phi_max {
-                ray.t_max = tt;
+                t_max = tt;
                 let u = its_phi / self.phi_max;
                 let v = p.z / self.height;
-                return Some((self.geometry_id(), u, v));
+                hit = Some((0, u, v));
+                break;
             }
         }
-        None
+        // 端面：prim_id为1表示底面，2表示顶面，v为到轴线的距离与半径之比
+        if self.closed && l_dir.z != 0.0 {
+            for (prim_id, z) in [(1, 0.0), (2, self.height)] {
+                let tt = (z - l_origin.z) / l_dir.z;
+                if tt <= local_ray.t_min || tt >= t_max {
+                    continue;
+                }
+                let p = local_ray.at(tt);
+                let rho = (p.x * p.x + p.y * p.y).sqrt();
+                let its_phi = Self::phi_of(p);
+                if rho <= self.radius && its_phi <= self.phi_max {
+                    t_max = tt;
+                    hit = Some((prim_id, its_phi / self.phi_max, rho / self.radius));
+                }
+            }
+        }
+        if hit.is_some() {
+            ray.t_max = t_max;
+        }
+        hit
     }
 
     fn fill_intersection(
         &self,
         distance: f32,
-        _prim_id: u64,
+        prim_id: u64,
         u: f32,
         v: f32,
         medium: Option<Rc<dyn Medium>>,
@@ -107,8 +139,26 @@ impl Shape for Cylinder {
     ) {
         let trans = self.transform();
         let phi = u * self.phi_max;
+        if prim_id != 0 {
+            let (z, nz) = if prim_id == 1 {
+                (0.0, -1.0)
+            } else {
+                (self.height, 1.0)
+            };
+            let rho = v * self.radius;
+            intersection.normal = trans.to_world_normal(V3f::new(0.0, 0.0, nz));
+            intersection.position =
+                trans.to_world_point(Point3::new(rho * phi.cos(), rho * phi.sin(), z));
+            intersection.tex_coord = Vector2::new(u, v);
+            intersection.dp_du =
+                trans.to_world_vec(self.phi_max * V3f::new(-rho * phi.sin(), rho * phi.cos(), 0.0));
+            intersection.dp_dv =
+                trans.to_world_vec(self.radius * V3f::new(phi.cos(), phi.sin(), 0.0));
+            self._fill_intersection(distance, medium, intersection);
+            return;
+        }
         let normal = V3f::new(phi.cos(), phi.sin(), 0.0);
-        intersection.normal = trans.to_world_vec(normal);
+        intersection.normal = trans.to_world_normal(normal);
 
         let position = Point3::new(
             self.radius * phi.cos(),
@@ -117,8 +167,11 @@ impl Shape for Cylinder {
         );
         intersection.position = trans.to_world_point(position);
         intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du = trans.to_world_vec(
+            self.phi_max * V3f::new(-self.radius * phi.sin(), self.radius * phi.cos(), 0.0),
+        );
+        intersection.dp_dv = trans.to_world_vec(V3f::new(0.0, 0.0, self.height));
 
-        intersection.shape = Some(Rc::new(self.clone()));
         self._fill_intersection(distance, medium, intersection);
     }
 
@@ -101,7 +101,7 @@ impl Shape for Disk {
     ) {
         let trans = self.transform();
         let normal = V3f::new(0.0, 0.0, 1.0);
-        intersection.normal = trans.to_world_vec(normal);
+        intersection.normal = trans.to_world_normal(normal);
 
         // TODO: uniformly sampling
         let r = v * (self.radius - self.inner_radius) + self.inner_radius;
@@ -110,8 +110,12 @@ impl Shape for Disk {
         intersection.position = trans.to_world_point(position);
 
         intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du =
+            trans.to_world_vec(self.phi_max * V3f::new(-r * phi.sin(), r * phi.cos(), 0.0));
+        intersection.dp_dv = trans.to_world_vec(
+            (self.radius - self.inner_radius) * V3f::new(phi.cos(), phi.sin(), 0.0),
+        );
 
-        intersection.shape = Some(Rc::new(self.clone()));
         self._fill_intersection(distance, medium, intersection);
     }
 
@@ -0,0 +1,195 @@
+use super::shape::ShapeBase;
+use crate::core_layer::function::solve_quadratic_or_linear;
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::{fetch_v3f, Bounds3, Medium, Ray, Shape, SurfaceInteraction, V3f};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2};
+use serde_json::Value;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+// 采样时沿v方向预计算面积分布的分段数
+const AREA_SEGMENTS: usize = 64;
+
+/// 线段p1p2绕z轴旋转得到的单叶双曲面，隐式方程为ah(x^2 + y^2) - ch * z^2 = 1
+#[derive(Clone)]
+pub struct Hyperboloid {
+    pub shape: ShapeBase,
+    p1: V3f,
+    p2: V3f,
+    ah: f32,
+    ch: f32,
+    phi_max: f32,
+    // 面积沿v方向的累积分布，用于按面积均匀采样
+    area_cdf: Vec<f32>,
+    pdf: f32,
+}
+
+impl Hyperboloid {
+    pub fn from_json(json: &Value) -> Self {
+        let mut p1 = fetch_v3f(json, "p1", V3f::new(1.0, 0.0, 0.0));
+        let mut p2 = fetch_v3f(json, "p2", V3f::new(0.0, 1.0, 1.0));
+        let phi_max = json["phi_max"].as_f64().unwrap_or(2.0 * PI) as f32;
+        if p1.z == p2.z {
+            panic!("The two points of a hyperboloid must have different z!");
+        }
+        // 过原点的直线旋转得到圆锥，无法用隐式方程表示，求解系数时不会收敛
+        if p1.cross(p2).magnitude2() == 0.0 {
+            panic!("The line of a hyperboloid must not pass through the origin!");
+        }
+        if phi_max <= 0.0 || phi_max > 2.0 * PI as f32 {
+            panic!("Invalid phi_max of hyperboloid!");
+        }
+        if p1.z > p2.z {
+            std::mem::swap(&mut p1, &mut p2);
+        }
+        let r_max = p1.truncate().magnitude().max(p2.truncate().magnitude());
+        let mut shape = ShapeBase::from_json(json);
+        let bounds3 = Bounds3::new(V3f::new(-r_max, -r_max, p1.z), V3f::new(r_max, r_max, p2.z));
+        shape.bounds3 = shape.transform.to_world_bounds3(bounds3);
+
+        // 与pbrt相同，沿直线取另一点求解隐式方程的系数
+        let (a, b) = if p2.z == 0.0 { (p2, p1) } else { (p1, p2) };
+        let mut pp = a;
+        let (mut ah, mut ch);
+        loop {
+            pp += 2.0 * (b - a);
+            let xy1 = pp.x * pp.x + pp.y * pp.y;
+            let xy2 = b.x * b.x + b.y * b.y;
+            ah = (1.0 / xy1 - (pp.z * pp.z) / (xy1 * b.z * b.z))
+                / (1.0 - (xy2 * pp.z * pp.z) / (xy1 * b.z * b.z));
+            ch = (ah * xy2 - 1.0) / (b.z * b.z);
+            if ah.is_finite() && ch.is_finite() {
+                break;
+            }
+        }
+
+        let mut hyperboloid = Self {
+            shape,
+            p1,
+            p2,
+            ah,
+            ch,
+            phi_max,
+            area_cdf: vec![],
+            pdf: 0.0,
+        };
+        // 由旋转对称性，|dp_du x dp_dv|只与v有关，按梯形公式积分
+        let density: Vec<f32> = (0..=AREA_SEGMENTS)
+            .map(|i| {
+                let (_, dp_du, dp_dv) =
+                    hyperboloid.local_geometry(0.0, i as f32 / AREA_SEGMENTS as f32);
+                dp_du.cross(dp_dv).magnitude()
+            })
+            .collect();
+        let mut cdf = vec![0.0];
+        for i in 0..AREA_SEGMENTS {
+            let last = cdf[i];
+            cdf.push(last + (density[i] + density[i + 1]) * 0.5 / AREA_SEGMENTS as f32);
+        }
+        let area = cdf[AREA_SEGMENTS];
+        hyperboloid.area_cdf = cdf.iter().map(|c| c / area).collect();
+        hyperboloid.pdf = 1.0 / area;
+        hyperboloid
+    }
+
+    // 局部坐标系中(u, v)处的位置和偏导
+    fn local_geometry(&self, u: f32, v: f32) -> (V3f, V3f, V3f) {
+        let phi = u * self.phi_max;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let rotate = |p: V3f| {
+            V3f::new(
+                p.x * cos_phi - p.y * sin_phi,
+                p.x * sin_phi + p.y * cos_phi,
+                p.z,
+            )
+        };
+        let position = rotate(self.p1 * (1.0 - v) + self.p2 * v);
+        let dp_du = self.phi_max * V3f::new(-position.y, position.x, 0.0);
+        let dp_dv = rotate(self.p2 - self.p1);
+        (position, dp_du, dp_dv)
+    }
+}
+
+impl Transformable for Hyperboloid {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Hyperboloid {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let trans = self.transform();
+        let o = trans.to_local_point(ray.origin);
+        let d = trans.to_local_vec(ray.direction);
+        let a = self.ah * (d.x * d.x + d.y * d.y) - self.ch * d.z * d.z;
+        let b = 2.0 * (self.ah * (d.x * o.x + d.y * o.y) - self.ch * d.z * o.z);
+        let c = self.ah * (o.x * o.x + o.y * o.y) - self.ch * o.z * o.z - 1.0;
+        for t in solve_quadratic_or_linear(a, b, c) {
+            if t <= ray.t_min || t >= ray.t_max {
+                continue;
+            }
+            let p = o + d * t;
+            if p.z < self.p1.z || p.z > self.p2.z {
+                continue;
+            }
+            // 相对于线段上同一高度的点绕z轴转过的角度
+            let v = (p.z - self.p1.z) / (self.p2.z - self.p1.z);
+            let pr = self.p1 * (1.0 - v) + self.p2 * v;
+            let phi = (p.y.atan2(p.x) - pr.y.atan2(pr.x)).rem_euclid(2.0 * PI as f32);
+            if phi > self.phi_max {
+                continue;
+            }
+            ray.t_max = t;
+            return Some((0, phi / self.phi_max, v));
+        }
+        None
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        _prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let trans = self.transform();
+        let (position, dp_du, dp_dv) = self.local_geometry(u, v);
+        // 隐式方程的梯度，背向z轴
+        let normal = V3f::new(
+            self.ah * position.x,
+            self.ah * position.y,
+            -self.ch * position.z,
+        );
+        intersection.position = trans.to_world_point(Point3::from_vec(position));
+        intersection.normal = trans.to_world_normal(normal.normalize());
+        intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du = trans.to_world_vec(dp_du);
+        intersection.dp_dv = trans.to_world_vec(dp_dv);
+
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        // 在预计算的分段线性CDF中查找v
+        let cdf = &self.area_cdf;
+        let i = cdf
+            .partition_point(|&c| c <= sample.y)
+            .clamp(1, AREA_SEGMENTS)
+            - 1;
+        let t = (sample.y - cdf[i]) / (cdf[i + 1] - cdf[i]).max(1e-12);
+        let v = (i as f32 + t.clamp(0.0, 1.0)) / AREA_SEGMENTS as f32;
+        let mut its = SurfaceInteraction::default();
+        self.fill_intersection(0.0, 0, sample.x, v, None, &mut its);
+        (its, self.pdf)
+    }
+}
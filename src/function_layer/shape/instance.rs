@@ -0,0 +1,203 @@
+use super::shape::{construct_shape, Shape, ShapeBase};
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::material::MaterialType;
+use crate::function_layer::{create_acceleration, Acceleration, Medium, Ray, SurfaceInteraction};
+use cgmath::{InnerSpace, Vector2};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// 原型中形状的prim_id占用的低48位
+const PRIM_MASK: u64 = (1 << 48) - 1;
+
+/// 原型：一组在原型空间中定义的形状及其加速结构，被所有实例共享
+pub struct Prototype {
+    acc: Box<dyn Acceleration>,
+}
+
+impl Prototype {
+    pub fn from_json(json: &Value) -> Self {
+        let mut acc = create_acceleration();
+        let shapes = match json.as_array() {
+            Some(shapes) => shapes.clone(),
+            None => vec![json.clone()],
+        };
+        for (geom_id, shape) in shapes.iter().enumerate() {
+            // 实例把原型中的形状编号和图元编号打包进一个u64，嵌套的实例会被截断
+            if shape["type"].as_str() == Some("instance") {
+                panic!("Instances can not be nested in prototypes!");
+            }
+            if geom_id >> 16 != 0 {
+                panic!("Too many shapes in prototype!");
+            }
+            let shape = construct_shape(shape);
+            shape.borrow_mut().set_geometry_id(geom_id as u64);
+            acc.attach_shape(shape);
+        }
+        acc.build();
+        Self { acc }
+    }
+}
+
+thread_local! {
+    // 场景中"prototypes"字段定义的原型，第一次被实例引用时构造
+    static PROTOTYPE_LIBRARY: RefCell<HashMap<String, Value>> = RefCell::new(HashMap::new());
+    static PROTOTYPE_INSTANCES: RefCell<HashMap<String, Rc<Prototype>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn register_prototypes(json: &Value) {
+    let Some(prototypes) = json.as_object() else {
+        return;
+    };
+    PROTOTYPE_LIBRARY.with(|lib| {
+        let mut lib = lib.borrow_mut();
+        for (name, proto) in prototypes {
+            lib.insert(name.clone(), proto.clone());
+        }
+    });
+}
+
+fn fetch_prototype(name: &str) -> Rc<Prototype> {
+    if let Some(proto) = PROTOTYPE_INSTANCES.with(|ins| ins.borrow().get(name).cloned()) {
+        return proto;
+    }
+    let json = PROTOTYPE_LIBRARY
+        .with(|lib| lib.borrow().get(name).cloned())
+        .unwrap_or_else(|| panic!("Undefined prototype: {}!", name));
+    let proto = Rc::new(Prototype::from_json(&json));
+    PROTOTYPE_INSTANCES.with(|ins| ins.borrow_mut().insert(name.to_string(), proto.clone()));
+    proto
+}
+
+/// 原型的一个实例，求交时将光线变换到原型空间
+/// prim_id的高16位为原型中形状的下标，低48位为该形状的prim_id，因此不支持实例的嵌套
+#[derive(Clone)]
+pub struct Instance {
+    shape: ShapeBase,
+    prototype: Rc<Prototype>,
+    // 是否用实例的材质替换原型中形状的材质
+    override_material: bool,
+}
+
+impl Instance {
+    pub fn from_json(json: &Value) -> Self {
+        let mut shape = ShapeBase::from_json(json);
+        let name = json["prototype"]
+            .as_str()
+            .expect("No prototype given for instance!");
+        let prototype = fetch_prototype(name);
+        let bounds = shape
+            .transform()
+            .to_world_bounds3(prototype.acc.bound3().clone());
+        shape.set_bounds(bounds);
+        Self {
+            shape,
+            prototype,
+            override_material: json.get("material").is_some(),
+        }
+    }
+
+    // 原型空间中的光线，方向归一化后t需要按比例缩放
+    fn local_ray(&self, ray: &Ray) -> (Ray, f32) {
+        let trans = self.transform();
+        let direction = trans.to_local_vec(ray.direction);
+        let scale = direction.magnitude();
+        let mut local = Ray::new(trans.to_local_point(ray.origin), direction / scale);
+        local.t_min = ray.t_min * scale;
+        local.t_max = ray.t_max * scale;
+        (local, scale)
+    }
+}
+
+impl Transformable for Instance {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Instance {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let (mut local, scale) = self.local_ray(ray);
+        let (geom_id, prim_id, u, v) = self.prototype.acc.ray_intersect(&mut local)?;
+        ray.t_max = local.t_max / scale;
+        Some(((geom_id << 48) | (prim_id & PRIM_MASK), u, v))
+    }
+
+    fn ray_occluded(&self, ray: &Ray) -> bool {
+        self.prototype.acc.occluded(&self.local_ray(ray).0)
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let geom_id = (prim_id >> 48) as usize;
+        let handle = &self.prototype.acc.acceleration().shapes[geom_id];
+        let trans = self.transform();
+        let wo = intersection.wo;
+        intersection.wo = trans.to_local_vec(wo);
+        handle.borrow().fill_intersection(
+            distance,
+            prim_id & PRIM_MASK,
+            u,
+            v,
+            medium,
+            intersection,
+        );
+
+        // 将原型空间中的几何信息变换到世界空间
+        intersection.wo = wo;
+        intersection.distance = distance;
+        intersection.position = trans.to_world_point(intersection.position);
+        intersection.normal = trans.to_world_normal(intersection.normal);
+        intersection.dp_du = trans.to_world_vec(intersection.dp_du);
+        intersection.dp_dv = trans.to_world_vec(intersection.dp_dv);
+        let tangent = trans.to_world_vec(intersection.tangent);
+        let bitangent = tangent.cross(intersection.normal).normalize();
+        intersection.tangent = intersection.normal.cross(bitangent).normalize();
+        intersection.bitangent = bitangent;
+        // 替换材质时由加速结构将交点的形状设置为实例本身
+        if self.override_material {
+            // 子形状可能按面设置了材质，一并清除
+            intersection.shape = None;
+            intersection.material = None;
+        } else if intersection.shape.is_none() {
+            intersection.shape = Some(handle.clone());
+        }
+    }
+
+    fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        // 实例暂不支持作为面光源
+        (SurfaceInteraction::default(), 0.0)
+    }
+
+    fn has_interface(&self) -> bool {
+        // 替换材质时子形状的材质不会被用到
+        if self.override_material {
+            return self
+                .material()
+                .is_some_and(|m| m.mat_type() == MaterialType::Interface);
+        }
+        let shapes = &self.prototype.acc.acceleration().shapes;
+        shapes.iter().any(|s| s.borrow().has_interface())
+    }
+
+    fn shape_type(&self) -> String {
+        "Instance".to_owned()
+    }
+}
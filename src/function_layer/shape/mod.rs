@@ -1,10 +1,18 @@
 mod cone;
+mod csg;
 mod cube;
+mod curves;
 mod cylinder;
 mod disk;
+mod hyperboloid;
+pub mod instance;
+mod paraboloid;
 mod parallelogram;
+mod rounded_box;
+mod sdf;
 pub mod shape;
 mod sphere;
+mod torus;
 mod triangle;
 
 pub use shape::{fetch_v3f, Shape};
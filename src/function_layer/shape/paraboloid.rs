@@ -0,0 +1,143 @@
+use super::shape::ShapeBase;
+use crate::core_layer::function::solve_quadratic_or_linear;
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::{Bounds3, Medium, Ray, Shape, SurfaceInteraction, V3f};
+use cgmath::{InnerSpace, Point3, Vector2};
+use serde_json::Value;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+/// 旋转抛物面z = zMax * (x^2 + y^2) / radius^2，截取zMin <= z <= zMax的部分
+#[derive(Clone)]
+pub struct Paraboloid {
+    pub shape: ShapeBase,
+    radius: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: f32,
+    pdf: f32,
+}
+
+impl Paraboloid {
+    pub fn from_json(json: &Value) -> Self {
+        let radius = json["radius"].as_f64().unwrap_or(1.0) as f32;
+        let z_min = json["zMin"].as_f64().unwrap_or(0.0) as f32;
+        let z_max = json["zMax"].as_f64().unwrap_or(1.0) as f32;
+        let phi_max = json["phi_max"].as_f64().unwrap_or(2.0 * PI) as f32;
+        if radius <= 0.0 {
+            panic!("The radius of a paraboloid must be positive!");
+        }
+        if z_min < 0.0 || z_max <= z_min {
+            panic!("Invalid z range of paraboloid!");
+        }
+        if phi_max <= 0.0 || phi_max > 2.0 * PI as f32 {
+            panic!("Invalid phi_max of paraboloid!");
+        }
+        let mut shape = ShapeBase::from_json(json);
+        let bounds3 = Bounds3::new(
+            V3f::new(-radius, -radius, z_min),
+            V3f::new(radius, radius, z_max),
+        );
+        shape.bounds3 = shape.transform.to_world_bounds3(bounds3);
+        let k = 4.0 * z_max / (radius * radius);
+        let area = radius.powi(4) * phi_max / (12.0 * z_max * z_max)
+            * ((k * z_max + 1.0).powf(1.5) - (k * z_min + 1.0).powf(1.5));
+        Self {
+            shape,
+            radius,
+            z_min,
+            z_max,
+            phi_max,
+            pdf: 1.0 / area,
+        }
+    }
+}
+
+impl Transformable for Paraboloid {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Paraboloid {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let trans = self.transform();
+        let o = trans.to_local_point(ray.origin);
+        let d = trans.to_local_vec(ray.direction);
+        let k = self.z_max / (self.radius * self.radius);
+        let a = k * (d.x * d.x + d.y * d.y);
+        let b = 2.0 * k * (d.x * o.x + d.y * o.y) - d.z;
+        let c = k * (o.x * o.x + o.y * o.y) - o.z;
+        // 光线平行于对称轴时a为0，只有一个交点
+        for t in solve_quadratic_or_linear(a, b, c) {
+            if t <= ray.t_min || t >= ray.t_max {
+                continue;
+            }
+            let p = o + d * t;
+            if p.z < self.z_min || p.z > self.z_max {
+                continue;
+            }
+            let phi = p.y.atan2(p.x).rem_euclid(2.0 * PI as f32);
+            if phi > self.phi_max {
+                continue;
+            }
+            ray.t_max = t;
+            let u = phi / self.phi_max;
+            let v = (p.z - self.z_min) / (self.z_max - self.z_min);
+            return Some((0, u, v));
+        }
+        None
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        _prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let trans = self.transform();
+        let phi = u * self.phi_max;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let z = self.z_min + v * (self.z_max - self.z_min);
+        let rho = self.radius * (z / self.z_max).sqrt();
+        let position = Point3::new(rho * cos_phi, rho * sin_phi, z);
+        // 隐式方程的梯度，指向抛物面的凸侧
+        let k = self.z_max / (self.radius * self.radius);
+        let normal = V3f::new(2.0 * k * position.x, 2.0 * k * position.y, -1.0).normalize();
+        intersection.position = trans.to_world_point(position);
+        intersection.normal = trans.to_world_normal(normal);
+        intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du =
+            trans.to_world_vec(self.phi_max * V3f::new(-position.y, position.x, 0.0));
+        // dz/dv为常数，drho/dz = rho / (2z)，顶点处退化为沿半径方向
+        let drho_dz = if z > 0.0 { rho / (2.0 * z) } else { 1.0 };
+        intersection.dp_dv = trans.to_world_vec(
+            (self.z_max - self.z_min) * V3f::new(drho_dz * cos_phi, drho_dz * sin_phi, 1.0),
+        );
+
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        // 面积微元正比于sqrt(1 + kz)，对其CDF求逆得到z
+        let k = 4.0 * self.z_max / (self.radius * self.radius);
+        let c0 = (k * self.z_min + 1.0).powf(1.5);
+        let c1 = (k * self.z_max + 1.0).powf(1.5);
+        let z = ((c0 + sample.y * (c1 - c0)).powf(2.0 / 3.0) - 1.0) / k;
+        let v = ((z - self.z_min) / (self.z_max - self.z_min)).clamp(0.0, 1.0);
+        let mut its = SurfaceInteraction::default();
+        self.fill_intersection(0.0, 0, sample.x, v, None, &mut its);
+        (its, self.pdf)
+    }
+}
@@ -108,9 +108,7 @@ impl Shape for Parallelogram {
         medium: Option<Rc<dyn Medium>>,
         intersection: &mut SurfaceInteraction,
     ) {
-        intersection.shape = Some(Rc::new(self.clone()));
         intersection.distance = distance;
-        intersection.shape = Some(Rc::new(self.clone()));
         intersection.normal = self.edge0.cross(self.edge1).normalize();
         intersection.tex_coord = Vector2::new(u, v);
         intersection.position = self.base + u * self.edge0 + v * self.edge1;
@@ -0,0 +1,270 @@
+use super::shape::ShapeBase;
+use crate::core_layer::function::solve_quadratic;
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::{fetch_v3f, Bounds3, Medium, Ray, Shape, SurfaceInteraction, V3f};
+use cgmath::{ElementWise, EuclideanSpace, InnerSpace, Point3, Vector2, Zero};
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 以原点为中心的圆角立方体，由6个面、12段四分之一圆柱和8个八分之一球面组成。
+/// prim_id与cube相同，为法线主分量所在的面2 * axis + (正方向 ? 1 : 0)
+#[derive(Clone)]
+pub struct RoundedBox {
+    pub shape: ShapeBase,
+    // 去掉圆角后内部立方体的半边长
+    inner: V3f,
+    radius: f32,
+    area_faces: f32,
+    area_edges: f32,
+    area_corners: f32,
+}
+
+impl RoundedBox {
+    pub fn from_json(json: &Value) -> Self {
+        let size = fetch_v3f(json, "size", V3f::new(2.0, 2.0, 2.0));
+        let radius = json["radius"].as_f64().unwrap_or(0.1) as f32;
+        let half = size * 0.5;
+        if radius <= 0.0 || radius > half.x.min(half.y).min(half.z) {
+            panic!("The radius of a rounded box must be in (0, size / 2]!");
+        }
+        let inner = half - V3f::new(radius, radius, radius);
+        let mut shape = ShapeBase::from_json(json);
+        shape.bounds3 = shape.transform.to_world_bounds3(Bounds3::new(-half, half));
+        Self {
+            shape,
+            inner,
+            radius,
+            area_faces: 8.0 * (inner.x * inner.y + inner.y * inner.z + inner.z * inner.x),
+            area_edges: 4.0 * PI * radius * (inner.x + inner.y + inner.z),
+            area_corners: 4.0 * PI * radius * radius,
+        }
+    }
+
+    fn half(&self) -> V3f {
+        self.inner + V3f::new(self.radius, self.radius, self.radius)
+    }
+
+    // 由局部坐标系中表面上的点计算(prim_id, u, v)
+    fn classify(&self, p: V3f) -> (u64, f32, f32) {
+        let n = p - clamp(p, self.inner);
+        let axis = (0..3)
+            .max_by(|&a, &b| n[a].abs().total_cmp(&n[b].abs()))
+            .unwrap();
+        let half = self.half();
+        let axis_u = (axis + 1) % 3;
+        let axis_v = (axis + 2) % 3;
+        let u = (p[axis_u] + half[axis_u]) / (2.0 * half[axis_u]);
+        let v = (p[axis_v] + half[axis_v]) / (2.0 * half[axis_v]);
+        let positive = if n[axis] > 0.0 { 1 } else { 0 };
+        (
+            (2 * axis + positive) as u64,
+            u.clamp(0.0, 1.0),
+            v.clamp(0.0, 1.0),
+        )
+    }
+}
+
+fn clamp(p: V3f, b: V3f) -> V3f {
+    V3f::new(
+        p.x.clamp(-b.x, b.x),
+        p.y.clamp(-b.y, b.y),
+        p.z.clamp(-b.z, b.z),
+    )
+}
+
+// 按权重从若干项中选择一项，并把样本重新映射到[0, 1)
+fn pick(weights: &[f32], sample: f32) -> (usize, f32) {
+    let total: f32 = weights.iter().sum();
+    let mut target = sample * total;
+    for (i, &w) in weights.iter().enumerate() {
+        if target < w || i == weights.len() - 1 {
+            return (i, (target / w).clamp(0.0, 0.99999994));
+        }
+        target -= w;
+    }
+    unreachable!()
+}
+
+impl Transformable for RoundedBox {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for RoundedBox {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let trans = self.transform();
+        let o = trans.to_local_point(ray.origin).to_vec();
+        let d = trans.to_local_vec(ray.direction);
+        let (b, r) = (self.inner, self.radius);
+        let half = self.half();
+
+        // 先与外包围盒求交，提前剔除
+        let (mut t0, mut t1) = (ray.t_min, ray.t_max);
+        for i in 0..3 {
+            let inv = 1.0 / d[i];
+            let (near, far) = ((-half[i] - o[i]) * inv, (half[i] - o[i]) * inv);
+            t0 = t0.max(near.min(far));
+            t1 = t1.min(near.max(far));
+        }
+        if t0 > t1 {
+            return None;
+        }
+
+        let mut t_hit = ray.t_max;
+        let mut update = |t: f32, valid: &dyn Fn(V3f) -> bool| {
+            if t > ray.t_min && t < t_hit && valid(o + d * t) {
+                t_hit = t;
+            }
+        };
+        for axis in 0..3 {
+            let (au, av) = ((axis + 1) % 3, (axis + 2) % 3);
+            for s in [-1.0, 1.0] {
+                // 平面部分
+                if d[axis] != 0.0 {
+                    let t = (s * half[axis] - o[axis]) / d[axis];
+                    update(t, &|p| p[au].abs() <= b[au] && p[av].abs() <= b[av]);
+                }
+                // 平行于axis的圆柱部分，s与s2为所在象限
+                for s2 in [-1.0, 1.0] {
+                    let (cu, cv) = (s * b[au], s2 * b[av]);
+                    let (ou, ov) = (o[au] - cu, o[av] - cv);
+                    let (du, dv) = (d[au], d[av]);
+                    if let Some((ta, tb)) = solve_quadratic(
+                        du * du + dv * dv,
+                        2.0 * (ou * du + ov * dv),
+                        ou * ou + ov * ov - r * r,
+                    ) {
+                        let valid = |p: V3f| {
+                            p[axis].abs() <= b[axis] && s * p[au] >= b[au] && s2 * p[av] >= b[av]
+                        };
+                        update(ta, &valid);
+                        update(tb, &valid);
+                    }
+                }
+            }
+        }
+        // 球面部分
+        for i in 0..8 {
+            let sign = V3f::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            let oc = o - b.mul_element_wise(sign);
+            if let Some((ta, tb)) = solve_quadratic(d.dot(d), 2.0 * oc.dot(d), oc.dot(oc) - r * r) {
+                let valid = |p: V3f| (0..3).all(|k| sign[k] * p[k] >= b[k]);
+                update(ta, &valid);
+                update(tb, &valid);
+            }
+        }
+
+        if t_hit >= ray.t_max {
+            return None;
+        }
+        ray.t_max = t_hit;
+        Some(self.classify(o + d * t_hit))
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let trans = self.transform();
+        let (b, r) = (self.inner, self.radius);
+        let half = self.half();
+        let axis = prim_id as usize / 2;
+        let s = if prim_id % 2 == 1 { 1.0 } else { -1.0 };
+        let (au, av) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        // 由另外两个坐标到内部立方体的距离反推axis方向的高度
+        let mut p = V3f::zero();
+        p[au] = -half[au] + 2.0 * half[au] * u;
+        p[av] = -half[av] + 2.0 * half[av] * v;
+        let du = (p[au].abs() - b[au]).max(0.0);
+        let dv = (p[av].abs() - b[av]).max(0.0);
+        let height = (r * r - du * du - dv * dv).max(0.0).sqrt();
+        p[axis] = s * (b[axis] + height);
+        let normal = p - clamp(p, b);
+
+        let mut dp_du = V3f::zero();
+        let mut dp_dv = V3f::zero();
+        dp_du[au] = 2.0 * half[au];
+        dp_dv[av] = 2.0 * half[av];
+        if height > 1e-6 {
+            dp_du[axis] = -s * du * p[au].signum() * 2.0 * half[au] / height;
+            dp_dv[axis] = -s * dv * p[av].signum() * 2.0 * half[av] / height;
+        }
+
+        intersection.position = trans.to_world_point(Point3::from_vec(p));
+        intersection.normal = trans.to_world_normal(normal.normalize());
+        intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du = trans.to_world_vec(dp_du);
+        intersection.dp_dv = trans.to_world_vec(dp_dv);
+
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        let (b, r) = (self.inner, self.radius);
+        let (part, x) = pick(
+            &[self.area_faces, self.area_edges, self.area_corners],
+            sample.x,
+        );
+        let mut p = V3f::zero();
+        match part {
+            0 => {
+                let (axis, x) = pick(&[b.y * b.z, b.z * b.x, b.x * b.y], x);
+                let (au, av) = ((axis + 1) % 3, (axis + 2) % 3);
+                let s = if x < 0.5 { -1.0 } else { 1.0 };
+                let x = (x * 2.0).fract();
+                p[axis] = s * (b[axis] + r);
+                p[au] = b[au] * (2.0 * x - 1.0);
+                p[av] = b[av] * (2.0 * sample.y - 1.0);
+            }
+            1 => {
+                let (axis, x) = pick(&[b.x, b.y, b.z], x);
+                let (au, av) = ((axis + 1) % 3, (axis + 2) % 3);
+                let quadrant = (x * 4.0) as usize;
+                let x = (x * 4.0).fract();
+                let su = if quadrant & 1 == 0 { -1.0 } else { 1.0 };
+                let sv = if quadrant & 2 == 0 { -1.0 } else { 1.0 };
+                let (sin_theta, cos_theta) = (x * PI * 0.5).sin_cos();
+                p[axis] = b[axis] * (2.0 * sample.y - 1.0);
+                p[au] = su * (b[au] + r * cos_theta);
+                p[av] = sv * (b[av] + r * sin_theta);
+            }
+            _ => {
+                // 在整个球面上均匀采样，再按符号移到对应的角上
+                let z = 1.0 - 2.0 * sample.y;
+                let rho = (1.0 - z * z).max(0.0).sqrt();
+                let (sin_phi, cos_phi) = (x * 2.0 * PI).sin_cos();
+                let dir = V3f::new(rho * cos_phi, rho * sin_phi, z);
+                for k in 0..3 {
+                    p[k] = dir[k].signum() * (b[k] + r * dir[k].abs());
+                }
+            }
+        }
+        let (prim_id, u, v) = self.classify(p);
+        let mut its = SurfaceInteraction::default();
+        self.fill_intersection(0.0, prim_id, u, v, None, &mut its);
+        (
+            its,
+            1.0 / (self.area_faces + self.area_edges + self.area_corners),
+        )
+    }
+}
@@ -0,0 +1,329 @@
+use super::shape::ShapeBase;
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::{fetch_v3f, Bounds3, Medium, Ray, Shape, SurfaceInteraction, V3f};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Zero};
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 有向距离场的节点，叶子为基本体，内部节点为CSG运算。
+/// smooth大于0时使用多项式smooth min做平滑过渡
+#[derive(Clone)]
+pub enum SdfNode {
+    Sphere {
+        center: V3f,
+        radius: f32,
+    },
+    // half为半边长，radius为圆角半径
+    Box {
+        center: V3f,
+        half: V3f,
+        radius: f32,
+    },
+    // 以z轴为对称轴
+    Torus {
+        center: V3f,
+        radius: f32,
+        minor_radius: f32,
+    },
+    Capsule {
+        a: V3f,
+        b: V3f,
+        radius: f32,
+    },
+    Union(Vec<SdfNode>, f32),
+    Intersection(Vec<SdfNode>, f32),
+    // 第一个子节点减去其余子节点
+    Difference(Vec<SdfNode>, f32),
+}
+
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+impl SdfNode {
+    pub fn from_json(json: &Value) -> Self {
+        let children = || -> Vec<SdfNode> {
+            let children: Vec<SdfNode> = json["children"]
+                .as_array()
+                .expect("CSG node of sdf needs children!")
+                .iter()
+                .map(SdfNode::from_json)
+                .collect();
+            if children.is_empty() {
+                panic!("CSG node of sdf needs at least one child!");
+            }
+            children
+        };
+        let smooth = json["smooth"].as_f64().unwrap_or(0.0) as f32;
+        let radius = json["radius"].as_f64().unwrap_or(1.0) as f32;
+        let center = fetch_v3f(json, "center", V3f::zero());
+        match json["type"].as_str().expect("No sdf node type given") {
+            "sphere" => Self::Sphere { center, radius },
+            "box" => {
+                let half = fetch_v3f(json, "size", V3f::new(2.0, 2.0, 2.0)) * 0.5;
+                let radius = json["radius"].as_f64().unwrap_or(0.0) as f32;
+                Self::Box {
+                    center,
+                    half,
+                    radius: radius.min(half.x.min(half.y).min(half.z)),
+                }
+            }
+            "torus" => Self::Torus {
+                center,
+                radius,
+                minor_radius: json["minorRadius"].as_f64().unwrap_or(0.25) as f32,
+            },
+            "capsule" => Self::Capsule {
+                a: fetch_v3f(json, "a", V3f::new(0.0, 0.0, -1.0)),
+                b: fetch_v3f(json, "b", V3f::new(0.0, 0.0, 1.0)),
+                radius: json["radius"].as_f64().unwrap_or(0.5) as f32,
+            },
+            "union" => Self::Union(children(), smooth),
+            "intersection" => Self::Intersection(children(), smooth),
+            "difference" => Self::Difference(children(), smooth),
+            t => panic!("Invalid sdf node type: {}", t),
+        }
+    }
+
+    pub fn distance(&self, p: V3f) -> f32 {
+        match self {
+            Self::Sphere { center, radius } => (p - center).magnitude() - radius,
+            Self::Box {
+                center,
+                half,
+                radius,
+            } => {
+                let q = p - center;
+                let q = V3f::new(q.x.abs(), q.y.abs(), q.z.abs())
+                    - (half - V3f::new(*radius, *radius, *radius));
+                let outside = V3f::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).magnitude();
+                outside + q.x.max(q.y).max(q.z).min(0.0) - radius
+            }
+            Self::Torus {
+                center,
+                radius,
+                minor_radius,
+            } => {
+                let q = p - center;
+                let rho = (q.x * q.x + q.y * q.y).sqrt() - radius;
+                (rho * rho + q.z * q.z).sqrt() - minor_radius
+            }
+            Self::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = (pa.dot(ba) / ba.magnitude2().max(1e-12)).clamp(0.0, 1.0);
+                (pa - ba * h).magnitude() - radius
+            }
+            Self::Union(children, k) => children
+                .iter()
+                .map(|c| c.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *k))
+                .unwrap(),
+            Self::Intersection(children, k) => -children
+                .iter()
+                .map(|c| -c.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *k))
+                .unwrap(),
+            Self::Difference(children, k) => {
+                let rest = children[1..]
+                    .iter()
+                    .map(|c| c.distance(p))
+                    .reduce(|a, b| smooth_min(a, b, *k));
+                match rest {
+                    None => children[0].distance(p),
+                    Some(d) => -smooth_min(-children[0].distance(p), d, *k),
+                }
+            }
+        }
+    }
+
+    /// 局部坐标系中的包围盒。smooth union最多使距离减小smooth / 4，据此扩大包围盒
+    pub fn bounds(&self) -> Bounds3 {
+        match self {
+            Self::Sphere { center, radius } => {
+                let r = V3f::new(*radius, *radius, *radius);
+                Bounds3::new(center - r, center + r)
+            }
+            Self::Box { center, half, .. } => Bounds3::new(center - half, center + half),
+            Self::Torus {
+                center,
+                radius,
+                minor_radius,
+            } => {
+                let extent = V3f::new(radius + minor_radius, radius + minor_radius, *minor_radius);
+                Bounds3::new(center - extent, center + extent)
+            }
+            Self::Capsule { a, b, radius } => {
+                let r = V3f::new(*radius, *radius, *radius);
+                let mut bounds = Bounds3::new(a - r, a + r);
+                bounds.expand(b - r);
+                bounds.expand(b + r);
+                bounds
+            }
+            Self::Union(children, k) => {
+                let mut bounds = children
+                    .iter()
+                    .map(|c| c.bounds())
+                    .reduce(|a, b| Bounds3::union_bounds(&a, &b))
+                    .unwrap();
+                let grow = V3f::new(k * 0.25, k * 0.25, k * 0.25);
+                bounds.p_min -= grow;
+                bounds.p_max += grow;
+                bounds
+            }
+            Self::Intersection(children, _) => children
+                .iter()
+                .map(|c| c.bounds())
+                .reduce(|a, b| Bounds3 {
+                    p_min: V3f::new(
+                        a.p_min.x.max(b.p_min.x),
+                        a.p_min.y.max(b.p_min.y),
+                        a.p_min.z.max(b.p_min.z),
+                    ),
+                    p_max: V3f::new(
+                        a.p_max.x.min(b.p_max.x),
+                        a.p_max.y.min(b.p_max.y),
+                        a.p_max.z.min(b.p_max.z),
+                    ),
+                })
+                .unwrap(),
+            Self::Difference(children, _) => children[0].bounds(),
+        }
+    }
+}
+
+/// 由有向距离场隐式定义的形状，在包围盒内用sphere tracing求交
+#[derive(Clone)]
+pub struct Sdf {
+    pub shape: ShapeBase,
+    root: SdfNode,
+    local_bounds: Bounds3,
+    max_steps: u32,
+    epsilon: f32,
+}
+
+impl Sdf {
+    pub fn from_json(json: &Value) -> Self {
+        let root = SdfNode::from_json(&json["sdf"]);
+        let local_bounds = root.bounds();
+        let mut shape = ShapeBase::from_json(json);
+        shape.bounds3 = shape.transform.to_world_bounds3(local_bounds.clone());
+        Self {
+            shape,
+            root,
+            local_bounds,
+            max_steps: json["maxSteps"].as_u64().unwrap_or(256) as u32,
+            epsilon: json["epsilon"].as_f64().unwrap_or(1e-4) as f32,
+        }
+    }
+
+    // 四面体采样估计距离场的梯度
+    fn gradient(&self, p: V3f) -> V3f {
+        let h = self.epsilon;
+        [
+            V3f::new(1.0, -1.0, -1.0),
+            V3f::new(-1.0, -1.0, 1.0),
+            V3f::new(-1.0, 1.0, -1.0),
+            V3f::new(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .map(|k| k * self.root.distance(p + k * h))
+        .sum()
+    }
+}
+
+impl Transformable for Sdf {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Sdf {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let trans = self.transform();
+        let o = trans.to_local_point(ray.origin).to_vec();
+        let d = trans.to_local_vec(ray.direction);
+        // 归一化方向后局部距离s = t * len
+        let len = d.magnitude();
+        let d = d / len;
+        let s_origin = ray.t_min * len;
+        let (mut s, mut s_end) = (s_origin, ray.t_max * len);
+        for i in 0..3 {
+            let inv = 1.0 / d[i];
+            let near = (self.local_bounds.p_min[i] - o[i]) * inv;
+            let far = (self.local_bounds.p_max[i] - o[i]) * inv;
+            s = s.max(near.min(far));
+            s_end = s_end.min(near.max(far));
+        }
+        if s > s_end {
+            return None;
+        }
+
+        let eps = self.epsilon;
+        let mut dist = self.root.distance(o + d * s);
+        let mut steps = 0;
+        let on_origin = s <= s_origin;
+        // 光线起点在表面上(如从交点出发的光线)时，先离开表面再开始步进；
+        // 从包围盒边界开始步进时则不跳过，否则会漏掉与包围盒重合的表面
+        while on_origin && dist.abs() < 2.0 * eps && steps < self.max_steps {
+            s += dist.abs().max(eps);
+            dist = self.root.distance(o + d * s);
+            steps += 1;
+        }
+        while steps < self.max_steps && s <= s_end {
+            if dist.abs() < eps {
+                // 局部坐标系中的交点：x、y存入u、v，z的位表示存入prim_id
+                let position = o + d * s;
+                ray.t_max = s / len;
+                return Some((position.z.to_bits() as u64, position.x, position.y));
+            }
+            s += dist.abs();
+            dist = self.root.distance(o + d * s);
+            steps += 1;
+        }
+        None
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let trans = self.transform();
+        let position = Point3::new(u, v, f32::from_bits(prim_id as u32));
+        let normal = self.gradient(position.to_vec()).normalize();
+        // 没有自然的参数化，按法线方向做球面映射
+        let phi = normal.y.atan2(normal.x);
+        let theta = normal.z.clamp(-1.0, 1.0).acos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        intersection.position = trans.to_world_point(position);
+        intersection.normal = trans.to_world_normal(normal);
+        intersection.tex_coord = Vector2::new(phi / (2.0 * PI) + 0.5, theta / PI);
+        intersection.dp_du = trans.to_world_vec(2.0 * PI * V3f::new(-normal.y, normal.x, 0.0));
+        intersection.dp_dv =
+            trans.to_world_vec(PI * V3f::new(cos_theta * cos_phi, cos_theta * sin_phi, -sin_theta));
+
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        panic!("Sdf shape does not support area sampling!")
+    }
+}
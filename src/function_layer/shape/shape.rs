@@ -1,16 +1,18 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector2, Zero};
+use cgmath::{InnerSpace, Vector2};
 use serde_json::Value;
 
+use crate::core_layer::colorspace::SpectrumRGB;
 use crate::core_layer::transform::{Transform, Transformable};
-use crate::function_layer::{Bounds3, construct_material, construct_medium, Light, Material, material::matte::MatteMaterial, Medium, MediumInterface, Ray, RR, SurfaceInteraction, V3f};
-use crate::function_layer::texture::image_texture::ImageTexture;
+use crate::function_layer::{Bounds3, construct_material, construct_medium, construct_texture, Light, Material, material::{matte::MatteMaterial, MaterialType}, Medium, MediumInterface, Ray, RR, SurfaceInteraction, Texture, V3f};
 
 use super::{
-    cone::Cone, cube::Cube, cylinder::Cylinder, disk::Disk, parallelogram::Parallelogram,
-    sphere::Sphere, triangle::TriangleMesh,
+    cone::Cone, csg::Csg, cube::Cube, curves::Curves, cylinder::Cylinder, disk::Disk,
+    hyperboloid::Hyperboloid, instance::Instance, paraboloid::Paraboloid,
+    parallelogram::Parallelogram, rounded_box::RoundedBox, sdf::Sdf, sphere::Sphere, torus::Torus,
+    triangle::TriangleMesh,
 };
 
 pub trait Shape: Transformable {
@@ -25,17 +27,25 @@ pub trait Shape: Transformable {
     fn material(&self) -> Option<Rc<dyn Material>> {
         self.shape().material.clone()
     }
+    // 形状上是否有只划分介质的表面，由子形状组成的形状需要逐个检查
+    fn has_interface(&self) -> bool {
+        self.material().is_some_and(|m| m.mat_type() == MaterialType::Interface)
+    }
     fn get_bounds(&self) -> &Bounds3 {
         &self.shape().bounds3
     }
     fn geometry_id(&self) -> u64 {
         self.shape().geometry_id
     }
-    fn texture(&self) -> Option<Rc<ImageTexture>> { self.shape().texture.clone() }
+    fn texture(&self) -> Option<Rc<dyn Texture<SpectrumRGB>>> { self.shape().texture.clone() }
     fn set_geometry_id(&mut self, id: u64) {
         self.shape_mut().geometry_id = id;
     }
     fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)>;
+    // 只判断是否相交，不修改光线
+    fn ray_occluded(&self, ray: &Ray) -> bool {
+        self.ray_intersect_shape(&mut ray.clone()).is_some()
+    }
     fn fill_intersection(
         &self,
         distance: f32,
@@ -60,13 +70,18 @@ pub trait Shape: Transformable {
             MediumInterface::new(medium.clone(), medium.clone())
         };
 
-        // 计算交点的切线和副切线
-        let mut tangent = V3f::new(1.0, 0.0, 0.0);
-        if tangent.dot(intersection.normal).abs() > 0.9 {
-            tangent = V3f::new(0.0, 1.0, 0.0);
+        // 计算交点的切线和副切线：切线沿dp_du方向并与法线正交，
+        // dp_du未知或退化(如球的两极)时任取一个与法线垂直的方向
+        let normal = intersection.normal;
+        let mut tangent = intersection.dp_du - normal * normal.dot(intersection.dp_du);
+        if tangent.magnitude2() < 1e-12 {
+            tangent = V3f::new(1.0, 0.0, 0.0);
+            if tangent.dot(normal).abs() > 0.9 {
+                tangent = V3f::new(0.0, 1.0, 0.0);
+            }
         }
-        let bitangent = tangent.cross(intersection.normal).normalize();
-        tangent = intersection.normal.cross(bitangent).normalize();
+        let bitangent = tangent.cross(normal).normalize();
+        tangent = normal.cross(bitangent).normalize();
         intersection.tangent = tangent;
         intersection.bitangent = bitangent;
     }
@@ -85,7 +100,7 @@ pub struct ShapeBase {
     pub medium_interface: MediumInterface,
     pub transform: Transform,
     pub bounds3: Bounds3,
-    pub texture: Option<Rc<ImageTexture>>,
+    pub texture: Option<Rc<dyn Texture<SpectrumRGB>>>,
 }
 
 pub fn fetch_v3f(json: &Value, field: &str, dft: V3f) -> V3f {
@@ -108,18 +123,7 @@ impl ShapeBase {
             Some(mat) => construct_material(mat),
         };
         let transform = if let Some(transform) = json.get("transform") {
-            let translate_mat =
-                Transform::translation(fetch_v3f(transform, "translate", V3f::zero()));
-            let scale_mat =
-                Transform::scalation(fetch_v3f(transform, "scale", V3f::from([1.0; 3])));
-            let rotate_mat = if !transform["rotate"].is_null() {
-                let axis = fetch_v3f(&transform["rotate"], "axis", V3f::from([1.0; 3]));
-                let radian = transform["rotate"]["radian"].as_f64().unwrap_or(0.0);
-                Transform::rotation(axis, radian as f32)
-            } else {
-                Matrix4::identity()
-            };
-            Transform::new(translate_mat, rotate_mat, scale_mat)
+            Transform::from_json(transform)
         } else {
             Transform::identity()
         };
@@ -138,11 +142,7 @@ impl ShapeBase {
         } else {
             MediumInterface::default()
         };
-        let texture = if let Some(js) = &json.get("texture") {
-            Some(Rc::new(ImageTexture::from_json(js)))
-        } else {
-            None
-        };
+        let texture = json.get("texture").map(construct_texture::<SpectrumRGB>);
 
         Self {
             geometry_id: 0,
@@ -169,6 +169,14 @@ pub fn construct_shape(json: &Value) -> RR<dyn Shape> {
         "cylinder" => Rc::new(RefCell::new(Cylinder::from_json(json))),
         "cone" => Rc::new(RefCell::new(Cone::from_json(json))),
         "cube" => Rc::new(RefCell::new(Cube::from_json(json))),
+        "curves" => Rc::new(RefCell::new(Curves::from_json(json))),
+        "torus" => Rc::new(RefCell::new(Torus::from_json(json))),
+        "paraboloid" => Rc::new(RefCell::new(Paraboloid::from_json(json))),
+        "hyperboloid" => Rc::new(RefCell::new(Hyperboloid::from_json(json))),
+        "roundedBox" => Rc::new(RefCell::new(RoundedBox::from_json(json))),
+        "sdf" => Rc::new(RefCell::new(Sdf::from_json(json))),
+        "csg" => Rc::new(RefCell::new(Csg::from_json(json))),
+        "instance" => Rc::new(RefCell::new(Instance::from_json(json))),
         t => panic!("Invalid shape type: {}", t),
     }
 }
@@ -102,9 +102,12 @@ impl Shape for Sphere {
         let position = self.center + self.radius * normal;
         intersection.position = position;
         intersection.tex_coord = Vector2::new(u * INV_PI * 0.5, v * INV_PI);
+        // u为方位角、v为极角，纹理坐标分别为u / 2π、v / π
+        intersection.dp_du =
+            2.0 * PI * self.radius * V3f::new(v.sin() * u.cos(), 0.0, -v.sin() * u.sin());
+        intersection.dp_dv =
+            PI * self.radius * V3f::new(v.cos() * u.sin(), -v.sin(), v.cos() * u.cos());
 
-        // TODO 计算交点的切线和副切线
-        intersection.shape = Some(Rc::new(self.clone()));
         self._fill_intersection(distance, medium, intersection);
     }
 
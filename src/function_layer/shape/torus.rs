@@ -0,0 +1,148 @@
+use super::shape::ShapeBase;
+use crate::core_layer::function::{solve_quadratic, solve_quartic};
+use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::{Bounds3, Medium, Ray, Shape, SurfaceInteraction, V3f};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2};
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 以z轴为对称轴的圆环，radius为中心圆的半径，minorRadius为截面圆的半径
+#[derive(Clone)]
+pub struct Torus {
+    pub shape: ShapeBase,
+    radius: f32,
+    minor_radius: f32,
+    pdf: f32,
+}
+
+impl Torus {
+    pub fn from_json(json: &Value) -> Self {
+        let radius = json["radius"].as_f64().unwrap_or(1.0) as f32;
+        let minor_radius = json["minorRadius"].as_f64().unwrap_or(0.25) as f32;
+        if radius <= 0.0 || minor_radius <= 0.0 {
+            panic!("The radii of a torus must be positive!");
+        }
+        if minor_radius >= radius {
+            panic!("The minor radius of a torus must be smaller than its radius!");
+        }
+        let mut shape = ShapeBase::from_json(json);
+        let extent = radius + minor_radius;
+        let bounds3 = Bounds3::new(
+            V3f::new(-extent, -extent, -minor_radius),
+            V3f::new(extent, extent, minor_radius),
+        );
+        shape.bounds3 = shape.transform.to_world_bounds3(bounds3);
+        let area = 4.0 * PI * PI * radius * minor_radius;
+        Self {
+            shape,
+            radius,
+            minor_radius,
+            pdf: 1.0 / area,
+        }
+    }
+}
+
+impl Transformable for Torus {
+    fn transform(&self) -> &Transform {
+        self.shape.transform()
+    }
+}
+
+impl Shape for Torus {
+    fn shape(&self) -> &ShapeBase {
+        &self.shape
+    }
+
+    fn shape_mut(&mut self) -> &mut ShapeBase {
+        &mut self.shape
+    }
+
+    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
+        let trans = self.transform();
+        let o = trans.to_local_point(ray.origin).to_vec();
+        let d = trans.to_local_vec(ray.direction);
+        // 先与包围球求交，把起点移到包围球附近并归一化方向，减小四次方程的数值误差
+        let extent = self.radius + self.minor_radius;
+        let (t_near, t_far) =
+            solve_quadratic(d.dot(d), 2.0 * o.dot(d), o.dot(o) - extent * extent)?;
+        if t_far <= ray.t_min || t_near >= ray.t_max {
+            return None;
+        }
+        let t_shift = t_near.max(0.0) as f64;
+        let len = d.magnitude() as f64;
+        let (o, d) = (o.cast::<f64>()?, d.cast::<f64>()?);
+        let o = o + d * t_shift;
+        let d = d / len;
+
+        let (r2, rr2) = (
+            (self.radius * self.radius) as f64,
+            (self.minor_radius * self.minor_radius) as f64,
+        );
+        let e = o.dot(o) - r2 - rr2;
+        let f = o.dot(d);
+        let roots = solve_quartic([
+            e * e - 4.0 * r2 * (rr2 - o.z * o.z),
+            4.0 * f * e + 8.0 * r2 * o.z * d.z,
+            2.0 * e + 4.0 * f * f + 4.0 * r2 * d.z * d.z,
+            4.0 * f,
+            1.0,
+        ]);
+        for s in roots {
+            let t = (t_shift + s / len) as f32;
+            if t <= ray.t_min || t >= ray.t_max {
+                continue;
+            }
+            let p = o + d * s;
+            let phi = (p.y.atan2(p.x) as f32).rem_euclid(2.0 * PI);
+            let rho = (p.x * p.x + p.y * p.y).sqrt() - self.radius as f64;
+            let theta = (p.z.atan2(rho) as f32).rem_euclid(2.0 * PI);
+            ray.t_max = t;
+            return Some((0, phi / (2.0 * PI), theta / (2.0 * PI)));
+        }
+        None
+    }
+
+    fn fill_intersection(
+        &self,
+        distance: f32,
+        _prim_id: u64,
+        u: f32,
+        v: f32,
+        medium: Option<Rc<dyn Medium>>,
+        intersection: &mut SurfaceInteraction,
+    ) {
+        let trans = self.transform();
+        let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+        let (sin_theta, cos_theta) = (v * 2.0 * PI).sin_cos();
+        let rho = self.radius + self.minor_radius * cos_theta;
+        let position = Point3::new(rho * cos_phi, rho * sin_phi, self.minor_radius * sin_theta);
+        let normal = V3f::new(cos_theta * cos_phi, cos_theta * sin_phi, sin_theta);
+        intersection.position = trans.to_world_point(position);
+        intersection.normal = trans.to_world_normal(normal);
+        intersection.tex_coord = Vector2::new(u, v);
+        intersection.dp_du =
+            trans.to_world_vec(2.0 * PI * V3f::new(-rho * sin_phi, rho * cos_phi, 0.0));
+        intersection.dp_dv = trans.to_world_vec(
+            2.0 * PI
+                * self.minor_radius
+                * V3f::new(-sin_theta * cos_phi, -sin_theta * sin_phi, cos_theta),
+        );
+
+        self._fill_intersection(distance, medium, intersection);
+    }
+
+    fn uniform_sample_on_surface(&self, sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
+        // 面积微元正比于radius + minorRadius * cos(theta)，用牛顿迭代求CDF的逆
+        let k = self.minor_radius / self.radius;
+        let target = sample.y * 2.0 * PI;
+        let mut theta = target;
+        for _ in 0..8 {
+            let f = theta + k * theta.sin() - target;
+            theta = (theta - f / (1.0 + k * theta.cos())).clamp(0.0, 2.0 * PI);
+        }
+        let mut its = SurfaceInteraction::default();
+        self.fill_intersection(0.0, 0, sample.x, theta / (2.0 * PI), None, &mut its);
+        (its, self.pdf)
+    }
+}
@@ -1,43 +1,98 @@
 use super::shape::{Shape, ShapeBase};
 use crate::core_layer::transform::{Transform, Transformable};
+use crate::function_layer::acceleration::{build_triangle_accel, TriangleAccel};
+use crate::function_layer::material::MaterialType;
 use crate::function_layer::{
-    create_acceleration, Acceleration, Medium, Ray, SurfaceInteraction, V3f,
+    construct_material, construct_texture, Material, Medium, Ray, SurfaceInteraction, V3f,
 };
+use crate::resource_layer::mesh::{MeshLoadOptions, MeshMaterial};
 use crate::resource_layer::MeshData;
 use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2};
-use serde_json::Value;
-use std::cell::RefCell;
+use serde_json::{json, Value};
 use std::rc::Rc;
 
 pub struct TriangleMesh {
     shape: ShapeBase,
     mesh: Rc<MeshData>,
-    acc: Option<Box<dyn Acceleration>>,
-}
-
-impl Clone for TriangleMesh {
-    fn clone(&self) -> Self {
-        Self {
-            shape: self.shape.clone(),
-            mesh: self.mesh.clone(),
-            acc: None,
-        }
-    }
+    // 内部BVH的宽度，4或8
+    bvh_width: usize,
+    acc: Option<Rc<dyn TriangleAccel>>,
+    // 由.mtl文件转换得到的材质，json中指定了材质时为空
+    materials: Vec<Rc<dyn Material>>,
 }
 
 impl TriangleMesh {
     pub fn from_json(json: &Value) -> Self {
         let shape = ShapeBase::from_json(json);
         let file_path = json["file"].as_str().unwrap();
-        let mesh = MeshData::load_from_file(file_path);
+        let options = MeshLoadOptions {
+            smooth_angle: json["smoothAngle"].as_f64().map(|a| a as f32),
+        };
+        let mut mesh = MeshData::load_from_file(file_path, options);
+        let level = json["subdivision"].as_u64().unwrap_or(0) as u32;
+        if level > 0 || !json["displacement"].is_null() {
+            // 细分和位移后的网格只属于当前形状，不放入网格池
+            mesh = Rc::new(refine_mesh(&mesh, level, json, options.smooth_angle));
+        }
+        let bvh_width = json["bvhWidth"].as_u64().unwrap_or(4) as usize;
+        let materials = if json.get("material").is_none() {
+            mesh.materials.iter().map(material_from_mtl).collect()
+        } else {
+            vec![]
+        };
         Self {
             shape,
             mesh,
+            bvh_width,
             acc: None,
+            materials,
         }
     }
 }
 
+// 在构建内部BVH之前对网格进行Loop细分和沿法线的位移，并重新计算法线和切线
+fn refine_mesh(mesh: &MeshData, level: u32, json: &Value, smooth_angle: Option<f32>) -> MeshData {
+    let mut refined = mesh.loop_subdivide(level);
+    if !json["displacement"].is_null() {
+        let texture = construct_texture::<f32>(&json["displacement"]);
+        let scale = json["displacementScale"].as_f64().unwrap_or(1.0) as f32;
+        refined.displace(|position, normal, tex_coord| {
+            let mut intersection = SurfaceInteraction::default();
+            intersection.position = position;
+            intersection.normal = normal;
+            intersection.tex_coord = tex_coord;
+            texture.evaluate(&intersection) * scale
+        });
+    }
+    // 细分后原有的法线已失效；位移时保留smoothAngle给出的折痕
+    refined.smooth_normals(smooth_angle.unwrap_or(180.0));
+    refined.compute_tangents();
+    refined
+}
+
+// 将.mtl中的材质参数转换为对应的材质类型
+fn material_from_mtl(m: &MeshMaterial) -> Rc<dyn Material> {
+    let albedo = match &m.diffuse_texture {
+        Some(file) => json!({"type": "imageTex", "file": file}),
+        None => json!(m.diffuse),
+    };
+    let transparent = m.dissolve < 1.0 || matches!(m.illumination_model, Some(4 | 6 | 7 | 9));
+    let mut mat = if transparent {
+        let eta = if m.ior > 1.0 { m.ior } else { 1.5 };
+        json!({"type": "dielectric", "albedo": [1.0, 1.0, 1.0], "roughness": 0.01, "eta": eta})
+    } else if matches!(m.illumination_model, Some(3 | 5)) {
+        json!({"type": "mirror"})
+    } else if m.specular.iter().any(|&s| s > 0.0) {
+        json!({"type": "phong", "kd": albedo, "ks": m.specular, "p": m.shininess.max(1.0)})
+    } else {
+        json!({"type": "matte", "albedo": albedo})
+    };
+    if let Some(file) = &m.normal_texture {
+        mat["normalmap"] = json!({ "file": file });
+    }
+    construct_material(&mat)
+}
+
 impl Transformable for TriangleMesh {
     fn transform(&self) -> &Transform {
         self.shape.transform()
@@ -57,13 +112,14 @@ impl Shape for TriangleMesh {
         // 当使用embree加速时，该方法不会被调用
         match &self.acc {
             None => None,
-            Some(acc) => {
-                let opt_its = acc.ray_intersect(ray);
-                match opt_its {
-                    None => None,
-                    Some((_, p, u, v)) => Some((p, u, v)),
-                }
-            }
+            Some(acc) => acc.intersect(ray),
+        }
+    }
+
+    fn ray_occluded(&self, ray: &Ray) -> bool {
+        match &self.acc {
+            None => false,
+            Some(acc) => acc.occluded(ray),
         }
     }
 
@@ -92,7 +148,7 @@ impl Shape for TriangleMesh {
         let nwuv: Vec<V3f> = (0..3)
             .map(|i: usize| {
                 self.transform()
-                    .to_world_vec(self.mesh.normal_buffer[face_info[i].normal_index])
+                    .to_world_normal(self.mesh.normal_buffer[face_info[i].normal_index])
             })
             .collect();
         let (nw, nu, nv) = (nwuv[0], nwuv[1], nwuv[2]);
@@ -103,9 +159,35 @@ impl Shape for TriangleMesh {
             .collect();
         let (tw, tu, tv) = (twuv[0], twuv[1], twuv[2]);
         intersection.tex_coord = w * tw + u * tu + v * tv;
-        intersection.shape = Some(Rc::new(self.clone()));
+        // 由三个顶点的位置和纹理坐标求解dp_du、dp_dv
+        let (duv1, duv2) = (tu - tw, tv - tw);
+        let (dp1, dp2) = (pu - pw, pv - pw);
+        let det = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() > 1e-12 {
+            intersection.dp_du = (dp1 * duv2.y - dp2 * duv1.y) / det;
+            intersection.dp_dv = (dp2 * duv1.x - dp1 * duv2.x) / det;
+        }
+        if !self.mesh.color_buffer.is_empty() {
+            let c = face_info.map(|d| self.mesh.color_buffer[d.vertex_index]);
+            intersection.vertex_color = Some(w * c[0] + u * c[1] + v * c[2]);
+        }
+        if let Some(index) = self.mesh.material_buffer[prim_id as usize] {
+            intersection.material = self.materials.get(index).cloned();
+        }
 
         self._fill_intersection(distance, medium, intersection);
+
+        // 使用插值得到的顶点切线代替dp_du，使法线贴图在相邻面之间连续
+        if !self.mesh.tangent_buffer.is_empty() {
+            let t = face_info.map(|d| self.mesh.tangent_buffer[d.tangent_index]);
+            let normal = intersection.normal;
+            let tangent = self.transform().to_world_vec(w * t[0] + u * t[1] + v * t[2]);
+            let tangent = tangent - normal * normal.dot(tangent);
+            if tangent.magnitude2() > 1e-12 {
+                intersection.tangent = tangent.normalize();
+                intersection.bitangent = intersection.tangent.cross(normal).normalize();
+            }
+        }
     }
 
     fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
@@ -115,137 +197,33 @@ impl Shape for TriangleMesh {
 
     fn init_internal_acceleration(&mut self) {
         // 当不使用embree时，TriangleMesh需要实现内部加速结构，调用该方法
-        self.acc = Some(create_acceleration());
-
-        let prim_count = self.mesh.face_count;
-        let mesh = Rc::new(self.clone());
-        for prime_id in 0..prim_count {
-            let v_indices: Vec<usize> = (0..3)
-                .map(|i: usize| self.mesh.face_buffer[prime_id][i].vertex_index)
-                .collect();
-            let v0 = mesh.mesh.vertex_buffer[v_indices[0]];
-            let v1 = mesh.mesh.vertex_buffer[v_indices[1]];
-            let v2 = mesh.mesh.vertex_buffer[v_indices[2]];
-
-            let triangle = Rc::new(RefCell::new(Triangle::new(
-                prime_id,
-                v0,
-                v1,
-                v2,
-                mesh.transform(),
-                mesh.geometry_id(),
-            )));
-            self.acc.as_mut().unwrap().attach_shape(triangle);
-        }
-        self.acc.as_mut().unwrap().build();
-        let b3 = self.acc.as_ref().unwrap().bound3().clone();
-        self.shape.set_bounds(b3);
-    }
-
-    fn shape_type(&self) -> String {
-        "Triangles".to_owned()
-    }
-}
-
-struct Triangle {
-    pub prim_id: usize,
-    pub v0: Point3<f32>,
-    // pub v1: Point3<f32>,
-    // pub v2: Point3<f32>,
-    pub e0: V3f,
-    pub e1: V3f,
-    shape: ShapeBase,
-}
-
-impl Triangle {
-    // 三角形没有记录transform，其坐标均为世界坐标
-    pub fn new(
-        prim_id: usize,
-        v0: Point3<f32>,
-        v1: Point3<f32>,
-        v2: Point3<f32>,
-        t: &Transform,
-        gid: u64,
-    ) -> Self {
-        let mut shape = ShapeBase::default();
-        shape.geometry_id = gid;
-        let v0 = t.to_world_point(v0);
-        let v1 = t.to_world_point(v1);
-        let v2 = t.to_world_point(v2);
-        let e0 = v1 - v0;
-        let e1 = v2 - v0;
-
-        shape.bounds3.expand(v0.to_vec());
-        shape.bounds3.expand(v1.to_vec());
-        shape.bounds3.expand(v2.to_vec());
-
-        Self {
-            prim_id,
-            v0,
-            e0,
-            e1,
-            shape,
-        }
-    }
-}
-
-impl Transformable for Triangle {
-    fn transform(&self) -> &Transform {
-        self.shape.transform()
-    }
-}
-
-impl Shape for Triangle {
-    fn shape(&self) -> &ShapeBase {
-        &self.shape
-    }
-
-    fn shape_mut(&mut self) -> &mut ShapeBase {
-        &mut self.shape
-    }
-
-    fn ray_intersect_shape(&self, ray: &mut Ray) -> Option<(u64, f32, f32)> {
-        let (u, v, t_tmp): (f32, f32, f32);
-        let pvec: V3f = ray.direction.cross(self.e1);
-        let det = self.e0.dot(pvec);
-        if det.abs() < 0.00001 {
-            return None;
-        }
-        let det_inv = 1.0 / det;
-        let tvec: V3f = ray.origin - self.v0;
-        let qvec: V3f = tvec.cross(self.e0);
-        t_tmp = self.e1.dot(qvec) * det_inv;
-        if t_tmp < ray.t_min || t_tmp > ray.t_max {
-            return None;
-        }
-        u = tvec.dot(pvec) * det_inv;
-        if u < 0.0 || u > 1.0 {
-            return None;
-        }
-        v = ray.direction.dot(qvec) * det_inv;
-        if v < 0.0 || u + v > 1.0 {
-            return None;
-        }
-        ray.t_max = t_tmp;
-        Some((self.prim_id as u64, u, v))
+        let triangles: Vec<[Point3<f32>; 3]> = self
+            .mesh
+            .face_buffer
+            .iter()
+            .map(|face| {
+                [0, 1, 2].map(|i| {
+                    self.transform()
+                        .to_world_point(self.mesh.vertex_buffer[face[i].vertex_index])
+                })
+            })
+            .collect();
+        let acc = build_triangle_accel(&triangles, self.bvh_width);
+        self.shape.set_bounds(acc.bounds().clone());
+        self.acc = Some(acc);
     }
 
-    fn fill_intersection(
-        &self,
-        _distance: f32,
-        _prim_id: u64,
-        _u: f32,
-        _v: f32,
-        _medium: Option<Rc<dyn Medium>>,
-        _intersection: &mut SurfaceInteraction,
-    ) {
-        // 该函数实际上不会被调用
+    fn has_interface(&self) -> bool {
+        // 逐面的材质也可能只划分介质
+        let is_interface = |m: &Rc<dyn Material>| m.mat_type() == MaterialType::Interface;
+        self.shape
+            .material
+            .iter()
+            .chain(&self.materials)
+            .any(is_interface)
     }
 
-    fn uniform_sample_on_surface(&self, _sample: Vector2<f32>) -> (SurfaceInteraction, f32) {
-        todo!()
-    }
     fn shape_type(&self) -> String {
-        "Triangle".to_owned()
+        "Triangles".to_owned()
     }
 }
@@ -0,0 +1,70 @@
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, fetch_texture, fetch_uv_scale, TextureMapping, TextureValue};
+use super::{Texture, TextureCoord};
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+pub struct CheckerboardTexture<TReturn> {
+    mapping: Rc<dyn TextureMapping>,
+    scale: Vector2<f32>,
+    tex1: Rc<dyn Texture<TReturn>>,
+    tex2: Rc<dyn Texture<TReturn>>,
+}
+
+impl<TReturn: TextureValue> CheckerboardTexture<TReturn> {
+    pub fn from_json(json: &Value) -> Self {
+        let tex1 = fetch_texture(json, "tex1", TReturn::from_rgb(V3f::from([1.0; 3])));
+        let tex2 = fetch_texture(json, "tex2", TReturn::from_rgb(V3f::zero()));
+        Self {
+            mapping: fetch_mapping(json),
+            scale: fetch_uv_scale(json),
+            tex1,
+            tex2,
+        }
+    }
+}
+
+// 对周期为2的方波做盒式滤波后的积分，用于棋盘格的抗锯齿
+fn filtered_square(x: f32, width: f32) -> f32 {
+    let tri = |x: f32| (x * 0.5 - (x * 0.5).floor() - 0.5).abs();
+    2.0 * (tri(x - 0.5 * width) - tri(x + 0.5 * width)) / width
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for CheckerboardTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        self.mapping.clone()
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
+    }
+
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let s = tex_coord.coord.x * self.scale.x;
+        let t = tex_coord.coord.y * self.scale.y;
+        let ds = tex_coord.duv_dx.x.abs().max(tex_coord.duv_dy.x.abs()) * self.scale.x;
+        let dt = tex_coord.duv_dx.y.abs().max(tex_coord.duv_dy.y.abs()) * self.scale.y;
+        // 没有光线微分时直接点采样
+        let alpha = if ds == 0.0 || dt == 0.0 {
+            if (s.floor() + t.floor()) as i64 % 2 == 0 {
+                0.0
+            } else {
+                1.0
+            }
+        } else {
+            0.5 - 0.5 * filtered_square(s, ds) * filtered_square(t, dt)
+        };
+        let v1 = self.tex1.evaluate_coord(tex_coord);
+        if alpha <= 0.0 {
+            return v1;
+        }
+        let v2 = self.tex2.evaluate_coord(tex_coord);
+        v1 * (1.0 - alpha) + v2 * alpha
+    }
+}
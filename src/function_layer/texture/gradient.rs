@@ -0,0 +1,71 @@
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, fetch_texture, TextureMapping, TextureValue};
+use super::{Texture, TextureCoord};
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{InnerSpace, Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+#[derive(Copy, Clone)]
+enum GradientType {
+    U,
+    V,
+    Diagonal,
+    Radial,
+}
+
+/// 渐变纹理，从tex1过渡到tex2
+pub struct GradientTexture<TReturn> {
+    mapping: Rc<dyn TextureMapping>,
+    tp: GradientType,
+    tex1: Rc<dyn Texture<TReturn>>,
+    tex2: Rc<dyn Texture<TReturn>>,
+}
+
+impl<TReturn: TextureValue> GradientTexture<TReturn> {
+    pub fn from_json(json: &Value) -> Self {
+        let tex1 = fetch_texture(json, "tex1", TReturn::from_rgb(V3f::zero()));
+        let tex2 = fetch_texture(json, "tex2", TReturn::from_rgb(V3f::from([1.0; 3])));
+        let tp = match json["direction"].as_str().unwrap_or("u") {
+            "u" => GradientType::U,
+            "v" => GradientType::V,
+            "diagonal" => GradientType::Diagonal,
+            "radial" => GradientType::Radial,
+            tp => panic!("Invalid gradient direction: {}!", tp),
+        };
+        Self {
+            mapping: fetch_mapping(json),
+            tp,
+            tex1,
+            tex2,
+        }
+    }
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for GradientTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        self.mapping.clone()
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
+    }
+
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let uv = tex_coord.coord;
+        let alpha = match self.tp {
+            GradientType::U => uv.x,
+            GradientType::V => uv.y,
+            GradientType::Diagonal => 0.5 * (uv.x + uv.y),
+            // 以纹理中心为圆心，到边缘中点处达到tex2
+            GradientType::Radial => 2.0 * (uv - Vector2::new(0.5, 0.5)).magnitude(),
+        }
+        .clamp(0.0, 1.0);
+        self.tex1.evaluate_coord(tex_coord) * (1.0 - alpha)
+            + self.tex2.evaluate_coord(tex_coord) * alpha
+    }
+}
@@ -0,0 +1,59 @@
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, fetch_texture, fetch_uv_scale, TextureMapping, TextureValue};
+use super::{Texture, TextureCoord};
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+/// 网格纹理，tex1为格子内部，tex2为网格线
+pub struct GridTexture<TReturn> {
+    mapping: Rc<dyn TextureMapping>,
+    scale: Vector2<f32>,
+    line_width: f32,
+    tex1: Rc<dyn Texture<TReturn>>,
+    tex2: Rc<dyn Texture<TReturn>>,
+}
+
+impl<TReturn: TextureValue> GridTexture<TReturn> {
+    pub fn from_json(json: &Value) -> Self {
+        let tex1 = fetch_texture(json, "tex1", TReturn::from_rgb(V3f::from([1.0; 3])));
+        let tex2 = fetch_texture(json, "tex2", TReturn::from_rgb(V3f::zero()));
+        let line_width = json["lineWidth"].as_f64().unwrap_or(0.05) as f32;
+        Self {
+            mapping: fetch_mapping(json),
+            scale: fetch_uv_scale(json),
+            line_width: line_width.clamp(0.0, 1.0),
+            tex1,
+            tex2,
+        }
+    }
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for GridTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        self.mapping.clone()
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
+    }
+
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let s = tex_coord.coord.x * self.scale.x;
+        let t = tex_coord.coord.y * self.scale.y;
+        // 到最近网格线的距离
+        let ds = (s - s.round()).abs();
+        let dt = (t - t.round()).abs();
+        let half = 0.5 * self.line_width;
+        if ds < half || dt < half {
+            self.tex2.evaluate_coord(tex_coord)
+        } else {
+            self.tex1.evaluate_coord(tex_coord)
+        }
+    }
+}
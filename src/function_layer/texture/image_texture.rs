@@ -1,35 +1,74 @@
-use super::mipmap::MipMap;
+use super::mipmap::{MipMap, MipMapOptions, PyramidFilter};
 use super::texture::TextureMapping;
-use super::texture::{Texture, TextureCoord, UVMapping};
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, Texture, TextureCoord, TextureValue};
 use crate::core_layer::colorspace::SpectrumRGB;
-use crate::function_layer::SurfaceInteraction;
-use crate::resource_layer::image_io::load_img;
-use cgmath::Vector2;
+use crate::function_layer::{SurfaceInteraction, V3f};
+use crate::resource_layer::image_io::load_img_cached;
+use cgmath::{ElementWise, Vector2};
+use image::Rgb32FImage;
 use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+type Pyramid = Rc<Vec<Rc<Rgb32FImage>>>;
+
+thread_local! {
+    // 同一张图片使用同一种降采样滤波器时共享mipmap金字塔
+    static PYRAMID_CACHE: RefCell<HashMap<(String, PyramidFilter), Pyramid>> =
+        RefCell::new(HashMap::new());
+}
+
+fn fetch_pyramid(file_path: &str, filter: PyramidFilter) -> Pyramid {
+    let key = (file_path.to_string(), filter);
+    if let Some(pyramid) = PYRAMID_CACHE.with(|cache| cache.borrow().get(&key).cloned()) {
+        return pyramid;
+    }
+    let img = load_img_cached(file_path).expect("Read Image Error!");
+    let pyramid = Rc::new(MipMap::build_pyramid(img, filter));
+    PYRAMID_CACHE.with(|cache| cache.borrow_mut().insert(key, pyramid.clone()));
+    pyramid
+}
+
 pub struct ImageTexture {
     size: Vector2<usize>,
     mapping: Rc<dyn TextureMapping>,
     mipmap: Rc<MipMap>,
+    // 只使用某一个通道时其下标，如glTF中粗糙度和金属度存放在同一张贴图的G、B通道
+    channel: Option<usize>,
+    // 读出的值先乘以factor再取exponent次幂，如glTF中粗糙度系数与贴图相乘后平方得到alpha，
+    // factor可以是标量或RGB，如glTF中的基础色系数
+    factor: V3f,
+    exponent: f32,
 }
 
 impl ImageTexture {
     pub fn from_json(json: &Value) -> Self {
         let file_path = json["file"].as_str().unwrap();
-        let img = load_img(file_path).expect("Read Image Error!");
-        let img = Rc::new(img);
-        let size = img.dimensions();
+        let options = MipMapOptions::from_json(json);
+        let pyramid = fetch_pyramid(file_path, options.pyramid_filter);
+        let size = pyramid[0].dimensions();
         let size = Vector2::new(size.0 as usize, size.1 as usize);
+        let channel = json["channel"].as_str().map(|c| match c {
+            "r" => 0,
+            "g" => 1,
+            "b" => 2,
+            c => panic!("Invalid image channel: {}!", c),
+        });
         Self {
             size,
-            mapping: Rc::new(UVMapping {}),
-            mipmap: Rc::new(MipMap::new(img)),
+            mapping: fetch_mapping(json),
+            mipmap: Rc::new(MipMap::with_pyramid(pyramid, options)),
+            channel,
+            factor: SpectrumRGB::from_json(&json["factor"])
+                .map_or(V3f::new(1.0, 1.0, 1.0), |f| f.rgb()),
+            exponent: json["exponent"].as_f64().unwrap_or(1.0) as f32,
         }
     }
 }
 
-impl Texture<SpectrumRGB> for ImageTexture {
+impl<TReturn: TextureValue> Texture<TReturn> for ImageTexture {
     fn size(&self) -> Vector2<usize> {
         self.size
     }
@@ -38,16 +77,22 @@ impl Texture<SpectrumRGB> for ImageTexture {
         self.mapping.clone()
     }
 
-    fn evaluate(&self, intersection: &SurfaceInteraction) -> SpectrumRGB {
-        let tex_coord = self.mapping.map(intersection);
-        self.evaluate_coord(&tex_coord)
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
     }
 
-    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> SpectrumRGB {
-        SpectrumRGB::from_rgb(self.mipmap.look_up(
-            tex_coord.coord,
-            tex_coord.duv_dx,
-            tex_coord.duv_dy,
-        ))
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let rgb = self
+            .mipmap
+            .look_up(tex_coord.coord, tex_coord.duv_dx, tex_coord.duv_dy);
+        let rgb = match self.channel {
+            Some(c) => V3f::from([rgb[c]; 3]),
+            None => rgb,
+        };
+        if self.factor == V3f::new(1.0, 1.0, 1.0) && self.exponent == 1.0 {
+            return TReturn::from_rgb(rgb);
+        }
+        let rgb = rgb.mul_element_wise(self.factor);
+        TReturn::from_rgb(rgb.map(|x| x.max(0.0).powf(self.exponent)))
     }
 }
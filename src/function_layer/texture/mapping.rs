@@ -0,0 +1,257 @@
+use super::texture::{TextureCoord, TextureMapping, UVMapping};
+use crate::core_layer::constants::INV_PI;
+use crate::core_layer::transform::Transform;
+use crate::function_layer::{fetch_v3f, SurfaceInteraction, V3f};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix2, Point3, Vector2};
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 在纹理空间中做球面映射，y轴为极轴
+pub struct SphericalMapping {
+    transform: Transform,
+}
+
+/// 在纹理空间中做柱面映射，y轴为柱面的轴
+pub struct CylindricalMapping {
+    transform: Transform,
+}
+
+/// 将纹理空间中的点投影到vs、vt张成的平面上
+pub struct PlanarMapping {
+    transform: Transform,
+    vs: V3f,
+    vt: V3f,
+    ds: f32,
+    dt: f32,
+}
+
+/// 沿三个坐标轴分别做平面投影，并按法线方向混合
+pub struct TriplanarMapping {
+    transform: Transform,
+    sharpness: f32,
+}
+
+/// 在其他映射得到的uv上再做缩放、旋转和平移
+pub struct UVTransformMapping {
+    inner: Rc<dyn TextureMapping>,
+    matrix: Matrix2<f32>,
+    offset: Vector2<f32>,
+}
+
+fn fetch_transform(json: &Value) -> Transform {
+    match json.get("transform") {
+        Some(t) => Transform::from_json(t),
+        None => Transform::identity(),
+    }
+}
+
+fn local_position(transform: &Transform, intersection: &SurfaceInteraction) -> Point3<f32> {
+    transform.to_local_point(intersection.position)
+}
+
+// 对非线性映射用有限差分估计纹理坐标的微分，并处理u方向跨越接缝的情况
+fn finite_difference(
+    map: impl Fn(Point3<f32>) -> Vector2<f32>,
+    transform: &Transform,
+    intersection: &SurfaceInteraction,
+) -> TextureCoord {
+    let p = local_position(transform, intersection);
+    let coord = map(p);
+    let delta = |dp: V3f| {
+        let mut d = map(p + transform.to_local_vec(dp)) - coord;
+        if d.x > 0.5 {
+            d.x -= 1.0;
+        } else if d.x < -0.5 {
+            d.x += 1.0;
+        }
+        d
+    };
+    TextureCoord {
+        coord,
+        duv_dx: delta(intersection.dp_dx),
+        duv_dy: delta(intersection.dp_dy),
+    }
+}
+
+impl SphericalMapping {
+    fn sphere(p: Point3<f32>) -> Vector2<f32> {
+        let dir = p.to_vec().normalize();
+        let theta = dir.y.clamp(-1.0, 1.0).acos();
+        let mut phi = dir.x.atan2(dir.z);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        Vector2::new(phi * INV_PI * 0.5, theta * INV_PI)
+    }
+}
+
+impl TextureMapping for SphericalMapping {
+    fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord {
+        finite_difference(Self::sphere, &self.transform, intersection)
+    }
+}
+
+impl CylindricalMapping {
+    fn cylinder(p: Point3<f32>) -> Vector2<f32> {
+        let mut phi = p.x.atan2(p.z);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        Vector2::new(phi * INV_PI * 0.5, p.y)
+    }
+}
+
+impl TextureMapping for CylindricalMapping {
+    fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord {
+        finite_difference(Self::cylinder, &self.transform, intersection)
+    }
+}
+
+impl TextureMapping for PlanarMapping {
+    fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord {
+        let p = local_position(&self.transform, intersection).to_vec();
+        let dp_dx = self.transform.to_local_vec(intersection.dp_dx);
+        let dp_dy = self.transform.to_local_vec(intersection.dp_dy);
+        TextureCoord {
+            coord: Vector2::new(self.ds + p.dot(self.vs), self.dt + p.dot(self.vt)),
+            duv_dx: Vector2::new(dp_dx.dot(self.vs), dp_dx.dot(self.vt)),
+            duv_dy: Vector2::new(dp_dy.dot(self.vs), dp_dy.dot(self.vt)),
+        }
+    }
+}
+
+impl TriplanarMapping {
+    // 分别投影到垂直于x、y、z轴的平面上
+    fn project(axis: usize, v: V3f) -> Vector2<f32> {
+        match axis {
+            0 => Vector2::new(v.z, v.y),
+            1 => Vector2::new(v.x, v.z),
+            _ => Vector2::new(v.x, v.y),
+        }
+    }
+
+    fn weights(&self, intersection: &SurfaceInteraction) -> V3f {
+        let n = self.transform.to_local_vec(intersection.normal).normalize();
+        let w = V3f::new(
+            n.x.abs().powf(self.sharpness),
+            n.y.abs().powf(self.sharpness),
+            n.z.abs().powf(self.sharpness),
+        );
+        w / (w.x + w.y + w.z)
+    }
+
+    fn map_axis(&self, axis: usize, intersection: &SurfaceInteraction) -> TextureCoord {
+        let p = local_position(&self.transform, intersection).to_vec();
+        let dp_dx = self.transform.to_local_vec(intersection.dp_dx);
+        let dp_dy = self.transform.to_local_vec(intersection.dp_dy);
+        TextureCoord {
+            coord: Self::project(axis, p),
+            duv_dx: Self::project(axis, dp_dx),
+            duv_dy: Self::project(axis, dp_dy),
+        }
+    }
+}
+
+impl TextureMapping for TriplanarMapping {
+    fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord {
+        // 只需要一个坐标时取权重最大的投影方向
+        let w = self.weights(intersection);
+        let axis = if w.x > w.y && w.x > w.z {
+            0
+        } else if w.y > w.z {
+            1
+        } else {
+            2
+        };
+        self.map_axis(axis, intersection)
+    }
+
+    fn map_weighted(&self, intersection: &SurfaceInteraction) -> Vec<(f32, TextureCoord)> {
+        let w = self.weights(intersection);
+        (0..3)
+            .filter(|&axis| w[axis] > 0.0)
+            .map(|axis| (w[axis], self.map_axis(axis, intersection)))
+            .collect()
+    }
+}
+
+impl UVTransformMapping {
+    fn apply(&self, tex_coord: TextureCoord) -> TextureCoord {
+        TextureCoord {
+            coord: self.matrix * tex_coord.coord + self.offset,
+            duv_dx: self.matrix * tex_coord.duv_dx,
+            duv_dy: self.matrix * tex_coord.duv_dy,
+        }
+    }
+}
+
+impl TextureMapping for UVTransformMapping {
+    fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord {
+        self.apply(self.inner.map(intersection))
+    }
+
+    fn map_weighted(&self, intersection: &SurfaceInteraction) -> Vec<(f32, TextureCoord)> {
+        self.inner
+            .map_weighted(intersection)
+            .into_iter()
+            .map(|(w, tex_coord)| (w, self.apply(tex_coord)))
+            .collect()
+    }
+}
+
+/// 读取纹理json中的"mapping"和"uvTransform"字段，缺省时使用UVMapping
+pub fn fetch_mapping(json: &Value) -> Rc<dyn TextureMapping> {
+    let mapping_json = &json["mapping"];
+    let tp = if mapping_json.is_string() {
+        mapping_json.as_str().unwrap()
+    } else {
+        mapping_json["type"].as_str().unwrap_or("uv")
+    };
+    let mapping: Rc<dyn TextureMapping> = match tp {
+        "uv" => Rc::new(UVMapping {}),
+        "spherical" => Rc::new(SphericalMapping {
+            transform: fetch_transform(mapping_json),
+        }),
+        "cylindrical" => Rc::new(CylindricalMapping {
+            transform: fetch_transform(mapping_json),
+        }),
+        "planar" => Rc::new(PlanarMapping {
+            transform: fetch_transform(mapping_json),
+            vs: fetch_v3f(mapping_json, "vs", V3f::new(1.0, 0.0, 0.0)),
+            vt: fetch_v3f(mapping_json, "vt", V3f::new(0.0, 0.0, 1.0)),
+            ds: mapping_json["ds"].as_f64().unwrap_or(0.0) as f32,
+            dt: mapping_json["dt"].as_f64().unwrap_or(0.0) as f32,
+        }),
+        "triplanar" => Rc::new(TriplanarMapping {
+            transform: fetch_transform(mapping_json),
+            sharpness: mapping_json["sharpness"].as_f64().unwrap_or(4.0) as f32,
+        }),
+        tp => panic!("Invalid texture mapping: {}!", tp),
+    };
+    let uv_transform = &json["uvTransform"];
+    if uv_transform.is_null() {
+        return mapping;
+    }
+    let scale = match &uv_transform["scale"] {
+        Value::Null => Vector2::new(1.0, 1.0),
+        s if s.is_number() => {
+            let s = s.as_f64().unwrap() as f32;
+            Vector2::new(s, s)
+        }
+        s => Vector2::from(serde_json::from_value::<[f32; 2]>(s.clone()).unwrap()),
+    };
+    let offset = match &uv_transform["offset"] {
+        Value::Null => Vector2::new(0.0, 0.0),
+        o => Vector2::from(serde_json::from_value::<[f32; 2]>(o.clone()).unwrap()),
+    };
+    let radian = uv_transform["rotate"].as_f64().unwrap_or(0.0) as f32;
+    let (sin, cos) = radian.sin_cos();
+    // cgmath的矩阵为列主序：先缩放后旋转
+    let matrix = Matrix2::new(cos, sin, -sin, cos) * Matrix2::new(scale.x, 0.0, 0.0, scale.y);
+    Rc::new(UVTransformMapping {
+        inner: mapping,
+        matrix,
+        offset,
+    })
+}
@@ -1,44 +1,181 @@
 use crate::function_layer::V3f;
-use cgmath::{num_traits::clamp, Vector2};
+use cgmath::{num_traits::clamp, InnerSpace, Vector2, Zero};
 use image::imageops::FilterType;
 use image::Rgb32FImage;
+use serde_json::Value;
+use std::mem::swap;
 use std::rc::Rc;
 
+/// 纹理坐标超出[0, 1]时的处理方式
+#[derive(Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+    Border(V3f),
+}
+
+/// 纹理查询时的滤波方式
+#[derive(Copy, Clone, PartialEq)]
+pub enum FilterMode {
+    Nearest,
+    Bilinear,
+    Trilinear,
+    Ewa,
+}
+
+/// 生成mipmap金字塔时使用的降采样滤波器
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PyramidFilter {
+    Box,
+    Lanczos,
+}
+
+#[derive(Copy, Clone)]
+pub struct MipMapOptions {
+    pub wrap: WrapMode,
+    pub filter: FilterMode,
+    pub pyramid_filter: PyramidFilter,
+    pub max_anisotropy: f32,
+}
+
+impl Default for MipMapOptions {
+    fn default() -> Self {
+        Self {
+            wrap: WrapMode::Clamp,
+            filter: FilterMode::Trilinear,
+            pyramid_filter: PyramidFilter::Box,
+            max_anisotropy: 8.0,
+        }
+    }
+}
+
+impl MipMapOptions {
+    pub fn from_json(json: &Value) -> Self {
+        let dft = Self::default();
+        let wrap = match json["wrap"].as_str().unwrap_or("clamp") {
+            "repeat" => WrapMode::Repeat,
+            "mirror" => WrapMode::Mirror,
+            "clamp" => WrapMode::Clamp,
+            "border" => {
+                let color = serde_json::from_value::<[f32; 3]>(json["border"].clone())
+                    .unwrap_or([0.0; 3]);
+                WrapMode::Border(V3f::from(color))
+            }
+            tp => panic!("Invalid wrap mode: {}!", tp),
+        };
+        let filter = match json["filter"].as_str().unwrap_or("trilinear") {
+            "nearest" => FilterMode::Nearest,
+            "bilinear" => FilterMode::Bilinear,
+            "trilinear" => FilterMode::Trilinear,
+            "ewa" => FilterMode::Ewa,
+            tp => panic!("Invalid filter mode: {}!", tp),
+        };
+        let pyramid_filter = match json["pyramidFilter"].as_str().unwrap_or("box") {
+            "box" => PyramidFilter::Box,
+            "lanczos" => PyramidFilter::Lanczos,
+            tp => panic!("Invalid pyramid filter: {}!", tp),
+        };
+        let max_anisotropy = json["maxAnisotropy"]
+            .as_f64()
+            .map_or(dft.max_anisotropy, |a| a as f32)
+            .max(1.0);
+        Self {
+            wrap,
+            filter,
+            pyramid_filter,
+            max_anisotropy,
+        }
+    }
+}
+
 pub struct MipMap {
-    pub pyramid: Vec<Rc<Rgb32FImage>>,
+    pub pyramid: Rc<Vec<Rc<Rgb32FImage>>>,
+    options: MipMapOptions,
+}
+
+// 按面积加权的盒式降采样，支持任意尺寸
+fn box_downsample(img: &Rgb32FImage, w: u32, h: u32) -> Rgb32FImage {
+    let (pw, ph) = img.dimensions();
+    let sx = pw as f32 / w as f32;
+    let sy = ph as f32 / h as f32;
+    Rgb32FImage::from_fn(w, h, |x, y| {
+        let (x0, x1) = (x as f32 * sx, (x + 1) as f32 * sx);
+        let (y0, y1) = (y as f32 * sy, (y + 1) as f32 * sy);
+        let mut sum = V3f::zero();
+        for py in y0.floor() as u32..(y1.ceil() as u32).min(ph) {
+            let wy = (y1.min(py as f32 + 1.0) - y0.max(py as f32)).max(0.0);
+            for px in x0.floor() as u32..(x1.ceil() as u32).min(pw) {
+                let wx = (x1.min(px as f32 + 1.0) - x0.max(px as f32)).max(0.0);
+                sum += V3f::from(img.get_pixel(px, py).0) * (wx * wy);
+            }
+        }
+        let avg = sum / (sx * sy);
+        image::Rgb([avg.x, avg.y, avg.z])
+    })
 }
 
 impl MipMap {
-    pub fn new(origin: Rc<Rgb32FImage>) -> Self {
+    /// 使用已经生成好的金字塔，多个纹理可以共享同一份金字塔而使用不同的查询方式
+    pub fn with_pyramid(pyramid: Rc<Vec<Rc<Rgb32FImage>>>, options: MipMapOptions) -> Self {
+        Self { pyramid, options }
+    }
+
+    pub fn build_pyramid(origin: Rc<Rgb32FImage>, filter: PyramidFilter) -> Vec<Rc<Rgb32FImage>> {
         let size = origin.dimensions();
-        if !size.0.is_power_of_two() {
-            panic!("目前只支持对长宽为2的次幂的图片做mipmap");
-        }
         let n_levels = 1 + size.0.max(size.1).ilog2();
         let mut pyramid = Vec::with_capacity(n_levels as usize);
         pyramid.push(origin);
         for _ in 1..n_levels {
             let previous = pyramid.last().unwrap();
             let p_size = previous.dimensions();
-            let current = image::imageops::resize(
-                previous.as_ref(),
-                p_size.0 / 2,
-                p_size.1 / 2,
-                FilterType::Nearest,
-            );
+            let (w, h) = ((p_size.0 / 2).max(1), (p_size.1 / 2).max(1));
+            let current = match filter {
+                PyramidFilter::Box => box_downsample(previous, w, h),
+                PyramidFilter::Lanczos => {
+                    image::imageops::resize(previous.as_ref(), w, h, FilterType::Lanczos3)
+                }
+            };
             pyramid.push(Rc::new(current));
         }
-        Self { pyramid }
+        pyramid
     }
 
     pub fn texel(&self, level: u32, x: i64, y: i64) -> V3f {
         let image = &self.pyramid[level as usize];
-        let x = clamp(x, 0, image.dimensions().0 as i64 - 1);
-        let y = clamp(y, 0, image.dimensions().1 as i64 - 1);
+        let (w, h) = (image.dimensions().0 as i64, image.dimensions().1 as i64);
+        let (x, y) = match self.options.wrap {
+            WrapMode::Repeat => (x.rem_euclid(w), y.rem_euclid(h)),
+            WrapMode::Mirror => {
+                let mirror = |c: i64, n: i64| {
+                    let c = c.rem_euclid(2 * n);
+                    if c >= n {
+                        2 * n - 1 - c
+                    } else {
+                        c
+                    }
+                };
+                (mirror(x, w), mirror(y, h))
+            }
+            WrapMode::Clamp => (clamp(x, 0, w - 1), clamp(y, 0, h - 1)),
+            WrapMode::Border(color) => {
+                if x < 0 || x >= w || y < 0 || y >= h {
+                    return color;
+                }
+                (x, y)
+            }
+        };
         let rbg = image.get_pixel(x as u32, y as u32).0;
         V3f::from(rbg)
     }
 
+    pub fn nearest(&self, level: u32, uv: Vector2<f32>) -> V3f {
+        let (x, y) = self.pyramid[level as usize].dimensions();
+        let x = (uv.x * x as f32).floor() as i64;
+        let y = (uv.y * y as f32).floor() as i64;
+        self.texel(level, x, y)
+    }
+
     pub fn bilinear(&self, level: u32, uv: Vector2<f32>) -> V3f {
         let level = clamp(level, 0, self.pyramid.len() as u32 - 1);
         let (x, y) = self.pyramid[level as usize].dimensions();
@@ -55,6 +192,15 @@ impl MipMap {
     }
 
     pub fn look_up(&self, uv: Vector2<f32>, duv0: Vector2<f32>, duv1: Vector2<f32>) -> V3f {
+        match self.options.filter {
+            FilterMode::Nearest => self.nearest(0, uv),
+            FilterMode::Bilinear => self.bilinear(0, uv),
+            FilterMode::Trilinear => self.trilinear(uv, duv0, duv1),
+            FilterMode::Ewa => self.look_up_ewa(uv, duv0, duv1),
+        }
+    }
+
+    fn trilinear(&self, uv: Vector2<f32>, duv0: Vector2<f32>, duv1: Vector2<f32>) -> V3f {
         let width = duv0
             .x
             .abs()
@@ -62,8 +208,6 @@ impl MipMap {
             .max(duv1.x.abs().max(duv1.y.abs()));
 
         let level = self.pyramid.len() as f32 - 1.0 + width.max(1e-8).log2();
-        // let x = uv.x * self.pyramid[0].dimensions().0 as f32;
-        // let y = uv.y * self.pyramid[0].dimensions().0 as f32;
         if level < 0.0 {
             self.bilinear(0, uv)
         } else if level >= self.pyramid.len() as f32 - 1.0 {
@@ -74,4 +218,78 @@ impl MipMap {
             (1.0 - dl) * self.bilinear(i_level, uv) + dl * self.bilinear(i_level + 1, uv)
         }
     }
+
+    fn look_up_ewa(&self, uv: Vector2<f32>, duv0: Vector2<f32>, duv1: Vector2<f32>) -> V3f {
+        let (mut major, mut minor) = (duv0, duv1);
+        if major.magnitude2() < minor.magnitude2() {
+            swap(&mut major, &mut minor);
+        }
+        let major_len = major.magnitude();
+        let mut minor_len = minor.magnitude();
+        if minor_len == 0.0 {
+            return self.bilinear(0, uv);
+        }
+        // 限制椭圆的离心率，避免过多的texel参与计算
+        if minor_len * self.options.max_anisotropy < major_len {
+            let scale = major_len / (minor_len * self.options.max_anisotropy);
+            minor *= scale;
+            minor_len *= scale;
+        }
+        let last = self.pyramid.len() as f32 - 1.0;
+        let lod = (last + minor_len.log2()).max(0.0);
+        if lod >= last {
+            return self.texel(last as u32, 0, 0);
+        }
+        let i_lod = lod.floor() as u32;
+        let dl = lod - i_lod as f32;
+        (1.0 - dl) * self.ewa(i_lod, uv, major, minor) + dl * self.ewa(i_lod + 1, uv, major, minor)
+    }
+
+    fn ewa(&self, level: u32, uv: Vector2<f32>, duv0: Vector2<f32>, duv1: Vector2<f32>) -> V3f {
+        let (w, h) = self.pyramid[level as usize].dimensions();
+        let (w, h) = (w as f32, h as f32);
+        let s = uv.x * w - 0.5;
+        let t = uv.y * h - 0.5;
+        let d0 = Vector2::new(duv0.x * w, duv0.y * h);
+        let d1 = Vector2::new(duv1.x * w, duv1.y * h);
+        // 计算椭圆的隐式方程系数 A*s^2 + B*s*t + C*t^2 < 1
+        let mut a = d0.y * d0.y + d1.y * d1.y + 1.0;
+        let mut b = -2.0 * (d0.x * d0.y + d1.x * d1.y);
+        let mut c = d0.x * d0.x + d1.x * d1.x + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+        let s0 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        const ALPHA: f32 = 2.0;
+        let exp_alpha = (-ALPHA).exp();
+        let mut sum = V3f::zero();
+        let mut sum_wts = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f32 - t;
+            for is in s0..=s1 {
+                let ss = is as f32 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    // 截断的高斯滤波
+                    let weight = (-ALPHA * r2).exp() - exp_alpha;
+                    sum += self.texel(level, is, it) * weight;
+                    sum_wts += weight;
+                }
+            }
+        }
+        if sum_wts > 0.0 {
+            sum / sum_wts
+        } else {
+            self.bilinear(level, uv)
+        }
+    }
 }
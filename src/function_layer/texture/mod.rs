@@ -1,7 +1,14 @@
+pub mod checkerboard;
 pub mod constant_texture;
+pub mod gradient;
+pub mod grid;
 pub mod image_texture;
+pub mod mapping;
 pub mod mipmap;
+pub mod noise;
 pub mod normal_texture;
 pub mod texture;
+pub mod vertex_color;
+pub mod voronoi;
 
 pub use texture::{Texture, TextureCoord};
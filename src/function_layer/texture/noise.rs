@@ -0,0 +1,136 @@
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, fetch_texture, fetch_uv_scale, TextureMapping, TextureValue};
+use super::{Texture, TextureCoord};
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+/// Perlin噪声及其分形叠加（fBm），噪声值在tex1与tex2之间插值
+pub struct NoiseTexture<TReturn> {
+    mapping: Rc<dyn TextureMapping>,
+    scale: Vector2<f32>,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+    turbulence: bool,
+    tex1: Rc<dyn Texture<TReturn>>,
+    tex2: Rc<dyn Texture<TReturn>>,
+}
+
+impl<TReturn: TextureValue> NoiseTexture<TReturn> {
+    pub fn from_json(json: &Value, default_octaves: u32) -> Self {
+        let tex1 = fetch_texture(json, "tex1", TReturn::from_rgb(V3f::zero()));
+        let tex2 = fetch_texture(json, "tex2", TReturn::from_rgb(V3f::from([1.0; 3])));
+        let octaves = json["octaves"].as_u64().unwrap_or(default_octaves as u64) as u32;
+        let lacunarity = json["lacunarity"].as_f64().unwrap_or(2.0) as f32;
+        let gain = json["gain"].as_f64().unwrap_or(0.5) as f32;
+        let turbulence = json["turbulence"].as_bool().unwrap_or(false);
+        Self {
+            mapping: fetch_mapping(json),
+            scale: fetch_uv_scale(json),
+            octaves: octaves.max(1),
+            lacunarity,
+            gain,
+            turbulence,
+            tex1,
+            tex2,
+        }
+    }
+
+    fn fbm(&self, p: Vector2<f32>) -> f32 {
+        let (mut sum, mut amplitude, mut frequency, mut norm) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..self.octaves {
+            let n = perlin(p * frequency);
+            sum += amplitude * if self.turbulence { n.abs() } else { n };
+            norm += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        sum / norm
+    }
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for NoiseTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        self.mapping.clone()
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
+    }
+
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let p = Vector2::new(
+            tex_coord.coord.x * self.scale.x,
+            tex_coord.coord.y * self.scale.y,
+        );
+        let n = self.fbm(p);
+        // 湍流模式的值域为[0, 1]，否则为[-1, 1]
+        let alpha = if self.turbulence { n } else { 0.5 + 0.5 * n }.clamp(0.0, 1.0);
+        self.tex1.evaluate_coord(tex_coord) * (1.0 - alpha)
+            + self.tex2.evaluate_coord(tex_coord) * alpha
+    }
+}
+
+static PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225,
+    140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148,
+    247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32,
+    57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122,
+    60, 211, 133, 230, 220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54,
+    65, 25, 63, 161, 1, 216, 80, 73, 209, 76, 132, 187, 208, 89, 18, 169,
+    200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173, 186, 3, 64,
+    52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212,
+    207, 206, 59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213,
+    119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101, 155, 167, 43, 172, 9,
+    129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232, 178, 185, 112, 104,
+    218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162, 241,
+    81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157,
+    184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93,
+    222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+#[inline]
+fn perm(i: i32) -> i32 {
+    PERMUTATION[(i & 255) as usize] as i32
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn grad(hash: i32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// 二维改进Perlin噪声，返回值约在[-1, 1]之间
+pub fn perlin(p: Vector2<f32>) -> f32 {
+    let (xf, yf) = (p.x.floor(), p.y.floor());
+    let (xi, yi) = (xf as i32, yf as i32);
+    let (dx, dy) = (p.x - xf, p.y - yf);
+    let h00 = perm(perm(xi) + yi);
+    let h10 = perm(perm(xi + 1) + yi);
+    let h01 = perm(perm(xi) + yi + 1);
+    let h11 = perm(perm(xi + 1) + yi + 1);
+    let (u, v) = (fade(dx), fade(dy));
+    let x0 = grad(h00, dx, dy) * (1.0 - u) + grad(h10, dx - 1.0, dy) * u;
+    let x1 = grad(h01, dx, dy - 1.0) * (1.0 - u) + grad(h11, dx - 1.0, dy - 1.0) * u;
+    x0 * (1.0 - v) + x1 * v
+}
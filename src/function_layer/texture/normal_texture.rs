@@ -1,7 +1,9 @@
-use super::texture::{Texture, TextureCoord, TextureMapping, UVMapping};
+use super::mapping::fetch_mapping;
+use super::texture::{Texture, TextureCoord, TextureMapping};
 use crate::function_layer::{SurfaceInteraction, V3f};
 use cgmath::{num_traits::clamp, Vector2};
 use image::Rgb32FImage;
+use crate::resource_layer::image_io::load_img_cached;
 use serde_json::Value;
 use std::rc::Rc;
 
@@ -14,17 +16,13 @@ pub struct NormalTexture {
 impl NormalTexture {
     pub fn from_json(json: &Value) -> Self {
         let relative_path = json["file"].as_str().unwrap();
-        let normal_map = image::io::Reader::open(relative_path)
-            .expect("Open image error!")
-            .decode()
-            .expect("Decode error!")
-            .to_rgb32f();
+        let normal_map = load_img_cached(relative_path).expect("Read Image Error!");
         let size = normal_map.dimensions();
-        let mapping = Rc::new(UVMapping {});
+        let mapping = fetch_mapping(json);
         Self {
             size: Vector2::new(size.0 as usize, size.1 as usize),
             mapping,
-            normal_map: Rc::new(normal_map),
+            normal_map,
         }
     }
 }
@@ -1,8 +1,19 @@
+use super::checkerboard::CheckerboardTexture;
+use super::constant_texture::ConstantTexture;
+use super::gradient::GradientTexture;
+use super::grid::GridTexture;
 use super::image_texture::ImageTexture;
+use super::noise::NoiseTexture;
+use super::vertex_color::VertexColorTexture;
+use super::voronoi::VoronoiTexture;
 use crate::core_layer::colorspace::SpectrumRGB;
-use crate::function_layer::SurfaceInteraction;
+use crate::function_layer::{SurfaceInteraction, V3f};
 use cgmath::{Vector2, Zero};
 use serde_json::Value;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
 use std::rc::Rc;
 
 pub struct TextureCoord {
@@ -23,6 +34,10 @@ impl Default for TextureCoord {
 
 pub trait TextureMapping {
     fn map(&self, intersection: &SurfaceInteraction) -> TextureCoord;
+    /// 需要混合多个纹理坐标的映射（如三平面映射）返回每个坐标及其权重
+    fn map_weighted(&self, intersection: &SurfaceInteraction) -> Vec<(f32, TextureCoord)> {
+        vec![(1.0, self.map(intersection))]
+    }
 }
 
 pub struct UVMapping;
@@ -44,9 +59,149 @@ pub trait Texture<TReturn> {
     fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn;
 }
 
-pub fn construct_texture<TReturn>(json: &Value) -> Rc<dyn Texture<SpectrumRGB>> {
-    match json["type"].as_str().expect("No spectrum type given!") {
+/// 按映射得到的（可能多个）纹理坐标对纹理求值
+pub fn evaluate_mapped<TReturn: TextureValue>(
+    texture: &dyn Texture<TReturn>,
+    mapping: &dyn TextureMapping,
+    intersection: &SurfaceInteraction,
+) -> TReturn {
+    let mut coords = mapping.map_weighted(intersection).into_iter();
+    let (w, tex_coord) = coords.next().unwrap();
+    let first = texture.evaluate_coord(&tex_coord);
+    if w == 1.0 {
+        return first;
+    }
+    coords.fold(first * w, |acc, (w, tex_coord)| {
+        acc + texture.evaluate_coord(&tex_coord) * w
+    })
+}
+
+/// 纹理可以返回的值类型：光谱、标量（粗糙度、折射率、混合权重等）以及二维的各向异性粗糙度
+pub trait TextureValue: Copy + Add<Output = Self> + Mul<f32, Output = Self> + 'static {
+    /// 由图片中读取的RGB值转换
+    fn from_rgb(rgb: V3f) -> Self;
+    /// 由json中的常量转换
+    fn from_json(json: &Value) -> Option<Self>;
+}
+
+impl TextureValue for SpectrumRGB {
+    fn from_rgb(rgb: V3f) -> Self {
+        SpectrumRGB::from_rgb(rgb)
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        if let Some(f) = json.as_f64() {
+            Some(SpectrumRGB::same(f as f32))
+        } else {
+            let rgb = serde_json::from_value::<[f32; 3]>(json.clone()).ok()?;
+            Some(SpectrumRGB::from_rgb(V3f::from(rgb)))
+        }
+    }
+}
+
+impl TextureValue for f32 {
+    fn from_rgb(rgb: V3f) -> Self {
+        SpectrumRGB::from_rgb(rgb).luminance()
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        json.as_f64().map(|f| f as f32)
+    }
+}
+
+impl TextureValue for Vector2<f32> {
+    fn from_rgb(rgb: V3f) -> Self {
+        let r = f32::from_rgb(rgb);
+        Vector2::new(r, r)
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        if let Some(f) = json.as_f64() {
+            Some(Vector2::new(f as f32, f as f32))
+        } else {
+            let v = serde_json::from_value::<[f32; 2]>(json.clone()).ok()?;
+            Some(Vector2::from(v))
+        }
+    }
+}
+
+/// 读取json中的某个字段作为纹理，既可以是常量也可以是嵌套的纹理定义
+pub fn fetch_texture<TReturn: TextureValue>(
+    json: &Value,
+    field: &str,
+    dft: TReturn,
+) -> Rc<dyn Texture<TReturn>> {
+    let val = &json[field];
+    if val.is_object() || val.is_string() {
+        construct_texture::<TReturn>(val)
+    } else {
+        let data = if val.is_null() {
+            dft
+        } else {
+            TReturn::from_json(val)
+                .unwrap_or_else(|| panic!("Error in {} format!", field))
+        };
+        Rc::new(ConstantTexture::new(&data))
+    }
+}
+
+/// 读取纹理的uv缩放系数，可以是标量或二维数组
+pub fn fetch_uv_scale(json: &Value) -> Vector2<f32> {
+    Vector2::<f32>::from_json(&json["scale"]).unwrap_or(Vector2::new(1.0, 1.0))
+}
+
+thread_local! {
+    // 场景中"textures"字段定义的具名纹理，以及按名称和返回值类型缓存的纹理实例
+    static TEXTURE_LIBRARY: RefCell<HashMap<String, Value>> = RefCell::new(HashMap::new());
+    static TEXTURE_INSTANCES: RefCell<HashMap<(String, TypeId), Rc<dyn Any>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// 注册场景中的具名纹理，纹理在第一次被引用时才会构造
+pub fn register_textures(json: &Value) {
+    let Some(textures) = json.as_object() else {
+        return;
+    };
+    TEXTURE_LIBRARY.with(|lib| {
+        let mut lib = lib.borrow_mut();
+        for (name, tex) in textures {
+            lib.insert(name.clone(), tex.clone());
+        }
+    });
+}
+
+fn fetch_named_texture<TReturn: TextureValue>(name: &str) -> Rc<dyn Texture<TReturn>> {
+    let key = (name.to_string(), TypeId::of::<TReturn>());
+    let cached = TEXTURE_INSTANCES.with(|ins| ins.borrow().get(&key).cloned());
+    if let Some(texture) = cached {
+        return texture
+            .downcast_ref::<Rc<dyn Texture<TReturn>>>()
+            .unwrap()
+            .clone();
+    }
+    let json = TEXTURE_LIBRARY
+        .with(|lib| lib.borrow().get(name).cloned())
+        .unwrap_or_else(|| panic!("Undefined texture: {}!", name));
+    let texture = construct_texture::<TReturn>(&json);
+    TEXTURE_INSTANCES.with(|ins| ins.borrow_mut().insert(key, Rc::new(texture.clone())));
+    texture
+}
+
+/// 根据json构造纹理，json为字符串时引用场景中的具名纹理
+pub fn construct_texture<TReturn: TextureValue>(json: &Value) -> Rc<dyn Texture<TReturn>> {
+    if let Some(name) = json.as_str() {
+        return fetch_named_texture(name);
+    }
+    match json["type"].as_str().expect("No texture type given!") {
         "imageTex" => Rc::new(ImageTexture::from_json(json)),
-        _ => panic!("Invalid spectrum type!"),
+        "constant" => fetch_texture(json, "value", TReturn::from_rgb(V3f::zero())),
+        "checkerboard" => Rc::new(CheckerboardTexture::from_json(json)),
+        "grid" => Rc::new(GridTexture::from_json(json)),
+        "perlin" => Rc::new(NoiseTexture::from_json(json, 1)),
+        "fbm" => Rc::new(NoiseTexture::from_json(json, 6)),
+        "voronoi" => Rc::new(VoronoiTexture::from_json(json)),
+        "gradient" => Rc::new(GradientTexture::from_json(json)),
+        "vertexColor" => Rc::new(VertexColorTexture::from_json(json)),
+        tp => panic!("Invalid texture type: {}!", tp),
     }
 }
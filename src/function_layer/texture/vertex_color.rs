@@ -0,0 +1,41 @@
+use super::texture::{TextureCoord, TextureMapping, TextureValue, UVMapping};
+use super::Texture;
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+/// 使用网格的顶点颜色，交点没有顶点颜色时返回default
+pub struct VertexColorTexture<TReturn> {
+    default: TReturn,
+}
+
+impl<TReturn: TextureValue> VertexColorTexture<TReturn> {
+    pub fn from_json(json: &Value) -> Self {
+        let default =
+            TReturn::from_json(&json["default"]).unwrap_or(TReturn::from_rgb(V3f::from([1.0; 3])));
+        Self { default }
+    }
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for VertexColorTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        Rc::new(UVMapping {})
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        match intersection.vertex_color {
+            Some(rgb) => TReturn::from_rgb(rgb),
+            None => self.default,
+        }
+    }
+
+    // 顶点颜色只与交点有关，单独的纹理坐标无法求值
+    fn evaluate_coord(&self, _tex_coord: &TextureCoord) -> TReturn {
+        self.default
+    }
+}
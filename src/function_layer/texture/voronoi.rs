@@ -0,0 +1,114 @@
+use super::mapping::fetch_mapping;
+use super::texture::{evaluate_mapped, fetch_texture, fetch_uv_scale, TextureMapping, TextureValue};
+use super::{Texture, TextureCoord};
+use crate::function_layer::{SurfaceInteraction, V3f};
+use cgmath::{InnerSpace, Vector2, Zero};
+use serde_json::Value;
+use std::rc::Rc;
+
+#[derive(Copy, Clone)]
+enum VoronoiMode {
+    // 到最近特征点的距离F1
+    Distance,
+    // F2 - F1，在胞元边界处为0
+    Edge,
+    // 每个胞元取一个随机值
+    Cell,
+}
+
+/// Voronoi（Worley）胞元噪声，输出值在tex1与tex2之间插值
+pub struct VoronoiTexture<TReturn> {
+    mapping: Rc<dyn TextureMapping>,
+    scale: Vector2<f32>,
+    jitter: f32,
+    mode: VoronoiMode,
+    tex1: Rc<dyn Texture<TReturn>>,
+    tex2: Rc<dyn Texture<TReturn>>,
+}
+
+impl<TReturn: TextureValue> VoronoiTexture<TReturn> {
+    pub fn from_json(json: &Value) -> Self {
+        let tex1 = fetch_texture(json, "tex1", TReturn::from_rgb(V3f::zero()));
+        let tex2 = fetch_texture(json, "tex2", TReturn::from_rgb(V3f::from([1.0; 3])));
+        let jitter = json["jitter"].as_f64().unwrap_or(1.0) as f32;
+        let mode = match json["mode"].as_str().unwrap_or("distance") {
+            "distance" => VoronoiMode::Distance,
+            "edge" => VoronoiMode::Edge,
+            "cell" => VoronoiMode::Cell,
+            tp => panic!("Invalid voronoi mode: {}!", tp),
+        };
+        Self {
+            mapping: fetch_mapping(json),
+            scale: fetch_uv_scale(json),
+            jitter: jitter.clamp(0.0, 1.0),
+            mode,
+            tex1,
+            tex2,
+        }
+    }
+
+    fn feature_point(&self, cell: Vector2<i32>) -> Vector2<f32> {
+        let h = hash2(cell.x, cell.y);
+        let jx = (h & 0xffff) as f32 / 65536.0;
+        let jy = (h >> 16) as f32 / 65536.0;
+        Vector2::new(
+            cell.x as f32 + 0.5 + self.jitter * (jx - 0.5),
+            cell.y as f32 + 0.5 + self.jitter * (jy - 0.5),
+        )
+    }
+}
+
+// 对整数坐标做哈希，得到32位伪随机数
+fn hash2(x: i32, y: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343) ^ (y as u32).wrapping_mul(0xd8163841);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^ (h >> 16)
+}
+
+impl<TReturn: TextureValue> Texture<TReturn> for VoronoiTexture<TReturn> {
+    fn size(&self) -> Vector2<usize> {
+        Vector2::zero()
+    }
+
+    fn mapping(&self) -> Rc<dyn TextureMapping> {
+        self.mapping.clone()
+    }
+
+    fn evaluate(&self, intersection: &SurfaceInteraction) -> TReturn {
+        evaluate_mapped(self, self.mapping.as_ref(), intersection)
+    }
+
+    fn evaluate_coord(&self, tex_coord: &TextureCoord) -> TReturn {
+        let p = Vector2::new(
+            tex_coord.coord.x * self.scale.x,
+            tex_coord.coord.y * self.scale.y,
+        );
+        let base = Vector2::new(p.x.floor() as i32, p.y.floor() as i32);
+        let (mut f1, mut f2) = (f32::INFINITY, f32::INFINITY);
+        let mut nearest = base;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let cell = base + Vector2::new(dx, dy);
+                let d = (self.feature_point(cell) - p).magnitude();
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                    nearest = cell;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+        let alpha = match self.mode {
+            VoronoiMode::Distance => f1,
+            VoronoiMode::Edge => f2 - f1,
+            VoronoiMode::Cell => hash2(nearest.y, nearest.x) as f32 / u32::MAX as f32,
+        }
+        .clamp(0.0, 1.0);
+        self.tex1.evaluate_coord(tex_coord) * (1.0 - alpha)
+            + self.tex2.evaluate_coord(tex_coord) * alpha
+    }
+}
@@ -7,6 +7,7 @@ use core_layer::colorspace::SpectrumRGB;
 use function_layer::camera::CameraSample;
 use function_layer::{construct_camera, construct_integrator, construct_sampler, Camera, Scene};
 use image::ImageFormat;
+use resource_layer::gltf_import::merge_gltf_imports;
 use serde_json::Value;
 use std::env::{args, current_dir, set_current_dir};
 use std::error::Error;
@@ -18,7 +19,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("{}", current_dir().unwrap().display());
     let scene_path = "scene.json";
     let scene = BufReader::new(std::fs::File::open(scene_path).unwrap());
-    let json: Value = serde_json::from_reader(scene)?;
+    let mut json: Value = serde_json::from_reader(scene)?;
+    merge_gltf_imports(&mut json);
     let camera = construct_camera(&json["camera"]);
     let scene = Scene::from_json(&json["scene"]);
     let integrator = construct_integrator(&json["integrator"]);
@@ -32,7 +34,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         for x in 0..width {
             let ndc = Vector2::new(x as f32 / width as f32, y as f32 / height as f32);
             let mut li = SpectrumRGB::same(0.0);
-            for _ in 0..spp {
+            for i in 0..spp {
+                sampler
+                    .borrow_mut()
+                    .start_pixel_sample(Vector2::new(x, y), i);
                 let mut ray = camera.sample_ray_differentials(
                     &CameraSample {
                         xy: sampler.borrow_mut().next_2d(),
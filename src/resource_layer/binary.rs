@@ -0,0 +1,54 @@
+// 二进制文件的顺序读取，默认为小端序，数据不足时报错
+pub(super) struct BinaryReader<'a> {
+    pub(super) data: &'a [u8],
+    pub(super) pos: usize,
+    pub(super) file_path: &'a str,
+    pub(super) big_endian: bool,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .unwrap_or_else(|| panic!("Unexpected end of {}!", self.file_path));
+        self.pos += N;
+        let mut bytes: [u8; N] = bytes.try_into().unwrap();
+        if self.big_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    pub(super) fn u8(&mut self) -> u8 {
+        u8::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn i8(&mut self) -> i8 {
+        i8::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.bytes())
+    }
+
+    pub(super) fn f64(&mut self) -> f64 {
+        f64::from_le_bytes(self.bytes())
+    }
+}
@@ -0,0 +1,409 @@
+use super::image_io::insert_img_cached;
+use super::mesh::{geometric_normal, DataIndex, MeshData};
+use crate::function_layer::V3f;
+use base64::Engine;
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector2, Vector4};
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+use gltf::texture::WrappingMode;
+use gltf::{buffer, image, scene, Gltf};
+use serde_json::{json, Value};
+use std::path::Path;
+
+type M4f = Matrix4<f32>;
+
+/// 由glTF文件转换得到的场景内容，均为场景json中对应字段的格式
+#[derive(Default)]
+pub struct GltfScene {
+    pub shapes: Vec<Value>,
+    pub lights: Vec<Value>,
+    pub camera: Option<Value>,
+}
+
+/// 将场景json中"scene"/"import"指定的glTF文件(可以是数组)合并进场景
+/// 形状与光源追加到已有的列表中；json中的相机字段优先于glTF中的第一个相机
+pub fn merge_gltf_imports(json: &mut Value) {
+    let files: Vec<String> = match &json["scene"]["import"] {
+        Value::String(file) => vec![file.clone()],
+        Value::Array(files) => files
+            .iter()
+            .map(|f| f.as_str().expect("Error in import format!").to_string())
+            .collect(),
+        _ => return,
+    };
+    for file in files {
+        let imported = import_gltf(&file);
+        let scene = &mut json["scene"];
+        for (field, values) in [("shapes", imported.shapes), ("lights", imported.lights)] {
+            if !scene[field].is_array() {
+                scene[field] = json!([]);
+            }
+            scene[field].as_array_mut().unwrap().extend(values);
+        }
+        if let Some(mut camera) = imported.camera {
+            if let Some(user) = json["camera"].as_object() {
+                for (key, value) in user {
+                    camera[key] = value.clone();
+                }
+            }
+            let aspect = camera
+                .as_object_mut()
+                .unwrap()
+                .remove("aspectRatio")
+                .and_then(|a| a.as_f64())
+                .unwrap_or(4.0 / 3.0);
+            if camera["film"].is_null() {
+                camera["film"] = json!({ "size": [(512.0 * aspect).round() as usize, 512] });
+            }
+            json["camera"] = camera;
+        }
+    }
+}
+
+/// 读取.gltf或.glb文件：网格转换为triangle形状，节点层级展开为各形状的变换，
+/// metallic-roughness材质转换为matte、conductor、dielectric及其混合，
+/// KHR_lights_punctual中的点光源和聚光灯转换为spotLight，并使用第一个透视相机
+pub fn import_gltf(file_path: &str) -> GltfScene {
+    let gltf = Gltf::open(file_path)
+        .unwrap_or_else(|err| panic!("Error in parsing gltf file {}: {}", file_path, err));
+    let dir = Path::new(file_path).parent().unwrap_or(Path::new(""));
+    let buffers: Vec<Vec<u8>> = gltf
+        .buffers()
+        .map(|b| match b.source() {
+            buffer::Source::Bin => gltf.blob.clone().expect("No binary chunk in glb file!"),
+            buffer::Source::Uri(uri) => read_uri(uri, dir),
+        })
+        .collect();
+    // 图片以文件路径引用；内嵌的图片解码后以"文件名#image下标"为键放入图片缓存
+    let images: Vec<String> = gltf
+        .images()
+        .map(|img| {
+            let bytes = match img.source() {
+                image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                    return dir.join(uri).to_string_lossy().into_owned();
+                }
+                image::Source::Uri { uri, .. } => read_uri(uri, dir),
+                image::Source::View { view, .. } => {
+                    let data = &buffers[view.buffer().index()];
+                    data[view.offset()..view.offset() + view.length()].to_vec()
+                }
+            };
+            let key = format!("{}#image{}", file_path, img.index());
+            let decoded = ::image::load_from_memory(&bytes)
+                .unwrap_or_else(|err| panic!("Error in decoding {}: {}", key, err))
+                .to_rgb32f();
+            insert_img_cached(&key, decoded);
+            key
+        })
+        .collect();
+
+    let mut importer = Importer {
+        file_path,
+        buffers: &buffers,
+        images: &images,
+        result: GltfScene::default(),
+    };
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .expect("No scene in gltf file!");
+    for node in scene.nodes() {
+        importer.visit(&node, M4f::identity());
+    }
+    importer.result
+}
+
+struct Importer<'a> {
+    file_path: &'a str,
+    buffers: &'a [Vec<u8>],
+    images: &'a [String],
+    result: GltfScene,
+}
+
+impl<'a> Importer<'a> {
+    // 深度优先遍历节点层级，累积节点的变换
+    fn visit(&mut self, node: &scene::Node, parent: M4f) {
+        let world = parent * M4f::from(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if primitive.mode() != Mode::Triangles {
+                    eprintln!(
+                        "Warning: skip non-triangle primitive in mesh {} of {}",
+                        mesh.index(),
+                        self.file_path
+                    );
+                    continue;
+                }
+                let key = format!("{}#mesh{}.{}", self.file_path, mesh.index(), primitive.index());
+                let has_colors = primitive.get(&gltf::Semantic::Colors(0)).is_some();
+                if !MeshData::is_loaded(&key) {
+                    MeshData::insert_loaded(&key, self.load_primitive(&primitive));
+                }
+                self.result.shapes.push(json!({
+                    "type": "triangle",
+                    "file": key,
+                    "transform": transform_json(world),
+                    "material": self.material_json(&primitive.material(), has_colors),
+                }));
+            }
+        }
+        if let Some(light) = node.light() {
+            self.push_light(&light, world);
+        }
+        if let (Some(camera), None) = (node.camera(), &self.result.camera) {
+            if let gltf::camera::Projection::Perspective(p) = camera.projection() {
+                let position = (world * Vector4::new(0.0, 0.0, 0.0, 1.0)).truncate();
+                let forward = (world * Vector4::new(0.0, 0.0, -1.0, 0.0)).truncate();
+                let up = (world * Vector4::new(0.0, 1.0, 0.0, 0.0)).truncate();
+                let look_at = position + forward.normalize();
+                let mut camera = json!({
+                    "type": "pinhole",
+                    "transform": {
+                        "position": to_array(position),
+                        "lookAt": to_array(look_at),
+                        "up": to_array(up.normalize()),
+                    },
+                    "verticalFov": p.yfov().to_degrees(),
+                    "tNear": p.znear(),
+                });
+                if let Some(far) = p.zfar() {
+                    camera["tFar"] = json!(far);
+                }
+                if let Some(aspect) = p.aspect_ratio() {
+                    camera["aspectRatio"] = json!(aspect);
+                }
+                self.result.camera = Some(camera);
+            }
+        }
+        for child in node.children() {
+            self.visit(&child, world);
+        }
+    }
+
+    fn load_primitive(&self, primitive: &gltf::Primitive) -> MeshData {
+        let reader = primitive.reader(|b| Some(&self.buffers[b.index()]));
+        let vertex_buffer: Vec<Point3<f32>> = reader
+            .read_positions()
+            .expect("No positions in gltf primitive!")
+            .map(Point3::from)
+            .collect();
+        let mut normal_buffer: Vec<V3f> = reader
+            .read_normals()
+            .map(|n| n.map(V3f::from).collect())
+            .unwrap_or_default();
+        let mut tex_coord_buffer: Vec<Vector2<f32>> = reader
+            .read_tex_coords(0)
+            .map(|t| t.into_f32().map(Vector2::from).collect())
+            .unwrap_or_default();
+        let tangent_buffer: Vec<V3f> = reader
+            .read_tangents()
+            .map(|t| t.map(|[x, y, z, _]| V3f::new(x, y, z)).collect())
+            .unwrap_or_default();
+        let color_buffer: Vec<V3f> = reader
+            .read_colors(0)
+            .map(|c| c.into_rgb_f32().map(V3f::from).collect())
+            .unwrap_or_default();
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..vertex_buffer.len()).collect(),
+        };
+
+        let has_normals = normal_buffer.len() == vertex_buffer.len();
+        let has_tex_coords = tex_coord_buffer.len() == vertex_buffer.len();
+        if !has_normals {
+            normal_buffer.clear();
+        }
+        // 与.obj相同，没有纹理坐标时每个三角形使用(0, 0), (1, 0), (1, 1)
+        if !has_tex_coords {
+            tex_coord_buffer = vec![
+                Vector2::new(0.0, 0.0),
+                Vector2::new(1.0, 0.0),
+                Vector2::new(1.0, 1.0),
+            ];
+        }
+        let mut face_buffer = Vec::with_capacity(indices.len() / 3);
+        for corners in indices.chunks_exact(3) {
+            let mut triangle_info = [DataIndex::default(); 3];
+            for (v, info) in triangle_info.iter_mut().enumerate() {
+                info.vertex_index = corners[v];
+                info.normal_index = corners[v];
+                info.tangent_index = corners[v];
+                info.tex_coord_index = if has_tex_coords { corners[v] } else { v };
+            }
+            if !has_normals {
+                let normal_index = normal_buffer.len();
+                normal_buffer.push(geometric_normal(
+                    triangle_info.map(|d| vertex_buffer[d.vertex_index]),
+                ));
+                triangle_info
+                    .iter_mut()
+                    .for_each(|info| info.normal_index = normal_index);
+            }
+            face_buffer.push(triangle_info);
+        }
+        let has_tangents = has_normals && tangent_buffer.len() == vertex_buffer.len();
+        let mut mesh = MeshData {
+            face_count: face_buffer.len(),
+            vertex_count: vertex_buffer.len(),
+            material_buffer: vec![None; face_buffer.len()],
+            vertex_buffer,
+            normal_buffer,
+            tex_coord_buffer,
+            face_buffer,
+            tangent_buffer,
+            color_buffer,
+            materials: vec![],
+        };
+        // 文件中没有切线时与其他格式一样由纹理坐标计算
+        if !has_tangents {
+            mesh.compute_tangents();
+        }
+        mesh
+    }
+
+    fn texture_json(&self, texture: &gltf::Texture, channel: Option<&str>) -> Value {
+        let sampler = texture.sampler();
+        let wrap = match sampler.wrap_s() {
+            WrappingMode::Repeat => "repeat",
+            WrappingMode::MirroredRepeat => "mirror",
+            WrappingMode::ClampToEdge => "clamp",
+        };
+        let mut json = json!({
+            "type": "imageTex",
+            "file": self.images[texture.source().index()],
+            "wrap": wrap,
+        });
+        if let Some(channel) = channel {
+            json["channel"] = json!(channel);
+        }
+        json
+    }
+
+    // 基础色贴图与基础色系数相乘；COLOR_0存在且没有基础色贴图时使用顶点颜色
+    fn material_json(&self, material: &gltf::Material, has_colors: bool) -> Value {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let albedo = match pbr.base_color_texture() {
+            Some(info) => {
+                let mut json = self.texture_json(&info.texture(), None);
+                json["factor"] = json!([r, g, b]);
+                json
+            }
+            None if has_colors => json!({ "type": "vertexColor" }),
+            None => json!([r, g, b]),
+        };
+        // metallicRoughness贴图的G通道为粗糙度，B通道为金属度
+        let mr_texture = pbr.metallic_roughness_texture().map(|info| info.texture());
+        // 贴图中的值与系数相乘后才是感知粗糙度，平方后得到alpha
+        let roughness = match &mr_texture {
+            Some(texture) => {
+                let mut json = self.texture_json(texture, Some("g"));
+                json["factor"] = json!(pbr.roughness_factor());
+                json["exponent"] = json!(2.0);
+                json
+            }
+            None => json!(pbr.roughness_factor() * pbr.roughness_factor()),
+        };
+        let metal = json!({
+            "type": "conductor",
+            "albedo": albedo,
+            "roughness": roughness,
+            "ndf": "ggx",
+            "eta": [0.2, 0.2, 0.2],
+            "k": [3.9, 3.9, 3.9],
+        });
+        let transmission = material
+            .transmission()
+            .map_or(0.0, |t| t.transmission_factor());
+        let mut mat = if transmission > 0.0 {
+            json!({
+                "type": "dielectric",
+                "albedo": albedo,
+                "roughness": roughness,
+                "ndf": "ggx",
+                "eta": material.ior().unwrap_or(1.5),
+            })
+        } else {
+            let weight = match &mr_texture {
+                Some(texture) if pbr.metallic_factor() > 0.0 => {
+                    let mut json = self.texture_json(texture, Some("b"));
+                    json["factor"] = json!(pbr.metallic_factor());
+                    json
+                }
+                _ => json!(pbr.metallic_factor()),
+            };
+            match weight.as_f64() {
+                Some(w) if w >= 1.0 => metal,
+                Some(w) if w <= 0.0 => json!({ "type": "matte", "albedo": albedo }),
+                _ => json!({
+                    "type": "mix",
+                    "materials": [{ "type": "matte", "albedo": albedo }, metal],
+                    "weight": weight,
+                }),
+            }
+        };
+        if let Some(normal) = material.normal_texture() {
+            mat["normalmap"] = json!({ "file": self.images[normal.texture().source().index()] });
+        }
+        if material.emissive_factor().iter().any(|&e| e > 0.0) {
+            eprintln!(
+                "Warning: emissive material in {} is not supported",
+                self.file_path
+            );
+        }
+        mat
+    }
+
+    // 点光源视为张角为180°的聚光灯；平行光暂不支持
+    fn push_light(&mut self, light: &gltf::khr_lights_punctual::Light, world: M4f) {
+        let position = (world * Vector4::new(0.0, 0.0, 0.0, 1.0)).truncate();
+        let direction = (world * Vector4::new(0.0, 0.0, -1.0, 0.0)).truncate();
+        let energy = V3f::from(light.color()) * light.intensity();
+        let angle = match light.kind() {
+            Kind::Point => 180.0,
+            Kind::Spot {
+                outer_cone_angle, ..
+            } => outer_cone_angle.to_degrees(),
+            Kind::Directional => {
+                eprintln!(
+                    "Warning: directional light in {} is not supported",
+                    self.file_path
+                );
+                return;
+            }
+        };
+        self.result.lights.push(json!({
+            "type": "spotLight",
+            "position": to_array(position),
+            "direction": to_array(direction.normalize()),
+            "energy": to_array(energy),
+            "angle": angle,
+        }));
+    }
+}
+
+fn to_array(v: V3f) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+// 节点的世界矩阵按行输出，保留其中可能存在的切变
+fn transform_json(world: M4f) -> Value {
+    let rows: [[f32; 4]; 4] = world.transpose().into();
+    json!({ "matrix": rows })
+}
+
+// 读取外部文件或data URI(base64)中的数据
+fn read_uri(uri: &str, dir: &Path) -> Vec<u8> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, encoded) = data
+                .split_once(";base64,")
+                .expect("Only base64 data uri is supported!");
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap_or_else(|err| panic!("Error in decoding data uri: {}", err))
+        }
+        None => std::fs::read(dir.join(uri))
+            .unwrap_or_else(|err| panic!("Error in reading {}: {}", uri, err)),
+    }
+}
@@ -0,0 +1,130 @@
+use super::binary::BinaryReader;
+use cgmath::Point3;
+use std::fs;
+
+/// 一根发丝的控制点，widths为空时宽度由场景文件给出
+pub struct HairStrand {
+    pub points: Vec<Point3<f32>>,
+    pub widths: Vec<f32>,
+}
+
+// Cem Yuksel的.hair格式头部中各数组是否存在的标志位
+const HAS_SEGMENTS: u32 = 1;
+const HAS_POINTS: u32 = 1 << 1;
+const HAS_THICKNESS: u32 = 1 << 2;
+
+/// 读取发丝文件：以"HAIR"开头的二进制.hair文件，或文本文件。
+/// 文本文件每行一个控制点"x y z [width]"，空行分隔不同的发丝，#开头的行为注释
+pub fn load_hair(file_path: &str) -> Vec<HairStrand> {
+    let data =
+        fs::read(file_path).unwrap_or_else(|err| panic!("Error in reading {}: {}", file_path, err));
+    let strands = if data.starts_with(b"HAIR") {
+        load_binary(&data, file_path)
+    } else {
+        load_text(&String::from_utf8_lossy(&data), file_path)
+    };
+    if strands.is_empty() {
+        panic!("No strands in {}!", file_path);
+    }
+    strands
+}
+
+fn load_text(text: &str, file_path: &str) -> Vec<HairStrand> {
+    let mut strands = vec![];
+    let mut current = HairStrand {
+        points: vec![],
+        widths: vec![],
+    };
+    let mut finish = |current: &mut HairStrand| {
+        if current.points.len() >= 2 {
+            // 只有部分控制点给出宽度时视为都没有给出
+            if current.widths.len() != current.points.len() {
+                current.widths.clear();
+            }
+            strands.push(std::mem::replace(
+                current,
+                HairStrand {
+                    points: vec![],
+                    widths: vec![],
+                },
+            ));
+        } else {
+            current.points.clear();
+            current.widths.clear();
+        }
+    };
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            finish(&mut current);
+            continue;
+        }
+        let values: Vec<f32> = line
+            .split_whitespace()
+            .map(|s| {
+                s.parse()
+                    .unwrap_or_else(|_| panic!("Error in parsing {}: {}", file_path, line))
+            })
+            .collect();
+        match values.as_slice() {
+            [x, y, z] => current.points.push(Point3::new(*x, *y, *z)),
+            [x, y, z, w] => {
+                current.points.push(Point3::new(*x, *y, *z));
+                current.widths.push(*w);
+            }
+            _ => panic!("Error in parsing {}: {}", file_path, line),
+        }
+    }
+    finish(&mut current);
+    strands
+}
+
+fn load_binary(data: &[u8], file_path: &str) -> Vec<HairStrand> {
+    let mut reader = BinaryReader {
+        data,
+        pos: 4,
+        file_path,
+        big_endian: false,
+    };
+    let hair_count = reader.u32() as usize;
+    let point_count = reader.u32() as usize;
+    let flags = reader.u32();
+    let default_segments = reader.u32() as usize;
+    let default_thickness = reader.f32();
+    if flags & HAS_POINTS == 0 {
+        panic!("No points in {}!", file_path);
+    }
+    // 头部共128字节，之后依次为段数、点、粗细等数组
+    reader.pos = 128;
+    let segments: Vec<usize> = if flags & HAS_SEGMENTS != 0 {
+        (0..hair_count).map(|_| reader.u16() as usize).collect()
+    } else {
+        vec![default_segments; hair_count]
+    };
+    let points: Vec<Point3<f32>> = (0..point_count)
+        .map(|_| Point3::new(reader.f32(), reader.f32(), reader.f32()))
+        .collect();
+    let thickness: Vec<f32> = if flags & HAS_THICKNESS != 0 {
+        (0..point_count).map(|_| reader.f32()).collect()
+    } else {
+        vec![default_thickness; point_count]
+    };
+
+    let mut strands = Vec::with_capacity(hair_count);
+    let mut first = 0;
+    for count in segments.iter().map(|s| s + 1) {
+        if first + count > point_count {
+            panic!("Point index out of range in {}!", file_path);
+        }
+        if count >= 2 {
+            strands.push(HairStrand {
+                points: points[first..first + count].to_vec(),
+                widths: thickness[first..first + count].to_vec(),
+            });
+        }
+        first += count;
+    }
+    strands
+}
@@ -1,5 +1,8 @@
 use image::codecs::hdr::HdrDecoder;
 use image::Rgb32FImage;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 pub fn load_img(file: &str) -> Result<Rgb32FImage, std::io::Error> {
     let img = if !file.ends_with(".hdr") {
@@ -19,3 +22,23 @@ pub fn load_img(file: &str) -> Result<Rgb32FImage, std::io::Error> {
     };
     Ok(img)
 }
+
+thread_local! {
+    // 以文件路径为键缓存已解码的图片，同一张图片在场景中只会被读取一次
+    static IMAGE_CACHE: RefCell<HashMap<String, Rc<Rgb32FImage>>> = RefCell::new(HashMap::new());
+}
+
+/// 将已解码的图片(如模型文件中内嵌的贴图)以key为路径放入缓存
+pub fn insert_img_cached(key: &str, img: Rgb32FImage) {
+    IMAGE_CACHE.with(|cache| cache.borrow_mut().insert(key.to_string(), Rc::new(img)));
+}
+
+/// 读取图片并缓存，多次读取同一路径时返回共享的图片
+pub fn load_img_cached(file: &str) -> Result<Rc<Rgb32FImage>, std::io::Error> {
+    if let Some(img) = IMAGE_CACHE.with(|cache| cache.borrow().get(file).cloned()) {
+        return Ok(img);
+    }
+    let img = Rc::new(load_img(file)?);
+    IMAGE_CACHE.with(|cache| cache.borrow_mut().insert(file.to_string(), img.clone()));
+    Ok(img)
+}
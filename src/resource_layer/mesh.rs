@@ -1,6 +1,9 @@
+use super::ply::load_ply;
 use crate::function_layer::V3f;
-use cgmath::Point3;
-use cgmath::Vector2;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Zero};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
 
 #[derive(Default, Copy, Clone)]
@@ -8,8 +11,23 @@ pub struct DataIndex {
     pub vertex_index: usize,
     pub normal_index: usize,
     pub tex_coord_index: usize,
+    pub tangent_index: usize,
 }
 
+/// 从.mtl文件中读取的材质参数，纹理路径已转换为相对于场景目录的路径
+#[derive(Clone, Default)]
+pub struct MeshMaterial {
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub ior: f32,
+    pub dissolve: f32,
+    pub illumination_model: Option<u8>,
+    pub diffuse_texture: Option<String>,
+    pub normal_texture: Option<String>,
+}
+
+#[derive(Clone)]
 pub struct MeshData {
     pub face_count: usize,
     pub vertex_count: usize,
@@ -17,73 +35,431 @@ pub struct MeshData {
     pub normal_buffer: Vec<V3f>,
     pub tex_coord_buffer: Vec<Vector2<f32>>,
     pub face_buffer: Vec<[DataIndex; 3]>,
+    // 逐顶点的切线，沿纹理坐标u增大的方向，为空时由几何的dp_du得到切线
+    pub tangent_buffer: Vec<V3f>,
+    // 顶点颜色，按vertex_index索引，文件中没有颜色时为空
+    pub color_buffer: Vec<V3f>,
+    // 每个面对应的材质在materials中的下标
+    pub material_buffer: Vec<Option<usize>>,
+    pub materials: Vec<MeshMaterial>,
 }
 
-// static mut MESH_POOL: HashMap<String, Rc<TriangleMesh>> = HashMap::new();
+/// 读取网格时的选项
+#[derive(Default, Clone, Copy)]
+pub struct MeshLoadOptions {
+    // 按角度平滑法线，夹角小于该值(角度制)的相邻面共享法线
+    pub smooth_angle: Option<f32>,
+}
+
+thread_local! {
+    // 以文件路径为键缓存已读取的网格，多个形状引用同一文件时共享顶点数据
+    static MESH_POOL: RefCell<HashMap<String, Rc<MeshData>>> = RefCell::new(HashMap::new());
+}
 
 impl MeshData {
-    pub fn load_from_file(file_path: &str) -> Rc<MeshData> {
-        // if unsafe { MESH_POOL.contains_key(file_path) } {
-        //     return unsafe { MESH_POOL[file_path].clone() };
-        // }
-        let mut config = tobj::LoadOptions::default();
-        config.triangulate = true;
-        let (models, _) = tobj::load_obj(file_path, &config).expect("Error in parsing obj file");
-        if models.len() != 1 {
-            panic!("目前只支持每个.obj文件中包含一个Mesh");
-        }
-        let mesh = &models[0].mesh;
-        let face_count = mesh.indices.len() / 3;
-        let vertex_count = mesh.positions.len() / 3;
-        let mut vertex_buffer = Vec::with_capacity(vertex_count);
-        let mut face_buffer = Vec::with_capacity(face_count);
-        for i in 0..face_count {
-            let mut triangle_info = [DataIndex::default(); 3];
-            for v in 0..3 {
-                let vertex_index = mesh.indices[i * 3 + v] as usize;
-                let normal_index = mesh.normal_indices[i * 3 + v] as usize;
-                let tex_coord_index = mesh.texcoord_indices[i * 3 + v] as usize;
-                triangle_info[v] = DataIndex {
-                    vertex_index,
-                    normal_index,
-                    tex_coord_index,
+    pub fn load_from_file(file_path: &str, options: MeshLoadOptions) -> Rc<MeshData> {
+        let key = match options.smooth_angle {
+            None => file_path.to_string(),
+            Some(angle) => format!("{}#smooth{}", file_path, angle),
+        };
+        if let Some(mesh) = MESH_POOL.with(|pool| pool.borrow().get(&key).cloned()) {
+            return mesh;
+        }
+        let mut mesh = match Path::new(file_path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ply") => load_ply(file_path),
+            _ => Self::load_obj(file_path),
+        };
+        if let Some(angle) = options.smooth_angle {
+            mesh.smooth_normals(angle);
+        }
+        mesh.compute_tangents();
+        let mesh = Rc::new(mesh);
+        MESH_POOL.with(|pool| pool.borrow_mut().insert(key, mesh.clone()));
+        mesh
+    }
+
+    /// 网格池中是否已有以key为路径的网格
+    pub fn is_loaded(key: &str) -> bool {
+        MESH_POOL.with(|pool| pool.borrow().contains_key(key))
+    }
+
+    /// 将不是从单独文件读取的网格(如glTF中的图元)放入网格池，之后可以通过key作为路径引用
+    pub fn insert_loaded(key: &str, mesh: MeshData) {
+        MESH_POOL.with(|pool| pool.borrow_mut().insert(key.to_string(), Rc::new(mesh)));
+    }
+
+    // 读取.obj文件，多个物体合并为一个网格，每个面记录所属的材质
+    fn load_obj(file_path: &str) -> MeshData {
+        let config = tobj::LoadOptions {
+            triangulate: true,
+            ..Default::default()
+        };
+        let (models, materials) =
+            tobj::load_obj(file_path, &config).expect("Error in parsing obj file");
+        let dir = Path::new(file_path).parent().unwrap_or(Path::new(""));
+        let materials = match materials {
+            Ok(materials) => materials
+                .iter()
+                .map(|m| MeshMaterial::from_mtl(m, dir))
+                .collect(),
+            Err(err) => {
+                eprintln!("Warning: failed to load mtl of {}: {}", file_path, err);
+                vec![]
+            }
+        };
+
+        let mut vertex_buffer = vec![];
+        let mut normal_buffer = vec![];
+        let mut tex_coord_buffer = vec![];
+        let mut face_buffer = vec![];
+        let mut material_buffer = vec![];
+        for model in &models {
+            let mesh = &model.mesh;
+            let v_offset = vertex_buffer.len();
+            let n_offset = normal_buffer.len();
+            let t_offset = tex_coord_buffer.len();
+            for p in mesh.positions.chunks_exact(3) {
+                vertex_buffer.push(Point3::new(p[0], p[1], p[2]));
+            }
+            for n in mesh.normals.chunks_exact(3) {
+                normal_buffer.push(V3f::new(n[0], n[1], n[2]));
+            }
+            for uv in mesh.texcoords.chunks_exact(2) {
+                tex_coord_buffer.push(Vector2::new(uv[0], uv[1]));
+            }
+            let has_normals = !mesh.normal_indices.is_empty();
+            let has_tex_coords = !mesh.texcoord_indices.is_empty();
+            // 没有纹理坐标时每个三角形使用(0, 0), (1, 0), (1, 1)
+            let default_uv = tex_coord_buffer.len();
+            if !has_tex_coords {
+                tex_coord_buffer.push(Vector2::new(0.0, 0.0));
+                tex_coord_buffer.push(Vector2::new(1.0, 0.0));
+                tex_coord_buffer.push(Vector2::new(1.0, 1.0));
+            }
+            for (i, face) in mesh.indices.chunks_exact(3).enumerate() {
+                let mut triangle_info = [DataIndex::default(); 3];
+                for v in 0..3 {
+                    triangle_info[v].vertex_index = face[v] as usize + v_offset;
+                    triangle_info[v].tex_coord_index = if has_tex_coords {
+                        mesh.texcoord_indices[i * 3 + v] as usize + t_offset
+                    } else {
+                        default_uv + v
+                    };
                 }
+                if has_normals {
+                    for (v, info) in triangle_info.iter_mut().enumerate() {
+                        info.normal_index = mesh.normal_indices[i * 3 + v] as usize + n_offset;
+                    }
+                } else {
+                    // 没有法线时使用几何法线
+                    let normal_index = normal_buffer.len();
+                    normal_buffer.push(geometric_normal(
+                        triangle_info.map(|d| vertex_buffer[d.vertex_index]),
+                    ));
+                    for info in triangle_info.iter_mut() {
+                        info.normal_index = normal_index;
+                    }
+                }
+                face_buffer.push(triangle_info);
+                material_buffer.push(mesh.material_id);
             }
-            face_buffer.push(triangle_info);
         }
-        let v_buf = &mesh.positions;
-        let n_buf = &mesh.normals;
-        let t_buf = &mesh.texcoords;
+        if face_buffer.is_empty() {
+            panic!("No triangles in {}!", file_path);
+        }
+        Self {
+            face_count: face_buffer.len(),
+            vertex_count: vertex_buffer.len(),
+            vertex_buffer,
+            normal_buffer,
+            tex_coord_buffer,
+            face_buffer,
+            tangent_buffer: vec![],
+            color_buffer: vec![],
+            material_buffer,
+            materials,
+        }
+    }
 
-        for i in 0..vertex_count {
-            let p = &v_buf[i * 3..i * 3 + 3];
-            vertex_buffer.push(Point3::from([p[0], p[1], p[2]]));
+    /// 重新计算法线：共享顶点的面中，与当前面夹角小于angle的面按面积加权平均
+    pub fn smooth_normals(&mut self, angle: f32) {
+        let cos_threshold = angle.to_radians().cos();
+        // 未加权归一化的面法线，其长度为面积的两倍
+        let face_normals: Vec<V3f> = self
+            .face_buffer
+            .iter()
+            .map(|face| {
+                let p = face.map(|d| self.vertex_buffer[d.vertex_index]);
+                (p[1] - p[0]).cross(p[2] - p[0])
+            })
+            .collect();
+        let mut vertex_faces: Vec<Vec<usize>> = vec![vec![]; self.vertex_buffer.len()];
+        for (i, face) in self.face_buffer.iter().enumerate() {
+            for d in face {
+                vertex_faces[d.vertex_index].push(i);
+            }
         }
-        let mut normal_buffer = vec![];
-        if n_buf.len() != 0 {
-            let n_buf_size = n_buf.len() / 3;
-            normal_buffer.reserve(n_buf_size);
-            for i in 0..n_buf_size {
-                let v = &n_buf[i * 3..i * 3 + 3];
-                normal_buffer.push(V3f::new(v[0], v[1], v[2]));
+        let mut normal_buffer = Vec::with_capacity(self.face_count * 3);
+        for (i, face) in self.face_buffer.iter_mut().enumerate() {
+            let n_face = face_normals[i];
+            if n_face.magnitude2() == 0.0 {
+                let normal_index = normal_buffer.len();
+                normal_buffer.push(V3f::new(0.0, 1.0, 0.0));
+                face.iter_mut().for_each(|d| d.normal_index = normal_index);
+                continue;
+            }
+            let n_face_unit = n_face.normalize();
+            for d in face.iter_mut() {
+                let mut normal = V3f::zero();
+                for &j in &vertex_faces[d.vertex_index] {
+                    let n = face_normals[j];
+                    if n.magnitude2() > 0.0 && n.normalize().dot(n_face_unit) >= cos_threshold {
+                        normal += n;
+                    }
+                }
+                d.normal_index = normal_buffer.len();
+                normal_buffer.push(normal.normalize());
             }
         }
-        let mut tex_coord_buffer = vec![];
-        if t_buf.len() != 0 {
-            let t_buf_size = t_buf.len() / 2;
-            tex_coord_buffer.reserve(t_buf_size);
-            for i in 0..t_buf_size {
-                let uv = &t_buf[i * 2..i * 2 + 2];
-                tex_coord_buffer.push(Vector2::new(uv[0], uv[1]));
+        self.normal_buffer = normal_buffer;
+    }
+
+    /// Loop细分levels次，得到新的网格。
+    /// 顶点按vertex_index共享拓扑，边界(以及非流形)边使用边界规则，纹理坐标线性插值，法线需要之后重新计算
+    pub fn loop_subdivide(&self, levels: u32) -> MeshData {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = mesh.loop_subdivide_once();
+        }
+        mesh
+    }
+
+    fn loop_subdivide_once(&self) -> MeshData {
+        let vertex_count = self.vertex_buffer.len();
+        // 每条边的编号以及与之相对的顶点
+        let mut edge_ids: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut edges: Vec<((usize, usize), Vec<usize>)> = vec![];
+        for face in &self.face_buffer {
+            for k in 0..3 {
+                let (a, b) = (face[k].vertex_index, face[(k + 1) % 3].vertex_index);
+                let key = (a.min(b), a.max(b));
+                let id = *edge_ids.entry(key).or_insert_with(|| {
+                    edges.push((key, vec![]));
+                    edges.len() - 1
+                });
+                edges[id].1.push(face[(k + 2) % 3].vertex_index);
+            }
+        }
+        let mut neighbors: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+        let mut boundary_neighbors: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+        for ((a, b), opposite) in &edges {
+            neighbors[*a].push(*b);
+            neighbors[*b].push(*a);
+            if opposite.len() != 2 {
+                boundary_neighbors[*a].push(*b);
+                boundary_neighbors[*b].push(*a);
             }
         }
-        Rc::new(Self {
-            face_count,
-            vertex_count,
+
+        let p = &self.vertex_buffer;
+        // 原有顶点按Loop规则移动
+        let mut vertex_buffer: Vec<Point3<f32>> = (0..vertex_count)
+            .map(|i| {
+                let v = p[i].to_vec();
+                let boundary = &boundary_neighbors[i];
+                if !boundary.is_empty() {
+                    // 只有恰好两条边界边的顶点沿边界平滑，其余(角点、非流形)保持不动
+                    if boundary.len() == 2 {
+                        let sum = p[boundary[0]].to_vec() + p[boundary[1]].to_vec();
+                        return Point3::from_vec(v * 0.75 + sum * 0.125);
+                    }
+                    return p[i];
+                }
+                let n = neighbors[i].len();
+                if n == 0 {
+                    return p[i];
+                }
+                let beta = if n == 3 {
+                    3.0 / 16.0
+                } else {
+                    3.0 / (8.0 * n as f32)
+                };
+                let sum = neighbors[i]
+                    .iter()
+                    .fold(V3f::zero(), |acc, &j| acc + p[j].to_vec());
+                Point3::from_vec(v * (1.0 - n as f32 * beta) + sum * beta)
+            })
+            .collect();
+        // 每条边上新增一个顶点
+        for ((a, b), opposite) in &edges {
+            let sum = p[*a].to_vec() + p[*b].to_vec();
+            vertex_buffer.push(if let [c, d] = opposite.as_slice() {
+                Point3::from_vec(sum * 0.375 + (p[*c].to_vec() + p[*d].to_vec()) * 0.125)
+            } else {
+                Point3::from_vec(sum * 0.5)
+            });
+        }
+        let color_buffer = if self.color_buffer.is_empty() {
+            vec![]
+        } else {
+            let c = &self.color_buffer;
+            let mut colors = c.clone();
+            colors.extend(edges.iter().map(|((a, b), _)| (c[*a] + c[*b]) * 0.5));
+            colors
+        };
+
+        // 纹理坐标按tex_coord_index取边的中点，接缝两侧各自插值
+        let mut tex_coord_buffer = self.tex_coord_buffer.clone();
+        let mut tex_mid: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut tex_midpoint = |a: usize, b: usize| {
+            *tex_mid.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let t = &mut tex_coord_buffer;
+                t.push((t[a] + t[b]) * 0.5);
+                t.len() - 1
+            })
+        };
+
+        let mut face_buffer = Vec::with_capacity(self.face_buffer.len() * 4);
+        let mut material_buffer = Vec::with_capacity(self.face_buffer.len() * 4);
+        for (face, material) in self.face_buffer.iter().zip(&self.material_buffer) {
+            let mid = [0, 1, 2].map(|k| {
+                let (a, b) = (face[k], face[(k + 1) % 3]);
+                let (va, vb) = (a.vertex_index, b.vertex_index);
+                DataIndex {
+                    vertex_index: vertex_count + edge_ids[&(va.min(vb), va.max(vb))],
+                    tex_coord_index: tex_midpoint(a.tex_coord_index, b.tex_coord_index),
+                    ..Default::default()
+                }
+            });
+            let corner = face.map(|d| DataIndex {
+                vertex_index: d.vertex_index,
+                tex_coord_index: d.tex_coord_index,
+                ..Default::default()
+            });
+            face_buffer.push([corner[0], mid[0], mid[2]]);
+            face_buffer.push([corner[1], mid[1], mid[0]]);
+            face_buffer.push([corner[2], mid[2], mid[1]]);
+            face_buffer.push(mid);
+            material_buffer.extend([*material; 4]);
+        }
+
+        let mut mesh = MeshData {
+            face_count: face_buffer.len(),
+            vertex_count: vertex_buffer.len(),
             vertex_buffer,
-            normal_buffer,
+            normal_buffer: vec![],
             tex_coord_buffer,
             face_buffer,
-        })
+            tangent_buffer: vec![],
+            color_buffer,
+            material_buffer,
+            materials: self.materials.clone(),
+        };
+        mesh.smooth_normals(180.0);
+        mesh
+    }
+
+    /// 沿顶点法线方向位移顶点，height给出纹理坐标处的位移量。
+    /// 同一位置的顶点使用面积加权的平均法线和第一次出现时的纹理坐标，保证网格不会开裂
+    pub fn displace(&mut self, height: impl Fn(Point3<f32>, V3f, Vector2<f32>) -> f32) {
+        let mut normals = vec![V3f::zero(); self.vertex_buffer.len()];
+        let mut tex_coords: Vec<Option<Vector2<f32>>> = vec![None; self.vertex_buffer.len()];
+        for face in &self.face_buffer {
+            let p = face.map(|d| self.vertex_buffer[d.vertex_index]);
+            let n = (p[1] - p[0]).cross(p[2] - p[0]);
+            for d in face {
+                normals[d.vertex_index] += n;
+                tex_coords[d.vertex_index].get_or_insert(self.tex_coord_buffer[d.tex_coord_index]);
+            }
+        }
+        for (i, p) in self.vertex_buffer.iter_mut().enumerate() {
+            if let (Some(uv), true) = (tex_coords[i], normals[i].magnitude2() > 0.0) {
+                let n = normals[i].normalize();
+                *p += n * height(*p, n, uv);
+            }
+        }
+    }
+
+    /// 由纹理坐标计算逐顶点的切线(与MikkTSpace相同的思路)：
+    /// 位置、法线和纹理坐标都相同的角点共享切线，各面的切线按角点处的夹角加权，再与顶点法线正交化
+    pub fn compute_tangents(&mut self) {
+        let mut tangent_ids: HashMap<(usize, usize, usize), usize> = HashMap::new();
+        let mut tangents: Vec<V3f> = vec![];
+        for face in self.face_buffer.iter_mut() {
+            let p = face.map(|d| self.vertex_buffer[d.vertex_index]);
+            let uv = face.map(|d| self.tex_coord_buffer[d.tex_coord_index]);
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (duv1, duv2) = (uv[1] - uv[0], uv[2] - uv[0]);
+            let det = duv1.x * duv2.y - duv1.y * duv2.x;
+            let face_tangent = if det.abs() > 1e-12 {
+                (e1 * duv2.y - e2 * duv1.y) / det
+            } else {
+                V3f::zero()
+            };
+            for k in 0..3 {
+                let d = &mut face[k];
+                let key = (d.vertex_index, d.normal_index, d.tex_coord_index);
+                let id = *tangent_ids.entry(key).or_insert_with(|| {
+                    tangents.push(V3f::zero());
+                    tangents.len() - 1
+                });
+                d.tangent_index = id;
+                let a = p[(k + 1) % 3] - p[k];
+                let b = p[(k + 2) % 3] - p[k];
+                let angle = if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 {
+                    a.angle(b).0
+                } else {
+                    0.0
+                };
+                if face_tangent.magnitude2() > 0.0 {
+                    tangents[id] += face_tangent.normalize() * angle;
+                }
+            }
+        }
+        for face in &self.face_buffer {
+            for d in face {
+                let n = self.normal_buffer[d.normal_index];
+                let t = tangents[d.tangent_index];
+                let t = t - n * n.dot(t);
+                tangents[d.tangent_index] = if t.magnitude2() > 1e-12 {
+                    t.normalize()
+                } else {
+                    V3f::zero()
+                };
+            }
+        }
+        self.tangent_buffer = tangents;
+    }
+}
+
+/// 三角形的几何法线，退化三角形返回(0, 1, 0)
+pub(super) fn geometric_normal(p: [Point3<f32>; 3]) -> V3f {
+    let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+    if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        V3f::new(0.0, 1.0, 0.0)
+    }
+}
+
+impl MeshMaterial {
+    fn from_mtl(m: &tobj::Material, dir: &Path) -> Self {
+        let texture_path = |file: &str| {
+            if file.is_empty() {
+                None
+            } else {
+                Some(dir.join(file).to_string_lossy().into_owned())
+            }
+        };
+        Self {
+            diffuse: m.diffuse,
+            specular: m.specular,
+            shininess: m.shininess,
+            ior: m.optical_density,
+            dissolve: m.dissolve,
+            illumination_model: m.illumination_model,
+            diffuse_texture: texture_path(&m.diffuse_texture),
+            normal_texture: texture_path(&m.normal_texture),
+        }
     }
 }
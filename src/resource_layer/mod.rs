@@ -1,4 +1,9 @@
+mod binary;
+pub mod gltf_import;
+pub mod hair;
 pub mod image_io;
 pub mod mesh;
+pub mod ply;
+pub mod volume;
 
 pub use mesh::MeshData;
@@ -0,0 +1,295 @@
+use super::binary::BinaryReader;
+use super::mesh::{geometric_normal, DataIndex, MeshData};
+use crate::function_layer::V3f;
+use cgmath::{Point3, Vector2};
+use std::fs;
+
+#[derive(Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone)]
+enum PlyScalar {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Self {
+        match name {
+            "char" | "int8" => Self::Char,
+            "uchar" | "uint8" => Self::UChar,
+            "short" | "int16" => Self::Short,
+            "ushort" | "uint16" => Self::UShort,
+            "int" | "int32" => Self::Int,
+            "uint" | "uint32" => Self::UInt,
+            "float" | "float32" => Self::Float,
+            "double" | "float64" => Self::Double,
+            tp => panic!("Invalid ply property type: {}!", tp),
+        }
+    }
+}
+
+enum PlyProperty {
+    Scalar(String, PlyScalar),
+    // 列表属性：名称、长度的类型、元素的类型
+    List(String, PlyScalar, PlyScalar),
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// 按格式逐个读取ply文件主体中的数值
+struct PlyReader<'a> {
+    ascii: bool,
+    reader: BinaryReader<'a>,
+}
+
+impl<'a> PlyReader<'a> {
+    fn read(&mut self, tp: PlyScalar) -> f64 {
+        if self.ascii {
+            return self.read_token();
+        }
+        let r = &mut self.reader;
+        match tp {
+            PlyScalar::Char => r.i8() as f64,
+            PlyScalar::UChar => r.u8() as f64,
+            PlyScalar::Short => r.i16() as f64,
+            PlyScalar::UShort => r.u16() as f64,
+            PlyScalar::Int => r.i32() as f64,
+            PlyScalar::UInt => r.u32() as f64,
+            PlyScalar::Float => r.f32() as f64,
+            PlyScalar::Double => r.f64(),
+        }
+    }
+
+    fn read_token(&mut self) -> f64 {
+        let r = &mut self.reader;
+        while r.pos < r.data.len() && r.data[r.pos].is_ascii_whitespace() {
+            r.pos += 1;
+        }
+        let start = r.pos;
+        while r.pos < r.data.len() && !r.data[r.pos].is_ascii_whitespace() {
+            r.pos += 1;
+        }
+        std::str::from_utf8(&r.data[start..r.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| panic!("Error in parsing {}!", r.file_path))
+    }
+}
+
+// 解析文件头，返回格式、元素列表以及主体数据的起始位置
+fn parse_header(data: &[u8]) -> (PlyFormat, Vec<PlyElement>, usize) {
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|w| w == END)
+        .expect("No end_header in ply file!");
+    let mut body = end + END.len();
+    // 跳过end_header所在行的换行符
+    while body < data.len() && data[body] != b'\n' {
+        body += 1;
+    }
+    let header = String::from_utf8_lossy(&data[..end]);
+    let mut lines = header.lines();
+    assert_eq!(lines.next().map(str::trim), Some("ply"), "Not a ply file!");
+
+    let mut format = PlyFormat::Ascii;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", fmt, ..] => {
+                format = match *fmt {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    fmt => panic!("Invalid ply format: {}!", fmt),
+                }
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().expect("Error in ply element count!"),
+                properties: vec![],
+            }),
+            ["property", "list", count_tp, item_tp, name] => elements
+                .last_mut()
+                .expect("Property without element!")
+                .properties
+                .push(PlyProperty::List(
+                    name.to_string(),
+                    PlyScalar::parse(count_tp),
+                    PlyScalar::parse(item_tp),
+                )),
+            ["property", tp, name] => elements
+                .last_mut()
+                .expect("Property without element!")
+                .properties
+                .push(PlyProperty::Scalar(name.to_string(), PlyScalar::parse(tp))),
+            // comment、obj_info等
+            _ => (),
+        }
+    }
+    (format, elements, body + 1)
+}
+
+/// 读取.ply文件(ascii或二进制)，支持顶点位置、法线、纹理坐标和颜色，多边形面按扇形三角化
+pub fn load_ply(file_path: &str) -> MeshData {
+    let data =
+        fs::read(file_path).unwrap_or_else(|err| panic!("Error in reading {}: {}", file_path, err));
+    let (format, elements, body) = parse_header(&data);
+    let mut reader = PlyReader {
+        ascii: format == PlyFormat::Ascii,
+        reader: BinaryReader {
+            data: &data,
+            pos: body,
+            file_path,
+            big_endian: format == PlyFormat::BinaryBigEndian,
+        },
+    };
+
+    let mut vertex_buffer = vec![];
+    let mut normal_buffer = vec![];
+    let mut tex_coord_buffer = vec![];
+    let mut color_buffer = vec![];
+    let mut polygons: Vec<Vec<f64>> = vec![];
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        for _ in 0..element.count {
+            let mut p = [0.0; 3];
+            let mut n = [0.0; 3];
+            let mut uv = [0.0; 2];
+            let mut rgb = [0.0; 3];
+            let (mut has_n, mut has_uv, mut has_rgb) = (false, false, false);
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar(name, tp) => {
+                        let value = reader.read(*tp) as f32;
+                        if !is_vertex {
+                            continue;
+                        }
+                        // 整数颜色归一化到[0, 1]
+                        let color = match tp {
+                            PlyScalar::UChar => value / 255.0,
+                            PlyScalar::UShort => value / 65535.0,
+                            _ => value,
+                        };
+                        match name.as_str() {
+                            "x" => p[0] = value,
+                            "y" => p[1] = value,
+                            "z" => p[2] = value,
+                            "nx" => (n[0], has_n) = (value, true),
+                            "ny" => n[1] = value,
+                            "nz" => n[2] = value,
+                            "u" | "s" | "texture_u" | "texture_s" => {
+                                (uv[0], has_uv) = (value, true)
+                            }
+                            "v" | "t" | "texture_v" | "texture_t" => uv[1] = value,
+                            "red" | "r" => (rgb[0], has_rgb) = (color, true),
+                            "green" | "g" => rgb[1] = color,
+                            "blue" | "b" => rgb[2] = color,
+                            _ => (),
+                        }
+                    }
+                    PlyProperty::List(name, count_tp, item_tp) => {
+                        let count = reader.read(*count_tp) as usize;
+                        let items: Vec<f64> = (0..count).map(|_| reader.read(*item_tp)).collect();
+                        if is_face && (name == "vertex_indices" || name == "vertex_index") {
+                            polygons.push(items);
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                vertex_buffer.push(Point3::from(p));
+                if has_n {
+                    normal_buffer.push(V3f::from(n));
+                }
+                if has_uv {
+                    tex_coord_buffer.push(Vector2::from(uv));
+                }
+                if has_rgb {
+                    color_buffer.push(V3f::from(rgb));
+                }
+            }
+        }
+    }
+
+    // 只有部分顶点带有的属性视为不存在
+    let has_normals = normal_buffer.len() == vertex_buffer.len();
+    let has_tex_coords = tex_coord_buffer.len() == vertex_buffer.len();
+    if color_buffer.len() != vertex_buffer.len() {
+        color_buffer.clear();
+    }
+    // 与.obj相同，没有纹理坐标时每个三角形使用(0, 0), (1, 0), (1, 1)
+    if !has_tex_coords {
+        tex_coord_buffer.clear();
+        tex_coord_buffer.push(Vector2::new(0.0, 0.0));
+        tex_coord_buffer.push(Vector2::new(1.0, 0.0));
+        tex_coord_buffer.push(Vector2::new(1.0, 1.0));
+    }
+    if !has_normals {
+        normal_buffer.clear();
+    }
+    let mut face_buffer = vec![];
+    for (i, polygon) in polygons.iter().enumerate() {
+        // 与.obj相同，格式错误的面直接报错
+        if polygon.len() < 3 {
+            panic!("Face {} has less than 3 vertices in {}!", i, file_path);
+        }
+        if polygon
+            .iter()
+            .any(|&v| v < 0.0 || v as usize >= vertex_buffer.len())
+        {
+            panic!("Vertex index out of range in face {} of {}!", i, file_path);
+        }
+        let polygon: Vec<usize> = polygon.iter().map(|&v| v as usize).collect();
+        for k in 1..polygon.len() - 1 {
+            let corners = [polygon[0], polygon[k], polygon[k + 1]];
+            let mut triangle_info = [DataIndex::default(); 3];
+            for (v, info) in triangle_info.iter_mut().enumerate() {
+                info.vertex_index = corners[v];
+                info.tex_coord_index = if has_tex_coords { corners[v] } else { v };
+                info.normal_index = corners[v];
+            }
+            if !has_normals {
+                // 没有法线时使用几何法线
+                let normal_index = normal_buffer.len();
+                normal_buffer.push(geometric_normal(corners.map(|i| vertex_buffer[i])));
+                triangle_info
+                    .iter_mut()
+                    .for_each(|info| info.normal_index = normal_index);
+            }
+            face_buffer.push(triangle_info);
+        }
+    }
+    if face_buffer.is_empty() {
+        panic!("No triangles in {}!", file_path);
+    }
+    MeshData {
+        face_count: face_buffer.len(),
+        vertex_count: vertex_buffer.len(),
+        vertex_buffer,
+        normal_buffer,
+        tex_coord_buffer,
+        tangent_buffer: vec![],
+        color_buffer,
+        material_buffer: vec![None; face_buffer.len()],
+        face_buffer,
+        materials: vec![],
+    }
+}
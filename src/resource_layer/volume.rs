@@ -0,0 +1,158 @@
+use super::binary::BinaryReader;
+use cgmath::Point3;
+use std::collections::HashMap;
+use std::fs;
+
+/// 稠密的标量体素网格，下标为(z * ny + y) * nx + x，体素中心位于包围盒内均匀划分的格子中心
+pub struct VolumeGrid {
+    pub res: [usize; 3],
+    pub data: Vec<f32>,
+    pub p_min: Point3<f32>,
+    pub p_max: Point3<f32>,
+}
+
+// 稀疏格式中一个叶节点覆盖的边长
+const LEAF_DIM: usize = 8;
+
+/// 读取体数据文件，返回按名字索引的网格(density、temperature、emission等)。支持三种格式：
+/// - Mitsuba的.vol：以"VOL"和版本号3开头，只包含一个网格，多通道时取各通道平均作为density
+/// - 稀疏格式：以"SVOL"开头，每个网格由8^3的叶节点和常量tile组成，读取后展开为稠密网格
+/// - 其他文件视为小端序f32的原始数据，需要给出分辨率，作为density
+pub fn load_volume(file_path: &str, resolution: Option<[usize; 3]>) -> HashMap<String, VolumeGrid> {
+    let data =
+        fs::read(file_path).unwrap_or_else(|err| panic!("Error in reading {}: {}", file_path, err));
+    let mut reader = BinaryReader {
+        data: &data,
+        pos: 0,
+        file_path,
+        big_endian: false,
+    };
+    if data.starts_with(b"VOL") {
+        reader.pos = 3;
+        HashMap::from([("density".to_string(), load_mitsuba(&mut reader))])
+    } else if data.starts_with(b"SVOL") {
+        reader.pos = 4;
+        load_sparse(&mut reader)
+    } else {
+        let res = resolution
+            .unwrap_or_else(|| panic!("No resolution given for raw volume {}!", file_path));
+        let count = res[0] * res[1] * res[2];
+        if data.len() != count * 4 {
+            panic!(
+                "Raw volume {} has {} bytes; expected {}!",
+                file_path,
+                data.len(),
+                count * 4
+            );
+        }
+        let grid = VolumeGrid {
+            res,
+            data: (0..count).map(|_| reader.f32()).collect(),
+            p_min: Point3::new(0.0, 0.0, 0.0),
+            p_max: Point3::new(1.0, 1.0, 1.0),
+        };
+        HashMap::from([("density".to_string(), grid)])
+    }
+}
+
+fn read_point(reader: &mut BinaryReader) -> Point3<f32> {
+    Point3::new(reader.f32(), reader.f32(), reader.f32())
+}
+
+fn load_mitsuba(reader: &mut BinaryReader) -> VolumeGrid {
+    let version = reader.u8();
+    if version != 3 {
+        panic!("Unsupported version {} of {}!", version, reader.file_path);
+    }
+    // 1为f32，3为u8(映射到[0, 1])
+    let encoding = reader.i32();
+    let res = [reader.i32(), reader.i32(), reader.i32()].map(|r| r.max(0) as usize);
+    let channels = reader.i32().max(1) as usize;
+    let (p_min, p_max) = (read_point(reader), read_point(reader));
+    let count = res[0] * res[1] * res[2];
+    let mut next = || match encoding {
+        1 => reader.f32(),
+        3 => reader.u8() as f32 / 255.0,
+        _ => panic!("Unsupported encoding {} of {}!", encoding, reader.file_path),
+    };
+    let data = (0..count)
+        .map(|_| (0..channels).map(|_| next()).sum::<f32>() / channels as f32)
+        .collect();
+    VolumeGrid {
+        res,
+        data,
+        p_min,
+        p_max,
+    }
+}
+
+// 每个网格：名字、体素下标范围[min, max)、世界空间包围盒、背景值和节点列表。
+// 节点以下标空间中的原点开头，类型0为常量tile，后跟一个值；
+// 类型1为叶节点，后跟64字节的激活掩码和按顺序排列的激活体素的值，未激活的体素为背景值
+fn load_sparse(reader: &mut BinaryReader) -> HashMap<String, VolumeGrid> {
+    let version = reader.u32();
+    if version != 1 {
+        panic!("Unsupported version {} of {}!", version, reader.file_path);
+    }
+    let grid_count = reader.u32();
+    let mut grids = HashMap::new();
+    for _ in 0..grid_count {
+        let name_len = reader.u32() as usize;
+        let name: Vec<u8> = (0..name_len).map(|_| reader.u8()).collect();
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let index_min = [reader.i32(), reader.i32(), reader.i32()];
+        let index_max = [reader.i32(), reader.i32(), reader.i32()];
+        let res = [0, 1, 2].map(|i| (index_max[i] - index_min[i]).max(0) as usize);
+        let (p_min, p_max) = (read_point(reader), read_point(reader));
+        let background = reader.f32();
+        let mut data = vec![background; res[0] * res[1] * res[2]];
+        let mut set = |x: i32, y: i32, z: i32, value: f32| {
+            let (x, y, z) = (x - index_min[0], y - index_min[1], z - index_min[2]);
+            if x >= 0
+                && y >= 0
+                && z >= 0
+                && (x as usize) < res[0]
+                && (y as usize) < res[1]
+                && (z as usize) < res[2]
+            {
+                data[(z as usize * res[1] + y as usize) * res[0] + x as usize] = value;
+            }
+        };
+        let node_count = reader.u32();
+        for _ in 0..node_count {
+            let origin = [reader.i32(), reader.i32(), reader.i32()];
+            let kind = reader.u8();
+            let values: Vec<Option<f32>> = match kind {
+                0 => vec![Some(reader.f32()); LEAF_DIM.pow(3)],
+                1 => {
+                    let mask: Vec<u8> = (0..LEAF_DIM.pow(3) / 8).map(|_| reader.u8()).collect();
+                    (0..LEAF_DIM.pow(3))
+                        .map(|i| ((mask[i / 8] >> (i % 8)) & 1 == 1).then(|| reader.f32()))
+                        .collect()
+                }
+                _ => panic!("Invalid node type {} in {}!", kind, reader.file_path),
+            };
+            for (i, value) in values.into_iter().enumerate() {
+                if let Some(value) = value {
+                    let (x, y, z) = (i % LEAF_DIM, i / LEAF_DIM % LEAF_DIM, i / LEAF_DIM.pow(2));
+                    set(
+                        origin[0] + x as i32,
+                        origin[1] + y as i32,
+                        origin[2] + z as i32,
+                        value,
+                    );
+                }
+            }
+        }
+        grids.insert(
+            name,
+            VolumeGrid {
+                res,
+                data,
+                p_min,
+                p_max,
+            },
+        );
+    }
+    grids
+}